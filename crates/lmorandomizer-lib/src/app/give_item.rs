@@ -0,0 +1,150 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use lmorandomizer_shared::lmo::GameItem;
+use tracing::debug;
+
+use crate::tick_driver::GAME_STATE_FOREGROUND;
+
+/// ゲームのアイテム取得関数。テストでは偽物に差し替える
+pub trait AcquireItem {
+    /// 0: 操作中, -2: バックグラウンド, -3: ポーズ中
+    fn game_state(&self) -> u32;
+    fn aquire_main_weapon(&self, main_weapon_id: u32);
+    fn aquire_sub_weapon(&self, sub_weapon_id: u32, amount: u32);
+    fn acquire_item(&self, item_id: u32);
+    fn acquire_rom(&self, rom_id: u32);
+    fn set_flag(&self, flag: u32);
+}
+
+/// フラグはアイテムのオブジェクトが立てるもので、取得関数は立てないので別に立てる
+pub fn give_item(target: &impl AcquireItem, item: GameItem, flag: u16) {
+    match item {
+        GameItem::MainWeapon(main_weapon) => target.aquire_main_weapon(main_weapon as u32),
+        GameItem::SubWeapon { sub_weapon, amount } => {
+            target.aquire_sub_weapon(sub_weapon as u32, amount as u32)
+        }
+        GameItem::Equipment(equipment) => target.acquire_item(equipment as u32),
+        GameItem::Rom(rom) => target.acquire_rom(rom as u32),
+    }
+    target.set_flag(flag as u32);
+}
+
+/// IPC のスレッドで積み、ゲームスレッドで取り出して与える
+#[derive(Debug, Default)]
+pub struct GiveItemQueue {
+    items: Mutex<VecDeque<(GameItem, u16)>>,
+}
+
+impl GiveItemQueue {
+    pub fn push(&self, item: GameItem, flag: u16) {
+        self.items.lock().unwrap().push_back((item, flag));
+    }
+
+    /// 取得の演出が重ならないよう、1回に1つだけ与える。与えたら `true`。
+    /// 操作中でなければ、操作に戻るまで積んだままにする
+    pub fn dispatch(&self, target: &impl AcquireItem) -> bool {
+        if target.game_state() != GAME_STATE_FOREGROUND {
+            return false;
+        }
+        // 取得関数の中でロックを持たないよう、先に取り出す
+        let Some((item, flag)) = self.items.lock().unwrap().pop_front() else {
+            return false;
+        };
+        debug!("Giving {item} (flag {flag})");
+        give_item(target, item, flag);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use lmorandomizer_shared::lmo::{Equipment, MainWeapon, Rom, SubWeapon};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeHandle {
+        game_state: Cell<u32>,
+        calls: RefCell<Vec<(&'static str, u32, u32)>>,
+    }
+
+    impl AcquireItem for FakeHandle {
+        fn game_state(&self) -> u32 {
+            self.game_state.get()
+        }
+
+        fn aquire_main_weapon(&self, main_weapon_id: u32) {
+            self.calls.borrow_mut().push(("main", main_weapon_id, 0));
+        }
+
+        fn aquire_sub_weapon(&self, sub_weapon_id: u32, amount: u32) {
+            self.calls.borrow_mut().push(("sub", sub_weapon_id, amount));
+        }
+
+        fn acquire_item(&self, item_id: u32) {
+            self.calls.borrow_mut().push(("item", item_id, 0));
+        }
+
+        fn acquire_rom(&self, rom_id: u32) {
+            self.calls.borrow_mut().push(("rom", rom_id, 0));
+        }
+
+        fn set_flag(&self, flag: u32) {
+            self.calls.borrow_mut().push(("flag", flag, 0));
+        }
+    }
+
+    #[test]
+    fn test_dispatch_one_item_per_call() {
+        let queue = GiveItemQueue::default();
+        queue.push(GameItem::MainWeapon(MainWeapon::Axe), 100);
+        queue.push(
+            GameItem::SubWeapon {
+                sub_weapon: SubWeapon::Shuriken,
+                amount: 10,
+            },
+            101,
+        );
+        queue.push(GameItem::Equipment(Equipment::Feather), 102);
+        queue.push(GameItem::Rom(Rom::GameMaster), 103);
+
+        let handle = FakeHandle::default();
+        assert!(queue.dispatch(&handle));
+        assert_eq!(handle.calls.borrow().len(), 2);
+        while queue.dispatch(&handle) {}
+        assert_eq!(
+            *handle.calls.borrow(),
+            [
+                ("main", MainWeapon::Axe as u32, 0),
+                ("flag", 100, 0),
+                ("sub", SubWeapon::Shuriken as u32, 10),
+                ("flag", 101, 0),
+                ("item", Equipment::Feather as u32, 0),
+                ("flag", 102, 0),
+                ("rom", Rom::GameMaster as u32, 0),
+                ("flag", 103, 0),
+            ]
+        );
+        assert!(!queue.dispatch(&handle));
+    }
+
+    #[test]
+    fn test_dispatch_waits_for_foreground() {
+        let queue = GiveItemQueue::default();
+        queue.push(GameItem::Equipment(Equipment::Feather), 102);
+
+        let handle = FakeHandle::default();
+        handle.game_state.set(-3i32 as u32);
+        assert!(!queue.dispatch(&handle));
+        assert!(handle.calls.borrow().is_empty());
+
+        handle.game_state.set(GAME_STATE_FOREGROUND);
+        assert!(queue.dispatch(&handle));
+        assert_eq!(
+            *handle.calls.borrow(),
+            [("item", Equipment::Feather as u32, 0), ("flag", 102, 0)]
+        );
+    }
+}
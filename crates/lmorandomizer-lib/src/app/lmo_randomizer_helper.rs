@@ -3,7 +3,7 @@ use std::{
     iter,
     os::windows::ffi::OsStrExt,
     path::PathBuf,
-    ptr::NonNull,
+    ptr::{NonNull, read_volatile},
     sync::{Arc, Mutex, OnceLock},
 };
 
@@ -11,10 +11,12 @@ use anyhow::{Result, anyhow};
 use lmorandomizer_shared::{
     Command,
     ipc::IpcStream,
-    lmo::{MEMO_FLAG_BASE_NO, Object, SubWeapon},
+    lmo::{Flags, MEMO_FLAG_BASE_NO, Object, SubWeapon},
+    protocol::{Event, HookSession},
+    tracker::{GameSnapshot, SnapshotDelta},
 };
 use smol::unblock;
-use tracing::trace;
+use tracing::{debug, trace};
 use windows::{
     Win32::{
         Foundation::{HANDLE, INVALID_HANDLE_VALUE},
@@ -26,12 +28,20 @@ use windows::{
     core::{PCSTR, PCWSTR},
 };
 
-use crate::hook::{CreateFileAFn, CurrentWeaponFn, LmoDelegate, LmoHandle};
+use crate::{
+    hook::{CreateFileAFn, CurrentWeaponFn, LmoDelegate, LmoHandle},
+    tick_driver::TickDelegate,
+};
+
+use super::give_item::{AcquireItem, GiveItemQueue};
 
 #[derive(Debug)]
 pub struct LmoRandomizerHelper {
     handle: LmoHandle,
     custom_path: OnceLock<PathBuf>,
+    /// 接続ごとにリセットし、`PollTracker` の差分の基準にする
+    last_snapshot: Mutex<Option<GameSnapshot>>,
+    give_item_queue: GiveItemQueue,
 }
 
 impl LmoRandomizerHelper {
@@ -39,33 +49,88 @@ impl LmoRandomizerHelper {
         Self {
             handle,
             custom_path: OnceLock::new(),
+            last_snapshot: Mutex::new(None),
+            give_item_queue: GiveItemQueue::default(),
         }
     }
 
     pub async fn ipc_main(&self, stream: IpcStream) -> Result<()> {
-        let stream = Arc::new(Mutex::new(stream));
+        let version = env!("CARGO_PKG_VERSION");
+        let session = unblock(move || HookSession::accept(stream, version)).await?;
+        debug!("IPC connected: {:?}", session.peer());
+        let session = Arc::new(Mutex::new(session));
+        *self.last_snapshot.lock().unwrap() = None;
 
         loop {
-            let stream = Arc::clone(&stream);
-            let Ok(cmd) =
-                unblock(move || rmp_serde::from_read::<_, Command>(&mut *stream.lock().unwrap()))
-                    .await
-            else {
-                break;
+            let reader = Arc::clone(&session);
+            let (id, cmd) = match unblock(move || reader.lock().unwrap().recv_request()).await {
+                Ok(ok) => ok,
+                Err(err) => {
+                    debug!("IPC closed: {err}");
+                    break;
+                }
             };
 
             trace!("ipc_main while {cmd:?}");
-            match cmd {
-                Command::Init(path_buf) => {
-                    self.custom_path
-                        .set(path_buf)
-                        .map_err(|_| anyhow!("Failed to set custom path"))?;
+            let result = self.handle_command(cmd);
+            let mut session = session.lock().unwrap();
+            match result {
+                Ok(event) => {
+                    if let Some(event) = event {
+                        session.push_event(event)?;
+                    }
+                    session.ack(id)?;
                 }
+                Err(err) => session.reply_error(Some(id), err.to_string())?,
             }
         }
         Ok(())
     }
 
+    /// 応答の前に送るイベントがあれば返す
+    fn handle_command(&self, cmd: Command) -> Result<Option<Event>> {
+        match cmd {
+            Command::Init(path_buf) => self
+                .custom_path
+                .set(path_buf)
+                .map(|_| None)
+                .map_err(|_| anyhow!("Failed to set custom path")),
+            Command::PollTracker => Ok(Some(self.poll_tracker())),
+            Command::GiveItem { item, flag } => {
+                self.give_item_queue.push(item, flag);
+                Ok(None)
+            }
+        }
+    }
+
+    fn poll_tracker(&self) -> Event {
+        let snapshot = self.read_snapshot();
+        let mut last_snapshot = self.last_snapshot.lock().unwrap();
+        let event = match last_snapshot.as_ref() {
+            None => Event::Snapshot(Box::new(snapshot.clone())),
+            Some(last) => Event::SnapshotDelta(SnapshotDelta::new(last, &snapshot)),
+        };
+        *last_snapshot = Some(snapshot);
+        event
+    }
+
+    /// ゲームスレッドと並行して読むので、値は多少ずれうる
+    fn read_snapshot(&self) -> GameSnapshot {
+        let handle = &self.handle;
+        unsafe {
+            let flags = read_volatile(handle.flags.as_ptr());
+            GameSnapshot {
+                flags: read_volatile(flags as *const Flags),
+                items: read_volatile(handle.items.as_ptr()),
+                main_weapon: read_volatile(handle.main_weapon.as_ptr()),
+                sub_weapon: read_volatile(handle.sub_weapon.as_ptr()),
+                coin: read_volatile(handle.coin.as_ptr()),
+                field: read_volatile(handle.field.as_ptr()),
+                room: read_volatile(handle.room_number.as_ptr()),
+            }
+        }
+    }
+
     /// ランダマイザー用のハンディースキャナーのフラグセットロジックをショートカットする
     fn skip_randomizers_memo(&self, obj: &Object) -> bool {
         if obj.op2 < MEMO_FLAG_BASE_NO as i32 {
@@ -156,9 +221,45 @@ impl LmoDelegate for LmoRandomizerHelper {
     }
 }
 
+impl TickDelegate for LmoRandomizerHelper {
+    fn on_game_tick(&self) {
+        self.give_item_queue.dispatch(&self.handle);
+    }
+
+    fn on_screen_change(&self, field: u32, room: u32) {
+        debug!("Screen changed: field={field}, room={room}");
+    }
+}
+
 unsafe impl Send for LmoRandomizerHelper {}
 unsafe impl Sync for LmoRandomizerHelper {}
 
+impl AcquireItem for LmoHandle {
+    fn game_state(&self) -> u32 {
+        unsafe { read_volatile(self.game_state.as_ptr()) }
+    }
+
+    fn aquire_main_weapon(&self, main_weapon_id: u32) {
+        LmoHandle::aquire_main_weapon(self, main_weapon_id);
+    }
+
+    fn aquire_sub_weapon(&self, sub_weapon_id: u32, amount: u32) {
+        LmoHandle::aquire_sub_weapon(self, sub_weapon_id, amount);
+    }
+
+    fn acquire_item(&self, item_id: u32) {
+        LmoHandle::acquire_item(self, item_id);
+    }
+
+    fn acquire_rom(&self, rom_id: u32) {
+        LmoHandle::acquire_rom(self, rom_id);
+    }
+
+    fn set_flag(&self, flag: u32) {
+        LmoHandle::set_flag(self, flag, true);
+    }
+}
+
 fn to_wide_null(s: &OsStr) -> Vec<u16> {
     s.encode_wide().chain(iter::once(0)).collect()
 }
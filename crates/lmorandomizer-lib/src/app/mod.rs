@@ -1,3 +1,6 @@
+mod give_item;
+#[cfg(windows)]
 mod lmo_randomizer_helper;
 
+#[cfg(windows)]
 pub use lmo_randomizer_helper::LmoRandomizerHelper;
@@ -15,3 +15,6 @@ use windows::{
 pub struct LmoHandle;
 
 pub type CurrentWeaponFn = extern "cdecl" fn(main_weapon: bool) -> u8;
+/// `game_main` が自身の引数をそのまま渡して `func(param)` と呼ぶ関数。
+/// 呼び出し元は戻り値を使わないが、`eax` を変えないよう元の戻り値をそのまま返す
+pub type GameTickFn = extern "cdecl" fn(param: *const ()) -> *const ();
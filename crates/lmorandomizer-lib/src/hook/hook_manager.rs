@@ -1,18 +1,25 @@
 use std::{fmt::Debug, sync::Arc};
 
-use super::{LmoDelegate, OriginalAddrs};
+use super::{LmoDelegate, OriginalAddrs, ScreenPosition};
+use crate::tick_driver::TickDriver;
 
 #[derive(Debug)]
 pub struct LmoHookManager {
     pub delegate: Arc<dyn LmoDelegate>,
     pub original_addrs: OriginalAddrs,
+    pub tick_driver: TickDriver<ScreenPosition>,
 }
 
 impl LmoHookManager {
-    pub fn new(delegate: Arc<dyn LmoDelegate>, original_addrs: OriginalAddrs) -> Self {
+    pub fn new(
+        delegate: Arc<dyn LmoDelegate>,
+        original_addrs: OriginalAddrs,
+        tick_driver: TickDriver<ScreenPosition>,
+    ) -> Self {
         Self {
             delegate,
             original_addrs,
+            tick_driver,
         }
     }
 }
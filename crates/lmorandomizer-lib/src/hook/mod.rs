@@ -4,11 +4,14 @@ mod hook_manager;
 use std::{
     fmt::Debug,
     mem::transmute,
-    ptr::NonNull,
+    ptr::{NonNull, read_volatile},
     sync::{Arc, OnceLock},
 };
 
-use crate::hook_utils::{hook_addr, hook_near_target};
+use crate::{
+    hook_utils::{hook_addr, hook_near_target},
+    tick_driver::{ReadScreen, TickDelegate, TickDriver},
+};
 use hook_manager::LmoHookManager;
 use windows::{
     Win32::{
@@ -21,11 +24,12 @@ use windows::{
     core::PCSTR,
 };
 
+use handle::GameTickFn;
 pub use handle::{CreateFileAFn, CurrentWeaponFn, LmoHandle};
 
 static LMO_HOOK_MANAGER: OnceLock<LmoHookManager> = OnceLock::new();
 
-pub trait LmoDelegate: Debug {
+pub trait LmoDelegate: TickDelegate + Debug {
     #[allow(clippy::too_many_arguments)]
     fn create_file_a(
         &self,
@@ -52,10 +56,43 @@ pub trait LmoDelegate: Debug {
     ) -> u8;
 }
 
+#[derive(Debug)]
+pub struct ScreenPosition {
+    game_state: NonNull<u32>,
+    field: NonNull<u32>,
+    room_number: NonNull<u32>,
+}
+
+impl ScreenPosition {
+    fn new(handle: &LmoHandle) -> Self {
+        Self {
+            game_state: handle.game_state,
+            field: handle.field,
+            room_number: handle.room_number,
+        }
+    }
+}
+
+impl ReadScreen for ScreenPosition {
+    fn game_state(&self) -> u32 {
+        unsafe { read_volatile(self.game_state.as_ptr()) }
+    }
+
+    fn read_position(&self) -> (u32, u32) {
+        unsafe {
+            (
+                read_volatile(self.field.as_ptr()),
+                read_volatile(self.room_number.as_ptr()),
+            )
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct OriginalAddrs {
     create_file_a: CreateFileAFn,
     current_weapon: CurrentWeaponFn,
+    game_tick: GameTickFn,
 }
 
 pub fn install_hook(
@@ -72,9 +109,15 @@ pub fn install_hook(
             let original = hook_near_target(addr, current_weapon_hook as _)?;
             unsafe { transmute::<NonNull<_>, CurrentWeaponFn>(original) }
         },
+        game_tick: {
+            let addr = handle.game_main.call_on_game_tick;
+            let original = hook_near_target(addr, game_tick_hook as _)?;
+            unsafe { transmute::<NonNull<_>, GameTickFn>(original) }
+        },
     };
 
-    let mng = LmoHookManager::new(delegate, addrs);
+    let tick_driver = TickDriver::new(ScreenPosition::new(handle));
+    let mng = LmoHookManager::new(delegate, addrs, tick_driver);
     LMO_HOOK_MANAGER.set(mng).unwrap();
     Ok(())
 }
@@ -120,3 +163,10 @@ extern "cdecl" fn current_weapon_hook(
         mng.original_addrs.current_weapon,
     )
 }
+
+extern "cdecl" fn game_tick_hook(param: *const ()) -> *const () {
+    let mng = LMO_HOOK_MANAGER.get().unwrap();
+    let result = (mng.original_addrs.game_tick)(param);
+    mng.tick_driver.tick(mng.delegate.as_ref());
+    result
+}
@@ -1,52 +1,12 @@
-#![cfg(target_os = "windows")]
+// ゲームに依らない部分は Windows 以外でもビルドしてテストする
+#![cfg_attr(not(windows), allow(dead_code))]
 mod app;
+#[cfg(windows)]
+mod dll;
+#[cfg(windows)]
 mod hook;
+#[cfg(windows)]
 mod hook_utils;
+mod tick_driver;
+#[cfg(windows)]
 mod tracing_helper;
-
-use std::{panic, process, sync::Arc, thread};
-
-use lmorandomizer_shared::ipc::IpcListener;
-use smol::block_on;
-use tracing::{debug, error};
-use windows::Win32::{
-    Foundation::HINSTANCE,
-    System::{LibraryLoader::GetModuleHandleW, SystemServices::DLL_PROCESS_ATTACH},
-};
-
-use crate::{
-    app::LmoRandomizerHelper,
-    hook::{LmoHandle, install_hook},
-    tracing_helper::init_tracing,
-};
-
-#[unsafe(no_mangle)]
-pub extern "stdcall" fn DllMain(_inst_dll: HINSTANCE, reason: u32, _reserved: u32) -> bool {
-    if reason == DLL_PROCESS_ATTACH {
-        thread::spawn(|| block_on(sub_main()));
-    }
-
-    true
-}
-
-async fn sub_main() {
-    init_tracing();
-    panic::set_hook(Box::new(|info| {
-        error!("panic occurred: {}", info);
-    }));
-    debug!("sub_main start");
-
-    let base_addr = unsafe { GetModuleHandleW(None) }.unwrap().0 as usize;
-    let handle = LmoHandle::new(base_addr);
-    let handle_ptr = &handle as *const LmoHandle;
-    let helper = Arc::new(LmoRandomizerHelper::new(handle));
-    let delegate = helper.clone();
-    install_hook(unsafe { handle_ptr.as_ref() }.unwrap(), delegate).unwrap();
-
-    let pipe_name = format!("lmorandomizer_for_{}", process::id());
-    let listener = IpcListener::new(&pipe_name);
-    let stream = listener.accept().await.unwrap();
-    helper.ipc_main(stream).await.unwrap();
-
-    debug!("sub_main end");
-}
@@ -0,0 +1,131 @@
+use std::sync::Mutex;
+
+/// `game_state` の値のうち、プレイヤーが操作している状態
+pub const GAME_STATE_FOREGROUND: u32 = 0;
+
+/// 現在の `game_state` と (field, room)。テストでは偽物に差し替える
+pub trait ReadScreen {
+    fn game_state(&self) -> u32;
+    fn read_position(&self) -> (u32, u32);
+}
+
+/// ゲームスレッドから呼ばれるコールバック
+pub trait TickDelegate {
+    /// ゲームスレッドで毎フレーム呼ばれる
+    fn on_game_tick(&self) {}
+
+    /// 操作中に画面が切り替わったフレームで、`on_game_tick` より先に呼ばれる。
+    /// 最初に操作中になったフレームでも、その時の画面で呼ばれる。
+    /// タイトル画面でも呼ばれうるので、最初の呼び出しは画面の移動とみなさないこと
+    fn on_screen_change(&self, _field: u32, _room: u32) {}
+}
+
+/// ゲームスレッドの毎フレームの処理から、デリゲートのコールバックを呼び分ける。
+/// 画面の切り替わりは操作中の (field, room) の変化で判定し、ポーズ中などは前の画面を保つ
+#[derive(Debug)]
+pub struct TickDriver<S> {
+    screen: S,
+    last_position: Mutex<Option<(u32, u32)>>,
+}
+
+impl<S: ReadScreen> TickDriver<S> {
+    pub fn new(screen: S) -> Self {
+        Self {
+            screen,
+            last_position: Mutex::new(None),
+        }
+    }
+
+    /// 画面が切り替わっていれば `on_screen_change` を先に呼ぶ
+    pub fn tick<D: TickDelegate + ?Sized>(&self, delegate: &D) {
+        if self.screen.game_state() == GAME_STATE_FOREGROUND {
+            let position = self.screen.read_position();
+            let changed = {
+                let mut last_position = self.last_position.lock().unwrap();
+                let changed = *last_position != Some(position);
+                *last_position = Some(position);
+                changed
+            };
+            if changed {
+                delegate.on_screen_change(position.0, position.1);
+            }
+        }
+        delegate.on_game_tick();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeScreen {
+        game_state: Cell<u32>,
+        position: Cell<(u32, u32)>,
+    }
+
+    impl ReadScreen for FakeScreen {
+        fn game_state(&self) -> u32 {
+            self.game_state.get()
+        }
+
+        fn read_position(&self) -> (u32, u32) {
+            self.position.get()
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingDelegate {
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl TickDelegate for RecordingDelegate {
+        fn on_game_tick(&self) {
+            self.calls.borrow_mut().push("tick".to_owned());
+        }
+
+        fn on_screen_change(&self, field: u32, room: u32) {
+            self.calls
+                .borrow_mut()
+                .push(format!("screen {field} {room}"));
+        }
+    }
+
+    #[test]
+    fn test_tick_driver() {
+        let driver = TickDriver::new(FakeScreen::default());
+        driver.screen.position.set((1, 0));
+        let delegate = RecordingDelegate::default();
+        driver.tick(&delegate);
+        driver.tick(&delegate);
+        driver.screen.position.set((1, 2));
+        driver.tick(&delegate);
+        assert_eq!(
+            *delegate.calls.borrow(),
+            ["screen 1 0", "tick", "tick", "screen 1 2", "tick"]
+        );
+    }
+
+    #[test]
+    fn test_tick_driver_ignores_screens_out_of_foreground() {
+        let driver = TickDriver::new(FakeScreen::default());
+        let delegate = RecordingDelegate::default();
+        driver.screen.game_state.set(-3i32 as u32);
+        driver.screen.position.set((1, 0));
+        driver.tick(&delegate);
+        driver.screen.game_state.set(GAME_STATE_FOREGROUND);
+        driver.tick(&delegate);
+        driver.screen.game_state.set(-2i32 as u32);
+        driver.screen.position.set((1, 2));
+        driver.tick(&delegate);
+        driver.screen.game_state.set(GAME_STATE_FOREGROUND);
+        driver.screen.position.set((1, 0));
+        driver.tick(&delegate);
+        assert_eq!(
+            *delegate.calls.borrow(),
+            ["tick", "screen 1 0", "tick", "tick", "tick"]
+        );
+    }
+}
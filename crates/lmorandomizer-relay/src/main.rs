@@ -0,0 +1,30 @@
+mod server;
+
+use std::{env, fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use lmorandomizer_shared::relay::DEFAULT_RELAY_PORT;
+use tracing::info;
+
+use crate::server::RelayServer;
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt().init();
+
+    let mut args = env::args().skip(1);
+    let manifest_path = PathBuf::from(
+        args.next()
+            .context("usage: lmorandomizer-relay <multiworld.json> [port]")?,
+    );
+    let port = match args.next() {
+        Some(port) => port.parse().context("invalid port")?,
+        None => DEFAULT_RELAY_PORT,
+    };
+    let manifest = serde_json::from_slice(&fs::read(&manifest_path)?)?;
+    // 同じマニフェストで起動し直せば続きから
+    let progress_path = manifest_path.with_extension("progress.json");
+
+    let server = RelayServer::bind(("127.0.0.1", port), manifest, progress_path)?;
+    info!("Listening on {}", server.local_addr()?);
+    server.run()
+}
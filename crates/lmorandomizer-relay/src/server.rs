@@ -0,0 +1,342 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+};
+
+use anyhow::{Result, anyhow, bail};
+use lmorandomizer_shared::{
+    multiworld::{MultiworldEntry, MultiworldManifest},
+    protocol::{Framed, Hello, PROTOCOL_VERSION, ProtocolError},
+    relay::{RelayClientMessage, RelayProgress, RelayServerMessage, RelayState},
+};
+use tracing::{debug, error, info, warn};
+
+/// 書き込みは遅い相手で詰まることがあるので、`Inner` とは別にロックする
+type Writer = Arc<Mutex<Framed<TcpStream>>>;
+
+struct Inner {
+    state: RelayState,
+    /// world -> (接続の番号, 送信側)。同じワールドが繋ぎ直したら新しい方に置き換える
+    clients: HashMap<u32, (u64, Writer)>,
+}
+
+struct Shared {
+    inner: Mutex<Inner>,
+    progress_path: PathBuf,
+    /// 保存済みの `RelayProgress::checked` の数。`inner` を持たずに書き込み順を揃える
+    saved_checks: Mutex<usize>,
+    next_connection: AtomicU64,
+}
+
+pub struct RelayServer {
+    listener: TcpListener,
+    shared: Arc<Shared>,
+}
+
+impl RelayServer {
+    /// `progress_path` に進行状況があれば続きから始める
+    pub fn bind(
+        addr: impl ToSocketAddrs,
+        manifest: MultiworldManifest,
+        progress_path: PathBuf,
+    ) -> Result<Self> {
+        let progress = load_progress(&progress_path)?;
+        let saved_checks = progress.as_ref().map_or(0, |x| x.checked.len());
+        let state = RelayState::new(manifest, progress).map_err(|err| anyhow!(err))?;
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            shared: Arc::new(Shared {
+                inner: Mutex::new(Inner {
+                    state,
+                    clients: HashMap::new(),
+                }),
+                progress_path,
+                saved_checks: Mutex::new(saved_checks),
+                next_connection: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// 接続ごとにスレッドを立てる。戻らない
+    pub fn run(self) -> Result<()> {
+        loop {
+            let (stream, addr) = self.listener.accept()?;
+            debug!("Accepted: {addr}");
+            let shared = self.shared.clone();
+            thread::spawn(move || {
+                if let Err(err) = handle_connection(&shared, stream) {
+                    warn!("Connection from {addr} closed: {err}");
+                }
+            });
+        }
+    }
+}
+
+fn load_progress(path: &Path) -> Result<Option<RelayProgress>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// 書き込み中に落ちても壊れないよう、一時ファイルに書いてから置き換える
+fn save_progress(path: &Path, progress: &RelayProgress) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(progress)?)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+impl Shared {
+    /// 他のスレッドが先に新しいものを書いていたら何もしない
+    fn save_progress(&self, progress: &RelayProgress) -> Result<()> {
+        let mut saved_checks = self.saved_checks.lock().unwrap();
+        if progress.checked.len() <= *saved_checks {
+            return Ok(());
+        }
+        save_progress(&self.progress_path, progress)?;
+        *saved_checks = progress.checked.len();
+        Ok(())
+    }
+}
+
+fn handle_connection(shared: &Shared, stream: TcpStream) -> Result<()> {
+    let mut reader = Framed::new(stream.try_clone()?);
+    let mut writer = Framed::new(stream);
+
+    let hello = match reader.recv()? {
+        RelayClientMessage::Hello(hello) => hello,
+        msg => bail!("handshake expected: {msg:?}"),
+    };
+    if hello.protocol_version != PROTOCOL_VERSION {
+        let err = ProtocolError::VersionMismatch {
+            ours: PROTOCOL_VERSION,
+            theirs: hello.protocol_version,
+        };
+        let message = err.to_string();
+        writer.send(&RelayServerMessage::Error { message })?;
+        return Err(err.into());
+    }
+    writer.send(&RelayServerMessage::Hello(Hello::new(env!(
+        "CARGO_PKG_VERSION"
+    ))))?;
+
+    let (world, fingerprint, received) = match reader.recv()? {
+        RelayClientMessage::Join {
+            world,
+            fingerprint,
+            received,
+        } => (world, fingerprint, received),
+        msg => bail!("join expected: {msg:?}"),
+    };
+    let validated = shared
+        .inner
+        .lock()
+        .unwrap()
+        .state
+        .validate_join(world, &fingerprint);
+    if let Err(message) = validated {
+        writer.send(&RelayServerMessage::Error {
+            message: message.clone(),
+        })?;
+        bail!(message);
+    }
+    let connection = shared.next_connection.fetch_add(1, Ordering::Relaxed);
+    let writer = Arc::new(Mutex::new(writer));
+    // 再送し終わるまで、この接続宛ての新しいアイテムは送らせない
+    let mut locked_writer = writer.lock().unwrap();
+    let backlog = {
+        let mut inner = shared.inner.lock().unwrap();
+        inner.clients.insert(world, (connection, writer.clone()));
+        inner.state.received(world).into_iter().cloned().collect()
+    };
+    let result = send_joined(&mut locked_writer, world, backlog, received);
+    drop(locked_writer);
+    if let Err(err) = result {
+        remove_client(shared, world, connection);
+        return Err(err.into());
+    }
+    info!("World {world} joined");
+
+    let result = serve_client(shared, &mut reader, world);
+
+    remove_client(shared, world, connection);
+    info!("World {world} left");
+    result
+}
+
+/// 切断中に届いていたアイテムのうち、`received` 番目以降を送り直す
+fn send_joined(
+    writer: &mut Framed<TcpStream>,
+    world: u32,
+    backlog: Vec<MultiworldEntry>,
+    received: u32,
+) -> Result<(), ProtocolError> {
+    writer.send(&RelayServerMessage::Joined { world })?;
+    for (index, entry) in backlog.into_iter().enumerate().skip(received as usize) {
+        writer.send(&RelayServerMessage::ReceiveItem {
+            index: index as u32,
+            entry,
+        })?;
+    }
+    Ok(())
+}
+
+/// 繋ぎ直した新しい接続は残す
+fn remove_client(shared: &Shared, world: u32, connection: u64) {
+    let mut inner = shared.inner.lock().unwrap();
+    if inner.clients.get(&world).map(|x| x.0) == Some(connection) {
+        inner.clients.remove(&world);
+    }
+}
+
+fn serve_client(shared: &Shared, reader: &mut Framed<TcpStream>, world: u32) -> Result<()> {
+    loop {
+        let location_flag = match reader.recv() {
+            Ok(RelayClientMessage::LocationChecked { location_flag }) => location_flag,
+            Ok(msg) => {
+                warn!("Unexpected message from world {world}: {msg:?}");
+                continue;
+            }
+            Err(ProtocolError::Decode(err)) => {
+                warn!("Failed to decode message from world {world}: {err}");
+                continue;
+            }
+            Err(ProtocolError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let (index, entry, progress) = {
+            let mut inner = shared.inner.lock().unwrap();
+            let Some((index, entry)) = inner.state.check(world, location_flag) else {
+                continue;
+            };
+            (index, entry, inner.state.progress().clone())
+        };
+        // 他のワールドの通信を止めないよう、ディスクへの書き込みはロックの外で行う
+        shared.save_progress(&progress)?;
+        info!(
+            "World {world}: {} -> world {}: {}",
+            entry.location, entry.recipient, entry.item
+        );
+        let recipient = entry.recipient;
+        let Some((connection, client)) = shared
+            .inner
+            .lock()
+            .unwrap()
+            .clients
+            .get(&recipient)
+            .cloned()
+        else {
+            // 次に繋いだときに送る
+            continue;
+        };
+        let result = client
+            .lock()
+            .unwrap()
+            .send(&RelayServerMessage::ReceiveItem { index, entry });
+        if let Err(err) = result {
+            error!("Failed to send an item to world {recipient}: {err}");
+            remove_client(shared, recipient, connection);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use lmorandomizer_shared::{
+        lmo::{Equipment, GameItem, Rom},
+        relay::RelayClient,
+    };
+
+    use super::*;
+
+    fn manifest() -> MultiworldManifest {
+        let entry = |world, location_flag, recipient, item| MultiworldEntry {
+            world,
+            location: format!("chest{location_flag}"),
+            location_flag,
+            recipient,
+            item,
+            item_flag: 100,
+        };
+        MultiworldManifest::new(
+            vec!["fp0".to_owned(), "fp1".to_owned()],
+            vec![
+                entry(0, 6000, 1, GameItem::Equipment(Equipment::Feather)),
+                entry(0, 6001, 1, GameItem::Rom(Rom::GameMaster)),
+                entry(1, 6000, 0, GameItem::Equipment(Equipment::GrappleClaw)),
+            ],
+        )
+    }
+
+    fn spawn_server(progress_path: &Path) -> SocketAddr {
+        let server =
+            RelayServer::bind("127.0.0.1:0", manifest(), progress_path.to_owned()).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+        addr
+    }
+
+    fn join(addr: SocketAddr, world: u32, received: u32) -> RelayClient<TcpStream> {
+        let stream = TcpStream::connect(addr).unwrap();
+        let fingerprint = format!("fp{world}");
+        RelayClient::connect(stream, "1.0.0", world, &fingerprint, received).unwrap()
+    }
+
+    #[test]
+    fn test_relay_with_simulated_clients() {
+        let progress_path =
+            env::temp_dir().join(format!("lmorandomizer_relay_{}.json", std::process::id()));
+        let _ = fs::remove_file(&progress_path);
+
+        let addr = spawn_server(&progress_path);
+        let mut player0 = join(addr, 0, 0);
+        let mut player1 = join(addr, 1, 0);
+
+        player0.check_location(7000).unwrap();
+        player0.check_location(6001).unwrap();
+        player0.check_location(6001).unwrap();
+        let (index, entry) = player1.recv_item().unwrap();
+        assert_eq!((index, entry.item), (0, GameItem::Rom(Rom::GameMaster)));
+
+        player1.check_location(6000).unwrap();
+        let (index, entry) = player0.recv_item().unwrap();
+        assert_eq!(index, 0);
+        assert_eq!(entry.item, GameItem::Equipment(Equipment::GrappleClaw));
+
+        // 切断中に取られたものは繋ぎ直したときに届く
+        drop(player1);
+        player0.check_location(6000).unwrap();
+        let mut player1 = join(addr, 1, 1);
+        let (index, entry) = player1.recv_item().unwrap();
+        assert_eq!((index, entry.location_flag), (1, 6000));
+
+        let stream = TcpStream::connect(addr).unwrap();
+        assert!(RelayClient::connect(stream, "1.0.0", 1, "fp0", 0).is_err());
+
+        // サーバーを起動し直しても進行状況は残っている
+        let addr = spawn_server(&progress_path);
+        let mut player1 = join(addr, 1, 0);
+        let flags: Vec<_> = (0..2).map(|_| player1.recv_item().unwrap().1).collect();
+        let flags: Vec<_> = flags.iter().map(|x| x.location_flag).collect();
+        assert_eq!(flags, [6001, 6000]);
+
+        fs::remove_file(&progress_path).unwrap();
+    }
+}
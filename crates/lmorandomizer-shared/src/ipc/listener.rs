@@ -4,7 +4,7 @@ use smol::{stream::StreamExt, unblock};
 use tracing::debug;
 use windows::{
     Win32::{
-        Foundation::{ERROR_NO_DATA, ERROR_PIPE_CONNECTED, HANDLE},
+        Foundation::{ERROR_NO_DATA, ERROR_PIPE_CONNECTED},
         Storage::FileSystem::PIPE_ACCESS_DUPLEX,
         System::Pipes::{
             ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_TYPE_BYTE, PIPE_WAIT,
@@ -13,7 +13,7 @@ use windows::{
     core::{Owned, PCWSTR},
 };
 
-use super::{IpcStream, to_wide_null};
+use super::{IpcAcceptor, IpcStream, to_wide_null};
 
 const PIPE_BUFFER_SIZE: u32 = 1024;
 
@@ -28,41 +28,54 @@ impl IpcListener {
     }
 
     pub fn incoming(&self) -> impl smol::stream::Stream<Item = io::Result<IpcStream>> {
-        smol::stream::repeat_with(|| self.accept()).then(|fut| fut)
+        smol::stream::repeat_with(|| self.accept_async()).then(|fut| fut)
     }
 
-    pub async fn accept(&self) -> io::Result<IpcStream> {
-        let handle = unsafe {
-            Owned::new(CreateNamedPipeW(
-                PCWSTR(self.pipe_name.as_ptr()),
-                PIPE_ACCESS_DUPLEX,
-                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
-                1,
-                PIPE_BUFFER_SIZE,
-                PIPE_BUFFER_SIZE,
-                0,    // デフォルトタイムアウト
-                None, // セキュリティ属性なし
-            ))
-        };
-        if handle.is_invalid() {
-            return Err(io::Error::last_os_error());
-        }
+    /// 接続を待つ間スレッドを塞がない `IpcAcceptor::accept`
+    pub async fn accept_async(&self) -> io::Result<IpcStream> {
+        let pipe_name = self.pipe_name.clone();
+        unblock(move || accept_blocking(&pipe_name)).await
+    }
+}
 
-        debug!("Pipe created, waiting for client to connect...");
+impl IpcAcceptor for IpcListener {
+    type Stream = IpcStream;
+
+    fn accept(&self) -> io::Result<IpcStream> {
+        accept_blocking(&self.pipe_name)
+    }
+}
 
-        // クライアントの接続を同期的に待機
-        let raw_handle = handle.0 as usize;
-        match unblock(move || unsafe { ConnectNamedPipe(HANDLE(raw_handle as _), None) }).await {
-            Ok(()) => {}
-            Err(e) => {
-                if e.code() != ERROR_PIPE_CONNECTED.to_hresult()
-                    && e.code() != ERROR_NO_DATA.to_hresult()
-                {
-                    return Err(io::Error::from_raw_os_error(e.code().0));
-                }
+fn accept_blocking(pipe_name: &[u16]) -> io::Result<IpcStream> {
+    let handle = unsafe {
+        Owned::new(CreateNamedPipeW(
+            PCWSTR(pipe_name.as_ptr()),
+            PIPE_ACCESS_DUPLEX,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+            1,
+            PIPE_BUFFER_SIZE,
+            PIPE_BUFFER_SIZE,
+            0,    // デフォルトタイムアウト
+            None, // セキュリティ属性なし
+        ))
+    };
+    if handle.is_invalid() {
+        return Err(io::Error::last_os_error());
+    }
+
+    debug!("Pipe created, waiting for client to connect...");
+
+    // クライアントの接続を同期的に待機
+    match unsafe { ConnectNamedPipe(*handle, None) } {
+        Ok(()) => {}
+        Err(e) => {
+            if e.code() != ERROR_PIPE_CONNECTED.to_hresult()
+                && e.code() != ERROR_NO_DATA.to_hresult()
+            {
+                return Err(io::Error::from_raw_os_error(e.code().0));
             }
         }
-
-        Ok(IpcStream::new(handle))
     }
+
+    Ok(IpcStream::new(handle))
 }
@@ -0,0 +1,100 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::mpsc::{Receiver, Sender, channel},
+};
+
+use super::{IpcAcceptor, IpcConnector, IpcTransport};
+
+/// 同じプロセス内の双方向パイプ。相手を drop すると読み込みは EOF になる
+pub struct MemoryStream {
+    tx: Sender<Vec<u8>>,
+    rx: Receiver<Vec<u8>>,
+    buf: VecDeque<u8>,
+}
+
+impl MemoryStream {
+    pub fn pair() -> (Self, Self) {
+        let (tx1, rx1) = channel();
+        let (tx2, rx2) = channel();
+        let a = Self {
+            tx: tx1,
+            rx: rx2,
+            buf: VecDeque::new(),
+        };
+        let b = Self {
+            tx: tx2,
+            rx: rx1,
+            buf: VecDeque::new(),
+        };
+        (a, b)
+    }
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buf.is_empty() {
+            match self.rx.recv() {
+                Ok(data) => self.buf.extend(data),
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.buf.len());
+        for (dst, src) in buf.iter_mut().zip(self.buf.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx
+            .send(buf.to_vec())
+            .map_err(|_| io::ErrorKind::BrokenPipe)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl IpcTransport for MemoryStream {}
+
+pub struct MemoryListener {
+    rx: Receiver<MemoryStream>,
+}
+
+impl IpcAcceptor for MemoryListener {
+    type Stream = MemoryStream;
+
+    fn accept(&self) -> io::Result<MemoryStream> {
+        self.rx
+            .recv()
+            .map_err(|_| io::Error::from(io::ErrorKind::NotConnected))
+    }
+}
+
+#[derive(Clone)]
+pub struct MemoryConnector {
+    tx: Sender<MemoryStream>,
+}
+
+impl IpcConnector for MemoryConnector {
+    type Stream = MemoryStream;
+
+    /// リスナーが drop されていれば `ConnectionRefused`
+    fn connect(&self) -> io::Result<MemoryStream> {
+        let (client, server) = MemoryStream::pair();
+        self.tx
+            .send(server)
+            .map_err(|_| io::Error::from(io::ErrorKind::ConnectionRefused))?;
+        Ok(client)
+    }
+}
+
+pub fn memory_channel() -> (MemoryListener, MemoryConnector) {
+    let (tx, rx) = channel();
+    (MemoryListener { rx }, MemoryConnector { tx })
+}
@@ -1,10 +1,123 @@
+#[cfg(target_os = "windows")]
 mod listener;
+mod memory;
+#[cfg(target_os = "windows")]
 mod stream;
+#[cfg(unix)]
+mod unix;
 
+use std::{
+    io::{self, Read, Write},
+    thread,
+    time::Duration,
+};
+
+use tracing::debug;
+
+#[cfg(target_os = "windows")]
 pub use listener::IpcListener;
-pub use stream::IpcStream;
+pub use memory::{MemoryConnector, MemoryListener, MemoryStream, memory_channel};
+#[cfg(target_os = "windows")]
+pub use stream::{IpcStream, NamedPipeConnector};
+#[cfg(unix)]
+pub use unix::{UnixSocketConnector, UnixSocketListener};
+
+/// ランチャーとフックの間の双方向のバイト列。フレーミングは `protocol` が行う
+pub trait IpcTransport: Read + Write + Send {}
+
+pub trait IpcAcceptor {
+    type Stream: IpcTransport;
+
+    /// 接続されるまでブロックする
+    fn accept(&self) -> io::Result<Self::Stream>;
+}
+
+pub trait IpcConnector {
+    type Stream: IpcTransport;
+
+    fn connect(&self) -> io::Result<Self::Stream>;
+}
+
+/// 相手の起動を待つため、失敗したら `interval` おきに `retries` 回までやり直す
+pub fn connect_with_retry<C: IpcConnector>(
+    connector: &C,
+    retries: u32,
+    interval: Duration,
+) -> io::Result<C::Stream> {
+    let mut i = 0;
+    loop {
+        match connector.connect() {
+            Ok(stream) => return Ok(stream),
+            Err(err) if i < retries => {
+                debug!("Failed to connect: {err}. Retrying...");
+                thread::sleep(interval);
+                i += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
 
 /// Rust の `&str` を Win32 が要求する UTF-16 + null 終端列に変換
+#[cfg(target_os = "windows")]
 fn to_wide_null(s: &str) -> Vec<u16> {
     s.encode_utf16().chain(std::iter::once(0)).collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        Command,
+        protocol::{HookSession, LauncherSession},
+    };
+
+    use super::*;
+
+    /// フック側の処理。トランスポートに依存しない
+    fn serve<A: IpcAcceptor>(listener: A, sessions: usize) {
+        for _ in 0..sessions {
+            let stream = listener.accept().unwrap();
+            let mut session = HookSession::accept(stream, "1.0.0").unwrap();
+            let (id, _) = session.recv_request().unwrap();
+            session.ack(id).unwrap();
+        }
+    }
+
+    fn request<C: IpcConnector>(connector: &C) {
+        let stream = connect_with_retry(connector, 50, Duration::from_millis(20)).unwrap();
+        let (mut session, _) = LauncherSession::connect(stream, "1.0.0").unwrap();
+        session
+            .request(Command::Init(PathBuf::from("world")))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_memory_reconnect() {
+        let (listener, connector) = memory_channel();
+        let server = thread::spawn(move || serve(listener, 2));
+        request(&connector);
+        request(&connector);
+        server.join().unwrap();
+
+        assert_eq!(
+            connector.connect().err().map(|x| x.kind()),
+            Some(io::ErrorKind::ConnectionRefused)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket_retry() {
+        let name = format!("lmorandomizer_test_{}", std::process::id());
+        let connector = UnixSocketConnector::new(&name);
+        let server = thread::spawn(move || {
+            // リスナーが遅れて起動しても接続できる
+            thread::sleep(Duration::from_millis(100));
+            serve(UnixSocketListener::bind(&name).unwrap(), 1);
+        });
+        request(&connector);
+        server.join().unwrap();
+    }
+}
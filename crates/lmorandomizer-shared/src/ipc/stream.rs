@@ -13,7 +13,7 @@ use windows::{
     core::{Owned, PCWSTR},
 };
 
-use super::to_wide_null;
+use super::{IpcConnector, IpcTransport, to_wide_null};
 
 pub struct IpcStream {
     handle: Owned<HANDLE>,
@@ -54,9 +54,32 @@ impl IpcStream {
     }
 }
 
+// パイプのハンドルはスレッドに依らない
 unsafe impl Send for IpcStream {}
 unsafe impl Sync for IpcStream {}
 
+impl IpcTransport for IpcStream {}
+
+pub struct NamedPipeConnector {
+    name: String,
+}
+
+impl NamedPipeConnector {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl IpcConnector for NamedPipeConnector {
+    type Stream = IpcStream;
+
+    fn connect(&self) -> io::Result<IpcStream> {
+        IpcStream::connect(&self.name)
+    }
+}
+
 impl Drop for IpcStream {
     fn drop(&mut self) {
         if let Err(err) = unsafe { DisconnectNamedPipe(*self.handle) } {
@@ -0,0 +1,67 @@
+use std::{
+    env, fs, io,
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+};
+
+use super::{IpcAcceptor, IpcConnector, IpcTransport};
+
+/// 名前付きパイプと同じ名前で使えるよう、一時ディレクトリのソケットにする
+fn socket_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("{name}.sock"))
+}
+
+impl IpcTransport for UnixStream {}
+
+pub struct UnixSocketListener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl UnixSocketListener {
+    pub fn bind(name: &str) -> io::Result<Self> {
+        let path = socket_path(name);
+        // 前回異常終了したときのソケットが残っていると bind できない
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        let listener = UnixListener::bind(&path)?;
+        Ok(Self { listener, path })
+    }
+}
+
+impl Drop for UnixSocketListener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl IpcAcceptor for UnixSocketListener {
+    type Stream = UnixStream;
+
+    fn accept(&self) -> io::Result<UnixStream> {
+        Ok(self.listener.accept()?.0)
+    }
+}
+
+pub struct UnixSocketConnector {
+    path: PathBuf,
+}
+
+impl UnixSocketConnector {
+    pub fn new(name: &str) -> Self {
+        Self {
+            path: socket_path(name),
+        }
+    }
+}
+
+impl IpcConnector for UnixSocketConnector {
+    type Stream = UnixStream;
+
+    fn connect(&self) -> io::Result<UnixStream> {
+        UnixStream::connect(&self.path)
+    }
+}
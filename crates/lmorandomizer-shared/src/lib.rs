@@ -1,10 +1,22 @@
-#[cfg(target_os = "windows")]
 pub mod ipc;
 pub mod lmo;
+pub mod multiworld;
+pub mod protocol;
+pub mod relay;
+pub mod tracker;
 
 use std::path::PathBuf;
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+use crate::lmo::GameItem;
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub enum Command {
     Init(PathBuf),
+    /// 応答の前にトラッカー用のスナップショットか差分を `Event` で送る
+    PollTracker,
+    /// ゲームスレッドで順に与え、取得済みのフラグ `flag` も立てる
+    GiveItem {
+        item: GameItem,
+        flag: u16,
+    },
 }
@@ -1,9 +1,12 @@
 use std::ptr::{NonNull, read_volatile};
 
-#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct Flags(#[serde(with = "serde_big_array::BigArray")] [u8; 1000]);
 
 impl Flags {
+    /// フラグの数 (1000 バイト)
+    pub const COUNT: u16 = 8000;
+
     pub fn new(value: [u8; 1000]) -> Self {
         Self(value)
     }
@@ -17,6 +20,24 @@ impl Flags {
         (byte & (1 << bit_index)) != 0
     }
 
+    pub fn set(&mut self, idx: u16, value: bool) {
+        let byte_index = (idx / 8) as usize;
+        let bit_index = (idx % 8) as u8;
+
+        if value {
+            self.0[byte_index] |= 1 << bit_index;
+        } else {
+            self.0[byte_index] &= !(1 << bit_index);
+        }
+    }
+
+    /// `self` と `other` で値が異なるフラグの番号
+    pub fn diff(&self, other: &Self) -> Vec<u16> {
+        (0..(self.0.len() * 8) as u16)
+            .filter(|&idx| self.get(idx) != other.get(idx))
+            .collect()
+    }
+
     /// # Safety
     /// `ptr` must be a valid pointer to a `Flags` struct.
     pub unsafe fn get_volatile(ptr: NonNull<Self>, idx: u16) -> bool {
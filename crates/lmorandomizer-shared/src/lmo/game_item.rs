@@ -0,0 +1,23 @@
+use std::fmt;
+
+use super::{Equipment, MainWeapon, Rom, SubWeapon};
+
+/// ゲームの取得関数で直接与えられるアイテム
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum GameItem {
+    MainWeapon(MainWeapon),
+    SubWeapon { sub_weapon: SubWeapon, amount: u8 },
+    Equipment(Equipment),
+    Rom(Rom),
+}
+
+impl fmt::Display for GameItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MainWeapon(x) => write!(f, "{x}"),
+            Self::SubWeapon { sub_weapon, amount } => write!(f, "{sub_weapon} x{amount}"),
+            Self::Equipment(x) => write!(f, "{x}"),
+            Self::Rom(x) => write!(f, "{x}"),
+        }
+    }
+}
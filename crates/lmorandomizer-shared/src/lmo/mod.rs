@@ -1,10 +1,12 @@
 mod equipment;
 mod flags;
+mod game_item;
 mod others;
 mod rom;
 
 pub use equipment::Equipment;
 pub use flags::Flags;
+pub use game_item::GameItem;
 pub use others::{FieldNumber, MainWeapon, Seal, SubWeapon};
 pub use rom::Rom;
 
@@ -116,7 +116,17 @@ impl fmt::Display for FieldNumber {
 }
 
 #[derive(
-    Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, num_derive::FromPrimitive, strum::EnumString,
+    Clone,
+    Copy,
+    Debug,
+    Eq,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    num_derive::FromPrimitive,
+    serde::Deserialize,
+    serde::Serialize,
+    strum::EnumString,
 )]
 #[repr(u8)]
 pub enum MainWeapon {
@@ -151,7 +161,17 @@ impl fmt::Display for MainWeapon {
 }
 
 #[derive(
-    Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, num_derive::FromPrimitive, strum::EnumString,
+    Clone,
+    Copy,
+    Debug,
+    Eq,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    num_derive::FromPrimitive,
+    serde::Deserialize,
+    serde::Serialize,
+    strum::EnumString,
 )]
 #[repr(u8)]
 pub enum SubWeapon {
@@ -10,6 +10,8 @@ use strum::IntoEnumIterator;
     PartialEq,
     PartialOrd,
     num_derive::FromPrimitive,
+    serde::Deserialize,
+    serde::Serialize,
     strum::EnumIter,
     strum::EnumString,
 )]
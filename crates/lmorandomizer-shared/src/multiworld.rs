@@ -0,0 +1,51 @@
+use crate::lmo::GameItem;
+
+/// あるワールドのスポットに置かれた、別のワールドのプレイヤー宛てのアイテム
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct MultiworldEntry {
+    /// スポットがあるワールドの番号
+    pub world: u32,
+    pub location: String,
+    /// スポットを取ると立つ、そのワールド固有のフラグ
+    pub location_flag: u16,
+    /// アイテムを受け取るワールドの番号
+    pub recipient: u32,
+    pub item: GameItem,
+    /// 受け取るワールドで、そのアイテムを取ると立つフラグ
+    pub item_flag: u16,
+}
+
+/// マルチワールドの生成結果。リレーサーバーが読む
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct MultiworldManifest {
+    pub version: u32,
+    /// `MultiworldEntry::world` の番号順
+    pub fingerprints: Vec<String>,
+    pub entries: Vec<MultiworldEntry>,
+}
+
+impl MultiworldManifest {
+    /// 互換性のない変更をしたら上げる
+    pub const VERSION: u32 = 1;
+
+    pub fn new(fingerprints: Vec<String>, entries: Vec<MultiworldEntry>) -> Self {
+        Self {
+            version: Self::VERSION,
+            fingerprints,
+            entries,
+        }
+    }
+
+    pub fn find(&self, world: u32, location_flag: u16) -> Option<&MultiworldEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.world == world && entry.location_flag == location_flag)
+    }
+
+    /// `recipient` が受け取るアイテム
+    pub fn items_for(&self, recipient: u32) -> impl Iterator<Item = &MultiworldEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.recipient == recipient)
+    }
+}
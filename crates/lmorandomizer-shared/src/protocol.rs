@@ -0,0 +1,399 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, Read, Write},
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    Command,
+    tracker::{GameSnapshot, SnapshotDelta},
+};
+
+/// 互換性のない変更をしたら上げる
+pub const PROTOCOL_VERSION: u32 = 1;
+/// 壊れた長さで巨大な確保をしないための上限
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Hello {
+    pub protocol_version: u32,
+    pub randomizer_version: String,
+}
+
+impl Hello {
+    pub fn new(randomizer_version: &str) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            randomizer_version: randomizer_version.to_owned(),
+        }
+    }
+}
+
+/// ゲーム側から任意のタイミングで送られる通知
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Event {
+    FlagSet {
+        flag: u16,
+    },
+    /// 接続後最初の `PollTracker` への応答
+    Snapshot(Box<GameSnapshot>),
+    /// 以降の `PollTracker` への応答
+    SnapshotDelta(SnapshotDelta),
+}
+
+/// ランチャーからフックへ
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub enum LauncherMessage {
+    Hello(Hello),
+    Request { id: u32, command: Command },
+}
+
+/// フックからランチャーへ
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub enum HookMessage {
+    Hello(Hello),
+    Ack {
+        id: u32,
+    },
+    /// `id` がない場合はリクエストを特定できなかったエラー
+    Error {
+        id: Option<u32>,
+        message: String,
+    },
+    Event(Event),
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    Io(io::Error),
+    /// 送るメッセージを直列化できなかった。こちらの不具合なので接続を閉じる
+    Encode(String),
+    /// フレームは読めたが中身を解釈できなかった。接続は続けられる
+    Decode(String),
+    VersionMismatch {
+        ours: u32,
+        theirs: u32,
+    },
+    Remote(String),
+    Unexpected(String),
+}
+
+impl ProtocolError {
+    /// 接続を閉じるべきエラーか
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, Self::Decode(_) | Self::Remote(_))
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "IPC I/O error: {err}"),
+            Self::Encode(err) => write!(f, "Failed to encode IPC message: {err}"),
+            Self::Decode(err) => write!(f, "Failed to decode IPC message: {err}"),
+            Self::VersionMismatch { ours, theirs } => write!(
+                f,
+                "IPC protocol version mismatch: ours={ours}, theirs={theirs}"
+            ),
+            Self::Remote(message) => write!(f, "Remote error: {message}"),
+            Self::Unexpected(message) => write!(f, "Unexpected IPC message: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<io::Error> for ProtocolError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// u32 (LE) の長さと MessagePack の本体を1フレームとして読み書きする
+pub struct Framed<T> {
+    stream: T,
+}
+
+impl<T: Read + Write> Framed<T> {
+    pub fn new(stream: T) -> Self {
+        Self { stream }
+    }
+
+    pub fn send(&mut self, msg: &impl Serialize) -> Result<(), ProtocolError> {
+        let payload =
+            rmp_serde::to_vec_named(msg).map_err(|err| ProtocolError::Encode(err.to_string()))?;
+        self.stream
+            .write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.stream.write_all(&payload)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    pub fn recv<M: DeserializeOwned>(&mut self) -> Result<M, ProtocolError> {
+        let mut len = [0; 4];
+        self.stream.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too long").into());
+        }
+        let mut payload = vec![0; len];
+        self.stream.read_exact(&mut payload)?;
+        rmp_serde::from_slice(&payload).map_err(|err| ProtocolError::Decode(err.to_string()))
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> T {
+        self.stream
+    }
+}
+
+/// ランチャー側。リクエストの応答を待つ間に届いたイベントは溜めておく
+pub struct LauncherSession<T> {
+    framed: Framed<T>,
+    next_id: u32,
+    events: VecDeque<Event>,
+}
+
+impl<T: Read + Write> LauncherSession<T> {
+    /// ハンドシェイクして、フックの `Hello` を返す
+    pub fn connect(stream: T, randomizer_version: &str) -> Result<(Self, Hello), ProtocolError> {
+        let mut framed = Framed::new(stream);
+        framed.send(&LauncherMessage::Hello(Hello::new(randomizer_version)))?;
+        let hello = match framed.recv()? {
+            HookMessage::Hello(hello) => hello,
+            HookMessage::Error { message, .. } => return Err(ProtocolError::Remote(message)),
+            msg => return Err(ProtocolError::Unexpected(format!("{msg:?}"))),
+        };
+        if hello.protocol_version != PROTOCOL_VERSION {
+            return Err(ProtocolError::VersionMismatch {
+                ours: PROTOCOL_VERSION,
+                theirs: hello.protocol_version,
+            });
+        }
+        let zelf = Self {
+            framed,
+            next_id: 0,
+            events: VecDeque::new(),
+        };
+        Ok((zelf, hello))
+    }
+
+    /// `Ack` か `Error` が返るまで待つ
+    pub fn request(&mut self, command: Command) -> Result<(), ProtocolError> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.framed
+            .send(&LauncherMessage::Request { id, command })?;
+        loop {
+            match self.framed.recv()? {
+                HookMessage::Ack { id: ack_id } if ack_id == id => return Ok(()),
+                HookMessage::Error {
+                    id: Some(err_id),
+                    message,
+                } if err_id == id => return Err(ProtocolError::Remote(message)),
+                HookMessage::Error { id: None, message } => {
+                    return Err(ProtocolError::Remote(message));
+                }
+                HookMessage::Event(event) => self.events.push_back(event),
+                msg => return Err(ProtocolError::Unexpected(format!("{msg:?}"))),
+            }
+        }
+    }
+
+    /// `PollTracker` を送り、応答の前に届いたスナップショットか差分を返す
+    pub fn poll_tracker(&mut self) -> Result<Event, ProtocolError> {
+        self.request(Command::PollTracker)?;
+        let idx = self
+            .events
+            .iter()
+            .rposition(|event| matches!(event, Event::Snapshot(_) | Event::SnapshotDelta(_)))
+            .ok_or_else(|| ProtocolError::Unexpected("no tracker snapshot".to_owned()))?;
+        Ok(self.events.remove(idx).unwrap())
+    }
+
+    /// 溜まっているイベントがなければ次のメッセージを待つ
+    pub fn next_event(&mut self) -> Result<Event, ProtocolError> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(event);
+        }
+        match self.framed.recv()? {
+            HookMessage::Event(event) => Ok(event),
+            HookMessage::Error { message, .. } => Err(ProtocolError::Remote(message)),
+            msg => Err(ProtocolError::Unexpected(format!("{msg:?}"))),
+        }
+    }
+}
+
+/// フック側
+pub struct HookSession<T> {
+    framed: Framed<T>,
+    peer: Hello,
+}
+
+impl<T: Read + Write> HookSession<T> {
+    /// ランチャーの `Hello` を待ち、バージョンが合えば `Hello` を返す
+    pub fn accept(stream: T, randomizer_version: &str) -> Result<Self, ProtocolError> {
+        let mut framed = Framed::new(stream);
+        let peer = match framed.recv()? {
+            LauncherMessage::Hello(hello) => hello,
+            msg => {
+                let message = format!("handshake expected: {msg:?}");
+                framed.send(&HookMessage::Error {
+                    id: None,
+                    message: message.clone(),
+                })?;
+                return Err(ProtocolError::Unexpected(message));
+            }
+        };
+        if peer.protocol_version != PROTOCOL_VERSION {
+            let err = ProtocolError::VersionMismatch {
+                ours: PROTOCOL_VERSION,
+                theirs: peer.protocol_version,
+            };
+            framed.send(&HookMessage::Error {
+                id: None,
+                message: err.to_string(),
+            })?;
+            return Err(err);
+        }
+        framed.send(&HookMessage::Hello(Hello::new(randomizer_version)))?;
+        Ok(Self { framed, peer })
+    }
+
+    pub fn peer(&self) -> &Hello {
+        &self.peer
+    }
+
+    /// 解釈できないリクエストにはエラーを返して次を待つ
+    pub fn recv_request(&mut self) -> Result<(u32, Command), ProtocolError> {
+        loop {
+            match self.framed.recv() {
+                Ok(LauncherMessage::Request { id, command }) => return Ok((id, command)),
+                Ok(msg) => self.reply_error(None, format!("unexpected message: {msg:?}"))?,
+                Err(ProtocolError::Decode(err)) => self.reply_error(None, err)?,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub fn ack(&mut self, id: u32) -> Result<(), ProtocolError> {
+        self.framed.send(&HookMessage::Ack { id })
+    }
+
+    pub fn reply_error(&mut self, id: Option<u32>, message: String) -> Result<(), ProtocolError> {
+        self.framed.send(&HookMessage::Error { id, message })
+    }
+
+    pub fn push_event(&mut self, event: Event) -> Result<(), ProtocolError> {
+        self.framed.send(&HookMessage::Event(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        thread,
+    };
+
+    use crate::ipc::MemoryStream;
+
+    use super::*;
+
+    #[test]
+    fn test_request_and_events() {
+        let (launcher, hook) = MemoryStream::pair();
+        let hook = thread::spawn(move || -> Result<(), ProtocolError> {
+            let mut session = HookSession::accept(hook, "1.0.0")?;
+            assert_eq!(session.peer().randomizer_version, "0.9.0");
+            let (id, command) = session.recv_request()?;
+            assert!(matches!(command, Command::Init(path) if path == Path::new("world")));
+            session.push_event(Event::FlagSet { flag: 7500 })?;
+            session.ack(id)?;
+            let (id, _) = session.recv_request()?;
+            session.reply_error(Some(id), "already initialized".to_owned())?;
+            Ok(())
+        });
+
+        let (mut session, hello) = LauncherSession::connect(launcher, "0.9.0").unwrap();
+        assert_eq!(hello, Hello::new("1.0.0"));
+        session
+            .request(Command::Init(PathBuf::from("world")))
+            .unwrap();
+        assert_eq!(session.next_event().unwrap(), Event::FlagSet { flag: 7500 });
+        let err = session
+            .request(Command::Init(PathBuf::from("world")))
+            .unwrap_err();
+        assert!(matches!(err, ProtocolError::Remote(msg) if msg == "already initialized"));
+        hook.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_encode_error_is_fatal() {
+        struct Unserializable;
+        impl Serialize for Unserializable {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("unserializable"))
+            }
+        }
+
+        let (launcher, _hook) = MemoryStream::pair();
+        let err = Framed::new(launcher).send(&Unserializable).unwrap_err();
+        assert!(matches!(err, ProtocolError::Encode(_)));
+        assert!(err.is_fatal());
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        let (launcher, hook) = MemoryStream::pair();
+        let hook = thread::spawn(move || HookSession::accept(hook, "1.0.0").map(|_| ()));
+
+        let mut framed = Framed::new(launcher);
+        let hello = Hello {
+            protocol_version: PROTOCOL_VERSION + 1,
+            randomizer_version: "2.0.0".to_owned(),
+        };
+        framed.send(&LauncherMessage::Hello(hello)).unwrap();
+        let reply: HookMessage = framed.recv().unwrap();
+        assert!(matches!(reply, HookMessage::Error { id: None, .. }));
+        assert!(matches!(
+            hook.join().unwrap(),
+            Err(ProtocolError::VersionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_broken_request_is_not_fatal() {
+        let (launcher, hook) = MemoryStream::pair();
+        let hook = thread::spawn(move || -> Result<(), ProtocolError> {
+            let mut session = HookSession::accept(hook, "1.0.0")?;
+            let (id, _) = session.recv_request()?;
+            session.ack(id)
+        });
+
+        let mut framed = Framed::new(launcher);
+        framed
+            .send(&LauncherMessage::Hello(Hello::new("1.0.0")))
+            .unwrap();
+        let _: HookMessage = framed.recv().unwrap();
+        let mut stream = framed.into_inner();
+        stream.write_all(&3u32.to_le_bytes()).unwrap();
+        stream.write_all(&[0xc1, 0xc1, 0xc1]).unwrap();
+        let mut framed = Framed::new(stream);
+        let reply: HookMessage = framed.recv().unwrap();
+        assert!(matches!(reply, HookMessage::Error { id: None, .. }));
+
+        let command = Command::Init(PathBuf::from("world"));
+        framed
+            .send(&LauncherMessage::Request { id: 3, command })
+            .unwrap();
+        let reply: HookMessage = framed.recv().unwrap();
+        assert!(matches!(reply, HookMessage::Ack { id: 3 }));
+        hook.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,484 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::mpsc::{Receiver, channel},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    Command,
+    lmo::Flags,
+    multiworld::{MultiworldEntry, MultiworldManifest},
+    protocol::{Framed, Hello, LauncherSession, PROTOCOL_VERSION, ProtocolError},
+    tracker::{Tracker, TrackerUpdate, poll_tracker_with},
+};
+
+/// ポートを指定しなかったときにリレーサーバーが待ち受ける
+pub const DEFAULT_RELAY_PORT: u16 = 51600;
+
+/// ランチャーからリレーサーバーへ
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub enum RelayClientMessage {
+    Hello(Hello),
+    /// `received` は受け取り済みのアイテムの数。それ以降を再送してもらう
+    Join {
+        world: u32,
+        fingerprint: String,
+        received: u32,
+    },
+    /// 自分のワールドのスポットを取った
+    LocationChecked {
+        location_flag: u16,
+    },
+}
+
+/// リレーサーバーからランチャーへ
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub enum RelayServerMessage {
+    Hello(Hello),
+    Joined {
+        world: u32,
+    },
+    /// `index` は受け取る側のワールドで 0 から数えた通し番号
+    ReceiveItem {
+        index: u32,
+        entry: MultiworldEntry,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// 再開用にディスクへ保存するリレーの進行状況
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct RelayProgress {
+    pub fingerprints: Vec<String>,
+    /// 取られた順の (world, location_flag)
+    pub checked: Vec<(u32, u16)>,
+}
+
+/// マニフェストと進行状況から、誰に何を届けるかを決める
+pub struct RelayState {
+    manifest: MultiworldManifest,
+    progress: RelayProgress,
+}
+
+impl RelayState {
+    pub fn new(
+        manifest: MultiworldManifest,
+        progress: Option<RelayProgress>,
+    ) -> Result<Self, String> {
+        if manifest.version != MultiworldManifest::VERSION {
+            return Err(format!(
+                "unsupported multiworld manifest version: {}",
+                manifest.version
+            ));
+        }
+        let progress = match progress {
+            Some(progress) if progress.fingerprints != manifest.fingerprints => {
+                return Err("progress belongs to a different multiworld".to_owned());
+            }
+            Some(progress) => progress,
+            None => RelayProgress {
+                fingerprints: manifest.fingerprints.clone(),
+                checked: Vec::new(),
+            },
+        };
+        Ok(Self { manifest, progress })
+    }
+
+    pub fn progress(&self) -> &RelayProgress {
+        &self.progress
+    }
+
+    pub fn validate_join(&self, world: u32, fingerprint: &str) -> Result<(), String> {
+        match self.manifest.fingerprints.get(world as usize) {
+            Some(x) if x == fingerprint => Ok(()),
+            Some(_) => Err(format!("fingerprint mismatch for world {world}")),
+            None => Err(format!("unknown world: {world}")),
+        }
+    }
+
+    /// 初めて取られた他プレイヤー宛てのスポットなら、受取人側の通し番号とエントリを返す
+    pub fn check(&mut self, world: u32, location_flag: u16) -> Option<(u32, MultiworldEntry)> {
+        if self.progress.checked.contains(&(world, location_flag)) {
+            return None;
+        }
+        let entry = self.manifest.find(world, location_flag)?.clone();
+        self.progress.checked.push((world, location_flag));
+        let index = self.received(entry.recipient).len() as u32 - 1;
+        Some((index, entry))
+    }
+
+    /// `recipient` に届けるべきアイテムを取られた順に
+    pub fn received(&self, recipient: u32) -> Vec<&MultiworldEntry> {
+        self.progress
+            .checked
+            .iter()
+            .filter_map(|&(world, flag)| self.manifest.find(world, flag))
+            .filter(|entry| entry.recipient == recipient)
+            .collect()
+    }
+}
+
+/// マルチワールドの各ワールドのフォルダに `relay.json` として置く、リレーに参加するための情報
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct RelayWorld {
+    pub world: u32,
+    pub fingerprint: String,
+    /// 他のプレイヤー宛てのアイテムを置いたスポットのフラグ
+    pub location_flags: Vec<u16>,
+}
+
+impl RelayWorld {
+    pub fn new(manifest: &MultiworldManifest, world: u32) -> Self {
+        Self {
+            world,
+            fingerprint: manifest.fingerprints[world as usize].clone(),
+            location_flags: manifest
+                .entries
+                .iter()
+                .filter(|entry| entry.world == world)
+                .map(|entry| entry.location_flag)
+                .collect(),
+        }
+    }
+}
+
+/// ランチャー側のリレーとの接続
+pub struct RelayClient<T> {
+    framed: Framed<T>,
+    world: u32,
+}
+
+impl<T: Read + Write> RelayClient<T> {
+    pub fn connect(
+        stream: T,
+        randomizer_version: &str,
+        world: u32,
+        fingerprint: &str,
+        received: u32,
+    ) -> Result<Self, ProtocolError> {
+        let mut framed = Framed::new(stream);
+        framed.send(&RelayClientMessage::Hello(Hello::new(randomizer_version)))?;
+        match framed.recv()? {
+            RelayServerMessage::Hello(hello) if hello.protocol_version != PROTOCOL_VERSION => {
+                return Err(ProtocolError::VersionMismatch {
+                    ours: PROTOCOL_VERSION,
+                    theirs: hello.protocol_version,
+                });
+            }
+            RelayServerMessage::Hello(_) => {}
+            RelayServerMessage::Error { message } => return Err(ProtocolError::Remote(message)),
+            msg => return Err(ProtocolError::Unexpected(format!("{msg:?}"))),
+        }
+        framed.send(&RelayClientMessage::Join {
+            world,
+            fingerprint: fingerprint.to_owned(),
+            received,
+        })?;
+        match framed.recv()? {
+            RelayServerMessage::Joined { world: joined } if joined == world => {}
+            RelayServerMessage::Error { message } => return Err(ProtocolError::Remote(message)),
+            msg => return Err(ProtocolError::Unexpected(format!("{msg:?}"))),
+        }
+        Ok(Self { framed, world })
+    }
+
+    pub fn world(&self) -> u32 {
+        self.world
+    }
+
+    pub fn check_location(&mut self, location_flag: u16) -> Result<(), ProtocolError> {
+        self.framed
+            .send(&RelayClientMessage::LocationChecked { location_flag })
+    }
+
+    /// 次のアイテムが届くまでブロックする
+    pub fn recv_item(&mut self) -> Result<(u32, MultiworldEntry), ProtocolError> {
+        match self.framed.recv()? {
+            RelayServerMessage::ReceiveItem { index, entry } => Ok((index, entry)),
+            RelayServerMessage::Error { message } => Err(ProtocolError::Remote(message)),
+            msg => Err(ProtocolError::Unexpected(format!("{msg:?}"))),
+        }
+    }
+}
+
+impl RelayClient<TcpStream> {
+    /// 受信を別のスレッドで待つために使う。`Framed` は読み残しを持たないので分けられる
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            framed: Framed::new(self.framed.get_ref().try_clone()?),
+            world: self.world,
+        })
+    }
+}
+
+/// ランチャーでトラッカーとリレーを繋ぐ。
+/// 受け取り済みかはゲームのフラグで判断するので、接続のたびに最初から送ってもらう
+pub struct RelayBridge<T> {
+    /// 切れたら `None` にして、トラッカーだけ続ける
+    relay: Option<RelayClient<T>>,
+    unreported: BTreeSet<u16>,
+    received: Receiver<MultiworldEntry>,
+    /// ゲームのフラグに反映されるまでの間に同じアイテムを与えないよう覚えておく
+    given: BTreeSet<u16>,
+}
+
+impl RelayBridge<TcpStream> {
+    /// 届いたアイテムは別のスレッドで受け取り、次の問い合わせのときに与える
+    pub fn connect(
+        addr: impl ToSocketAddrs,
+        randomizer_version: &str,
+        world: &RelayWorld,
+    ) -> Result<Self, ProtocolError> {
+        let stream = TcpStream::connect(addr)?;
+        let relay = RelayClient::connect(
+            stream,
+            randomizer_version,
+            world.world,
+            &world.fingerprint,
+            0,
+        )?;
+        let mut reader = relay.try_clone()?;
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            loop {
+                match reader.recv_item() {
+                    Ok((_, entry)) => {
+                        if tx.send(entry).is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) if err.is_fatal() => {
+                        tracing::warn!("Relay closed: {err}");
+                        return;
+                    }
+                    Err(err) => tracing::warn!("Failed to receive an item: {err}"),
+                }
+            }
+        });
+        Ok(Self::new(relay, world, rx))
+    }
+}
+
+impl<T: Read + Write> RelayBridge<T> {
+    pub fn new(
+        relay: RelayClient<T>,
+        world: &RelayWorld,
+        received: Receiver<MultiworldEntry>,
+    ) -> Self {
+        Self {
+            relay: Some(relay),
+            unreported: world.location_flags.iter().copied().collect(),
+            received,
+            given: BTreeSet::new(),
+        }
+    }
+
+    /// 最初のスナップショットが届くまでは何もしない
+    fn update<S: Read + Write>(
+        &mut self,
+        session: &mut LauncherSession<S>,
+        tracker: &Tracker,
+    ) -> Result<(), ProtocolError> {
+        let Some(snapshot) = tracker.snapshot() else {
+            return Ok(());
+        };
+        self.report(&snapshot.flags);
+        self.give_received(session, &snapshot.flags)
+    }
+
+    fn report(&mut self, flags: &Flags) {
+        let Some(relay) = &mut self.relay else {
+            return;
+        };
+        let checked: Vec<_> = self
+            .unreported
+            .iter()
+            .copied()
+            .filter(|&flag| flags.get(flag))
+            .collect();
+        for flag in checked {
+            if let Err(err) = relay.check_location(flag) {
+                tracing::warn!("Relay closed: {err}");
+                self.relay = None;
+                return;
+            }
+            self.unreported.remove(&flag);
+        }
+    }
+
+    fn give_received<S: Read + Write>(
+        &mut self,
+        session: &mut LauncherSession<S>,
+        flags: &Flags,
+    ) -> Result<(), ProtocolError> {
+        for entry in self.received.try_iter() {
+            if flags.get(entry.item_flag) || !self.given.insert(entry.item_flag) {
+                continue;
+            }
+            let command = Command::GiveItem {
+                item: entry.item,
+                flag: entry.item_flag,
+            };
+            if let Err(err) = session.request(command) {
+                self.given.remove(&entry.item_flag);
+                if err.is_fatal() {
+                    return Err(err);
+                }
+                tracing::warn!("Failed to give {}: {err}", entry.location);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `poll_tracker_until_closed` に加えて、取得したスポットをリレーに報告し、届いたアイテムを与える
+pub fn poll_tracker_with_relay_until_closed<T: Read + Write, R: Read + Write>(
+    session: &mut LauncherSession<T>,
+    tracker: &mut Tracker,
+    bridge: &mut RelayBridge<R>,
+    interval: Duration,
+    mut on_update: impl FnMut(TrackerUpdate),
+) -> ProtocolError {
+    poll_tracker_with(session, tracker, interval, |session, tracker, update| {
+        if let Some(update) = update {
+            on_update(update);
+        }
+        bridge.update(session, tracker)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ipc::MemoryStream,
+        lmo::{Equipment, GameItem},
+        protocol::{Event, HookSession},
+        tracker::{GameSnapshot, LocationManifest},
+    };
+
+    use super::*;
+
+    fn entry(world: u32, location_flag: u16, recipient: u32) -> MultiworldEntry {
+        MultiworldEntry {
+            world,
+            location: format!("chest{location_flag}"),
+            location_flag,
+            recipient,
+            item: GameItem::Equipment(Equipment::Feather),
+            item_flag: 100,
+        }
+    }
+
+    #[test]
+    fn test_relay_state() {
+        let fingerprints = vec!["a".to_owned(), "b".to_owned()];
+        let manifest = MultiworldManifest::new(
+            fingerprints.clone(),
+            vec![entry(0, 6000, 1), entry(1, 6000, 0), entry(0, 6001, 1)],
+        );
+        let mut state = RelayState::new(manifest.clone(), None).unwrap();
+        assert!(state.validate_join(1, "b").is_ok());
+        assert!(state.validate_join(1, "a").is_err());
+        assert!(state.validate_join(2, "c").is_err());
+
+        assert_eq!(state.check(0, 6001).map(|x| x.0), Some(0));
+        assert_eq!(state.check(0, 6001), None);
+        assert_eq!(state.check(0, 7000), None);
+        assert_eq!(state.check(0, 6000).map(|x| x.0), Some(1));
+        let received: Vec<_> = state.received(1).iter().map(|x| x.location_flag).collect();
+        assert_eq!(received, [6001, 6000]);
+        assert!(state.received(0).is_empty());
+
+        let progress = state.progress().clone();
+        let state = RelayState::new(manifest.clone(), Some(progress.clone())).unwrap();
+        assert_eq!(state.received(1).len(), 2);
+        let other = MultiworldManifest::new(vec!["x".to_owned()], manifest.entries);
+        assert!(RelayState::new(other, Some(progress)).is_err());
+    }
+
+    #[test]
+    fn test_poll_tracker_with_relay() {
+        let mut received_entry = entry(1, 6000, 0);
+        received_entry.item_flag = 300;
+        let mut new_entry = entry(1, 6001, 0);
+        new_entry.item = GameItem::Equipment(Equipment::GrappleClaw);
+        new_entry.item_flag = 301;
+        let manifest = MultiworldManifest::new(
+            vec!["a".to_owned(), "b".to_owned()],
+            vec![entry(0, 200, 1), entry(0, 201, 1), received_entry.clone()],
+        );
+        let world = RelayWorld::new(&manifest, 0);
+        assert_eq!(world.location_flags, [200, 201]);
+
+        let (launcher, hook) = MemoryStream::pair();
+        let hook = thread::spawn(move || -> Result<Vec<Command>, ProtocolError> {
+            let mut session = HookSession::accept(hook, "1.0.0")?;
+            let mut snapshot = GameSnapshot {
+                flags: Flags::new([0; 1000]),
+                items: [0; 60],
+                main_weapon: 0,
+                sub_weapon: 0,
+                coin: 0,
+                field: 1,
+                room: 0,
+            };
+            snapshot.flags.set(200, true);
+            snapshot.flags.set(300, true);
+            let mut commands = Vec::new();
+            for _ in 0..2 {
+                let (id, command) = session.recv_request()?;
+                if matches!(command, Command::PollTracker) {
+                    session.push_event(Event::Snapshot(Box::new(snapshot.clone())))?;
+                }
+                session.ack(id)?;
+                commands.push(command);
+            }
+            Ok(commands)
+        });
+
+        let (relay, mut server) = MemoryStream::pair();
+        let relay = RelayClient {
+            framed: Framed::new(relay),
+            world: 0,
+        };
+        let (tx, rx) = channel();
+        // 受け取り済みのフラグが立っているものと、同じものが2回届いたもの
+        tx.send(received_entry).unwrap();
+        tx.send(new_entry.clone()).unwrap();
+        tx.send(new_entry).unwrap();
+        let mut bridge = RelayBridge::new(relay, &world, rx);
+
+        let (mut session, _) = LauncherSession::connect(launcher, "1.0.0").unwrap();
+        let mut tracker = Tracker::new(LocationManifest::default());
+        let err = poll_tracker_with_relay_until_closed(
+            &mut session,
+            &mut tracker,
+            &mut bridge,
+            Duration::ZERO,
+            |_| {},
+        );
+        assert!(err.is_fatal());
+
+        let commands = hook.join().unwrap().unwrap();
+        assert!(matches!(commands[0], Command::PollTracker));
+        assert!(matches!(
+            commands[1],
+            Command::GiveItem {
+                item: GameItem::Equipment(Equipment::GrappleClaw),
+                flag: 301
+            }
+        ));
+        drop(bridge);
+        let mut server = Framed::new(&mut server);
+        assert!(matches!(
+            server.recv().unwrap(),
+            RelayClientMessage::LocationChecked { location_flag: 200 }
+        ));
+        assert!(server.recv::<RelayClientMessage>().is_err());
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    collections::BTreeSet,
+    io::{Read, Write},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    lmo::Flags,
+    protocol::{Event, LauncherSession, ProtocolError},
+};
+
+/// ゲームのメモリから読み取ったトラッカー用の状態
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct GameSnapshot {
+    pub flags: Flags,
+    #[serde(with = "serde_big_array::BigArray")]
+    pub items: [u8; 60],
+    pub main_weapon: u8,
+    pub sub_weapon: u8,
+    pub coin: u16,
+    pub field: u32,
+    pub room: u32,
+}
+
+/// 前回のスナップショットからの差分。値が変わらなかったものは `None`
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct SnapshotDelta {
+    pub set_flags: Vec<u16>,
+    pub cleared_flags: Vec<u16>,
+    /// (index, value)
+    pub items: Vec<(u8, u8)>,
+    pub main_weapon: Option<u8>,
+    pub sub_weapon: Option<u8>,
+    pub coin: Option<u16>,
+    /// (field, room)
+    pub position: Option<(u32, u32)>,
+}
+
+fn changed<T: PartialEq + Copy>(old: T, new: T) -> Option<T> {
+    (old != new).then_some(new)
+}
+
+impl SnapshotDelta {
+    pub fn new(old: &GameSnapshot, new: &GameSnapshot) -> Self {
+        let (set_flags, cleared_flags) = old
+            .flags
+            .diff(&new.flags)
+            .into_iter()
+            .partition(|&idx| new.flags.get(idx));
+        Self {
+            set_flags,
+            cleared_flags,
+            items: old
+                .items
+                .iter()
+                .zip(&new.items)
+                .enumerate()
+                .filter(|(_, (old, new))| old != new)
+                .map(|(i, (_, &new))| (i as u8, new))
+                .collect(),
+            main_weapon: changed(old.main_weapon, new.main_weapon),
+            sub_weapon: changed(old.sub_weapon, new.sub_weapon),
+            coin: changed(old.coin, new.coin),
+            position: changed((old.field, old.room), (new.field, new.room)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn apply(&self, snapshot: &mut GameSnapshot) {
+        for &idx in &self.set_flags {
+            snapshot.flags.set(idx, true);
+        }
+        for &idx in &self.cleared_flags {
+            snapshot.flags.set(idx, false);
+        }
+        for &(i, value) in &self.items {
+            snapshot.items[i as usize] = value;
+        }
+        snapshot.main_weapon = self.main_weapon.unwrap_or(snapshot.main_weapon);
+        snapshot.sub_weapon = self.sub_weapon.unwrap_or(snapshot.sub_weapon);
+        snapshot.coin = self.coin.unwrap_or(snapshot.coin);
+        if let Some((field, room)) = self.position {
+            snapshot.field = field;
+            snapshot.room = room;
+        }
+    }
+}
+
+/// 生成したワールドのスポットと、取得時に立つフラグ
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct TrackedLocation {
+    pub name: String,
+    /// どれか1つが立っていれば取得済み
+    pub flags: Vec<u16>,
+}
+
+impl TrackedLocation {
+    pub fn is_checked(&self, flags: &Flags) -> bool {
+        self.flags.iter().any(|&flag| flags.get(flag))
+    }
+}
+
+/// ランダマイザーが `script.dat` と一緒に書き出す、スポットとフラグの対応表
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct LocationManifest {
+    pub version: u32,
+    pub locations: Vec<TrackedLocation>,
+}
+
+impl LocationManifest {
+    /// 互換性のない変更をしたら上げる
+    pub const VERSION: u32 = 1;
+
+    pub fn new(locations: Vec<TrackedLocation>) -> Self {
+        Self {
+            version: Self::VERSION,
+            locations,
+        }
+    }
+
+    pub fn checked_locations<'a>(&'a self, flags: &Flags) -> Vec<&'a str> {
+        self.locations
+            .iter()
+            .filter(|location| location.is_checked(flags))
+            .map(|location| location.name.as_str())
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackerUpdate {
+    pub newly_checked: Vec<String>,
+    /// (field, room)
+    pub position: Option<(u32, u32)>,
+}
+
+/// フックから届くスナップショットを積み上げ、取得済みのスポットを求める
+pub struct Tracker {
+    locations: Vec<TrackedLocation>,
+    snapshot: Option<GameSnapshot>,
+    checked: BTreeSet<usize>,
+}
+
+impl Tracker {
+    pub fn new(manifest: LocationManifest) -> Self {
+        Self {
+            locations: manifest.locations,
+            snapshot: None,
+            checked: BTreeSet::new(),
+        }
+    }
+
+    pub fn snapshot(&self) -> Option<&GameSnapshot> {
+        self.snapshot.as_ref()
+    }
+
+    pub fn checked_locations(&self) -> impl Iterator<Item = &str> {
+        self.checked
+            .iter()
+            .map(|&i| self.locations[i].name.as_str())
+    }
+
+    /// 最初のスナップショットより前に届いた差分は適用できないので `None`
+    pub fn apply(&mut self, event: &Event) -> Option<TrackerUpdate> {
+        let position = match event {
+            Event::Snapshot(snapshot) => {
+                self.snapshot = Some((**snapshot).clone());
+                Some((snapshot.field, snapshot.room))
+            }
+            Event::SnapshotDelta(delta) => {
+                delta.apply(self.snapshot.as_mut()?);
+                delta.position
+            }
+            Event::FlagSet { flag } => {
+                // 範囲外のフラグは壊れた通知なので無視する
+                if *flag < Flags::COUNT {
+                    self.snapshot.as_mut()?.flags.set(*flag, true);
+                }
+                None
+            }
+        };
+        let flags = &self.snapshot.as_ref()?.flags;
+        let newly_checked = self
+            .locations
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !self.checked.contains(i))
+            .filter(|(_, location)| location.is_checked(flags))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        self.checked.extend(&newly_checked);
+        Some(TrackerUpdate {
+            newly_checked: newly_checked
+                .into_iter()
+                .map(|i| self.locations[i].name.clone())
+                .collect(),
+            position,
+        })
+    }
+}
+
+/// 接続が切れるまで `interval` ごとに `PollTracker` を送り、変化があれば `on_update` に渡す。
+/// 接続を閉じたエラーを返す
+pub fn poll_tracker_until_closed<T: Read + Write>(
+    session: &mut LauncherSession<T>,
+    tracker: &mut Tracker,
+    interval: Duration,
+    mut on_update: impl FnMut(TrackerUpdate),
+) -> ProtocolError {
+    poll_tracker_with(session, tracker, interval, |_, _, update| {
+        if let Some(update) = update {
+            on_update(update);
+        }
+        Ok(())
+    })
+}
+
+/// `after_poll` は問い合わせのたびに、変化がなければ `None` で呼ばれ、同じ接続で別のコマンドを送れる
+pub(crate) fn poll_tracker_with<T: Read + Write>(
+    session: &mut LauncherSession<T>,
+    tracker: &mut Tracker,
+    interval: Duration,
+    mut after_poll: impl FnMut(
+        &mut LauncherSession<T>,
+        &Tracker,
+        Option<TrackerUpdate>,
+    ) -> Result<(), ProtocolError>,
+) -> ProtocolError {
+    loop {
+        let update = match session.poll_tracker() {
+            Ok(event) => tracker
+                .apply(&event)
+                .filter(|x| !x.newly_checked.is_empty() || x.position.is_some()),
+            Err(err) if err.is_fatal() => return err,
+            Err(err) => {
+                tracing::warn!("Failed to poll tracker: {err}");
+                None
+            }
+        };
+        match after_poll(session, tracker, update) {
+            Ok(()) => {}
+            Err(err) if err.is_fatal() => return err,
+            Err(err) => tracing::warn!("{err}"),
+        }
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Command, ipc::MemoryStream, protocol::HookSession};
+
+    use super::*;
+
+    fn snapshot() -> GameSnapshot {
+        GameSnapshot {
+            flags: Flags::new([0; 1000]),
+            items: [0; 60],
+            main_weapon: 0,
+            sub_weapon: 0,
+            coin: 0,
+            field: 1,
+            room: 0,
+        }
+    }
+
+    #[test]
+    fn test_checked_locations() {
+        let manifest = LocationManifest::new(vec![
+            TrackedLocation {
+                name: "Surface/Chest(feather)".to_owned(),
+                flags: vec![720],
+            },
+            TrackedLocation {
+                name: "Surface/Shop(a, b, c)[0]".to_owned(),
+                flags: vec![721],
+            },
+        ]);
+        let mut flags = Flags::new([0; 1000]);
+        assert!(manifest.checked_locations(&flags).is_empty());
+        flags.set(721, true);
+        assert_eq!(
+            manifest.checked_locations(&flags),
+            ["Surface/Shop(a, b, c)[0]"]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_delta() {
+        let old = snapshot();
+        let mut new = old.clone();
+        new.flags.set(100, true);
+        new.items[3] = 1;
+        new.coin = 30;
+        new.room = 2;
+        let delta = SnapshotDelta::new(&old, &new);
+        assert_eq!(delta.set_flags, [100]);
+        assert_eq!(delta.items, [(3, 1)]);
+        assert_eq!(delta.position, Some((1, 2)));
+        assert_eq!(delta.main_weapon, None);
+
+        let mut applied = old.clone();
+        delta.apply(&mut applied);
+        assert_eq!(applied, new);
+        assert!(SnapshotDelta::new(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_tracker_with_recorded_snapshots() {
+        let mut first = snapshot();
+        first.flags.set(200, true);
+        let mut second = first.clone();
+        second.flags.set(201, true);
+        second.room = 3;
+        // フックが送るのと同じ形式で記録したもの
+        let recorded: Vec<Vec<u8>> = [
+            Event::Snapshot(Box::new(first.clone())),
+            Event::SnapshotDelta(SnapshotDelta::new(&first, &second)),
+        ]
+        .iter()
+        .map(|x| rmp_serde::to_vec_named(x).unwrap())
+        .collect();
+
+        let mut tracker = Tracker::new(LocationManifest::new(vec![
+            TrackedLocation {
+                name: "chest:surface:feather".to_owned(),
+                flags: vec![200],
+            },
+            TrackedLocation {
+                name: "seal:surface:origin".to_owned(),
+                flags: vec![201, 202],
+            },
+            TrackedLocation {
+                name: "rom:gameMaster".to_owned(),
+                flags: vec![203],
+            },
+        ]));
+        let delta = SnapshotDelta::default();
+        assert_eq!(tracker.apply(&Event::SnapshotDelta(delta)), None);
+
+        let updates: Vec<_> = recorded
+            .iter()
+            .map(|x| tracker.apply(&rmp_serde::from_slice(x).unwrap()).unwrap())
+            .collect();
+        assert_eq!(updates[0].newly_checked, ["chest:surface:feather"]);
+        assert_eq!(updates[1].newly_checked, ["seal:surface:origin"]);
+        assert_eq!(updates[1].position, Some((1, 3)));
+        assert_eq!(
+            tracker.checked_locations().collect::<Vec<_>>(),
+            ["chest:surface:feather", "seal:surface:origin"]
+        );
+    }
+
+    #[test]
+    fn test_tracker_ignores_out_of_range_flag() {
+        let mut tracker = Tracker::new(LocationManifest::new(vec![TrackedLocation {
+            name: "chest:surface:feather".to_owned(),
+            flags: vec![200],
+        }]));
+        tracker.apply(&Event::Snapshot(Box::new(snapshot())));
+        let update = tracker.apply(&Event::FlagSet { flag: Flags::COUNT });
+        assert!(update.unwrap().newly_checked.is_empty());
+        let update = tracker.apply(&Event::FlagSet { flag: 200 });
+        assert_eq!(update.unwrap().newly_checked, ["chest:surface:feather"]);
+    }
+
+    #[test]
+    fn test_poll_tracker_until_closed() {
+        let (launcher, hook) = MemoryStream::pair();
+        let hook = thread::spawn(move || -> Result<(), ProtocolError> {
+            let mut session = HookSession::accept(hook, "1.0.0")?;
+            let mut first = snapshot();
+            first.flags.set(200, true);
+            let second = first.clone();
+            let events = [
+                Event::Snapshot(Box::new(first.clone())),
+                Event::SnapshotDelta(SnapshotDelta::new(&first, &second)),
+            ];
+            for event in events {
+                let (id, command) = session.recv_request()?;
+                assert!(matches!(command, Command::PollTracker));
+                session.push_event(event)?;
+                session.ack(id)?;
+            }
+            Ok(())
+        });
+
+        let (mut session, _) = LauncherSession::connect(launcher, "1.0.0").unwrap();
+        let mut tracker = Tracker::new(LocationManifest::new(vec![TrackedLocation {
+            name: "chest:surface:feather".to_owned(),
+            flags: vec![200],
+        }]));
+        let mut updates = Vec::new();
+        let err = poll_tracker_until_closed(&mut session, &mut tracker, Duration::ZERO, |x| {
+            updates.push(x)
+        });
+        assert!(err.is_fatal());
+        // 変化のない差分は通知しない
+        assert_eq!(
+            updates,
+            [TrackerUpdate {
+                newly_checked: vec!["chest:surface:feather".to_owned()],
+                position: Some((1, 0)),
+            }]
+        );
+        hook.join().unwrap().unwrap();
+    }
+}
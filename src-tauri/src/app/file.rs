@@ -1,5 +1,6 @@
 use anyhow::Result;
 use futures::future::join_all;
+use lmorandomizer_shared::tracker::LocationManifest;
 use log::info;
 use semver::Version;
 use std::{
@@ -12,7 +13,10 @@ use tokio::{
     io::{self, AsyncReadExt, AsyncWriteExt},
 };
 
-use crate::{dataset::game_structure::GameStructure, randomizer::SpoilerLog};
+use crate::{
+    dataset::game_structure::GameStructure,
+    randomizer::{Fingerprint, SealedSpoilerLog, SpoilerLock, SpoilerLog},
+};
 
 pub async fn read_file(path: &Path) -> io::Result<Vec<u8>> {
     let mut file = File::open(path)
@@ -55,6 +59,7 @@ async fn read_game_structure_files_internal(
         "res/17_Twin_Labyrinths_Right.yml",
         "res/18_Dimensional_Corridor.yml",
         "res/19_True_Shrine_of_the_Mother.yml",
+        "res/22_Surface_Night.yml",
     ];
     #[allow(clippy::redundant_closure)]
     let futures: Vec<_> = file_paths
@@ -90,16 +95,33 @@ pub async fn read_game_structure_files_debug() -> Result<GameStructure> {
     read_game_structure_files_internal(|file_path| Ok(PathBuf::from(file_path))).await
 }
 
+/// `lock` がある場合は `path` の拡張子を `sealed` にして封印したものだけを書く
 pub async fn write_spoiler_log(
     path: &Path,
     version: &Version,
     seed: &str,
+    fingerprint: &Fingerprint,
     spoiler_log: &SpoilerLog,
+    lock: Option<&SpoilerLock>,
 ) -> io::Result<()> {
-    info!("Writing file: {:?}", path);
-    let header = format!("version = v{version}\nseed = {seed}\n\n");
-    let mut file = File::create(path).await?;
-    file.write_all(header.as_bytes()).await?;
-    file.write_all(spoiler_log.to_string().as_bytes()).await?;
-    Ok(())
+    let header = format!("version = v{version}\nseed = {seed}\nfingerprint = {fingerprint}\n\n");
+    let text = format!("{header}{spoiler_log}");
+    if let Some(lock) = lock {
+        let sealed = SealedSpoilerLog::seal(&text, lock, fingerprint.to_string())
+            .map_err(io::Error::other)?;
+        return write_file(&path.with_extension("sealed"), &sealed.to_json()).await;
+    }
+    write_file(path, text.as_bytes()).await
+}
+
+/// フラグの番号からアイテムが分かるので、スポイラーログを封印する場合は書かない
+pub async fn write_location_manifest(
+    path: &Path,
+    manifest: &LocationManifest,
+    lock: Option<&SpoilerLock>,
+) -> io::Result<()> {
+    if lock.is_some() {
+        return Ok(());
+    }
+    write_file(path, &serde_json::to_vec_pretty(manifest).unwrap()).await
 }
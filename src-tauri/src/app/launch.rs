@@ -1,111 +1,264 @@
 use anyhow::{Context, Result, bail};
+use lmorandomizer_shared::{
+    ipc::IpcStream,
+    protocol::LauncherSession,
+    relay::{DEFAULT_RELAY_PORT, RelayBridge, RelayWorld, poll_tracker_with_relay_until_closed},
+    tracker::{LocationManifest, Tracker, TrackerUpdate, poll_tracker_until_closed},
+};
 use log::error;
 use semver::Version;
+use sha3::Digest;
 use smol::fs;
-use std::path::{Path, PathBuf};
-use tauri::{AppHandle, Manager};
+use std::{
+    net::TcpStream,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter};
 use tokio::io::{self};
 
 use crate::{
-    app::file::{read_file, read_game_structure_files, write_file, write_spoiler_log},
+    app::{
+        file::{
+            read_file, read_game_structure_files, write_file, write_location_manifest,
+            write_spoiler_log,
+        },
+        now_millis,
+        saves::{backup_saves, find_previous_worlds},
+        worlds_dir,
+    },
     launcher,
     randomizer::{RandomizeOptions, randomize},
-    script::file::scriptconverter::is_valid_script_dat,
+    script::file::scriptconverter::{is_valid_script_dat, script_dat_hash},
 };
 
+const TRACKER_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// fingerprint と、セーブを移せる同じシードの他のワールドとそのバックアップを返す
 pub async fn launch(
     handle: AppHandle,
     install_directory: String,
     options: RandomizeOptions,
-) -> Result<()> {
+) -> Result<(String, Vec<(String, Option<String>)>)> {
     let install_directory = PathBuf::from(install_directory);
     log::trace!("{:?}", install_directory);
 
     let version = &handle.package_info().version;
-    let dst_dir_path = {
-        let mut path = handle.path().app_data_dir().unwrap();
-        path.push("worlds");
-        path.push(to_dir_name(version, &options));
-        path
+    let worlds_dir = worlds_dir(&handle);
+    let src_file_path = install_directory.join("data").join("script.dat");
+    // 改造済みの script.dat は内容が変わったら別のワールドにする
+    let modded_script_hash = if options.allow_modded_script {
+        let working = read_valid_file(&src_file_path, true).await?;
+        Some(script_dat_hash(&working))
+    } else {
+        None
     };
+    let dir_name = to_dir_name(version, &options, modded_script_hash.as_deref());
+    let dst_dir_path = worlds_dir.join(&dir_name);
     let dst_file_path = dst_dir_path.join("script.dat");
     let spoiler_log_file_path = dst_dir_path.join("spoilerlog.txt");
+    let fingerprint_file_path = dst_dir_path.join("fingerprint.txt");
 
-    let found = match fs::metadata(&dst_file_path).await {
-        Ok(_) => true,
-        Err(err) if err.kind() == io::ErrorKind::NotFound => false,
-        Err(err) => return Err(err.into()),
-    };
+    // fingerprint.txt が無い古いワールドは fingerprint を出せないので作り直す
+    let found = exists(&dst_file_path).await? && exists(&fingerprint_file_path).await?;
+    let mut previous_worlds = Vec::new();
     if !found {
+        // 新しいワールドになった場合に、以前のセーブを失わないよう退避しておく
+        let now = now_millis();
+        for world in find_previous_worlds(&worlds_dir, &dir_name).await? {
+            let backup = backup_saves(&worlds_dir, &world, now).await?;
+            previous_worlds.push((world, backup));
+        }
         let _ = fs::create_dir_all(&dst_dir_path).await;
 
         create_randomized_script_dat(
             &handle,
-            install_directory.clone(),
+            &src_file_path,
             options,
             version,
             &dst_file_path,
             &spoiler_log_file_path,
+            &fingerprint_file_path,
         )
         .await?;
     }
 
-    match launcher::launch(&install_directory, "lamulana.exe", dst_dir_path) {
-        Ok(_) => (),
+    // 生成済みのワールドを使う場合に備えてファイルから読む
+    let fingerprint = fs::read_to_string(&fingerprint_file_path)
+        .await
+        .context("Failed to read fingerprint")?;
+    let locations = read_location_manifest(&dst_dir_path.join("locations.json")).await?;
+    let session = match launcher::launch(&install_directory, "lamulana.exe", dst_dir_path) {
+        Ok(session) => session,
+        Err(err) => bail!("Failed to launch the game: {err}"),
+    };
+    spawn_tracker(handle, session, locations, None);
+
+    Ok((fingerprint, previous_worlds))
+}
+
+/// `relay_address` を省略したら同じ PC のリレーに繋ぐ。fingerprint を返す
+pub async fn launch_multiworld(
+    handle: AppHandle,
+    install_directory: String,
+    world_directory: String,
+    relay_address: Option<String>,
+) -> Result<String> {
+    let install_directory = PathBuf::from(install_directory);
+    let world_directory = PathBuf::from(world_directory);
+    let relay_world = read_file(&world_directory.join("relay.json"))
+        .await
+        .context("Failed to read relay.json")?;
+    let relay_world: RelayWorld = serde_json::from_slice(&relay_world)?;
+    // スポイラーログを封印したワールドでもリレーは使えるので、トラッカーは空にする
+    let locations = read_location_manifest(&world_directory.join("locations.json"))
+        .await?
+        .unwrap_or_default();
+    // リレーに繋げなければゲームを起動しない
+    let relay_address = relay_address.unwrap_or_else(|| format!("127.0.0.1:{DEFAULT_RELAY_PORT}"));
+    let relay = RelayBridge::connect(&relay_address, env!("CARGO_PKG_VERSION"), &relay_world)
+        .with_context(|| format!("Failed to join the relay at {relay_address}"))?;
+    let session = match launcher::launch(&install_directory, "lamulana.exe", world_directory) {
+        Ok(session) => session,
         Err(err) => bail!("Failed to launch the game: {err}"),
+    };
+    spawn_tracker(handle, session, Some(locations), Some(relay));
+
+    Ok(relay_world.fingerprint)
+}
+
+/// スポイラーログを封印したワールドには対応表がない
+async fn read_location_manifest(path: &Path) -> Result<Option<LocationManifest>> {
+    if !exists(path).await? {
+        return Ok(None);
+    }
+    let json = read_file(path)
+        .await
+        .context("Failed to read location manifest")?;
+    let manifest: LocationManifest = serde_json::from_slice(&json)?;
+    if manifest.version != LocationManifest::VERSION {
+        bail!(
+            "Unsupported location manifest version: {}",
+            manifest.version
+        );
     }
+    Ok(Some(manifest))
+}
 
-    Ok(())
+/// ゲームが終わるまでフックに問い合わせ、取得したスポットと画面の変化を `tracker-update` で通知する。
+/// `relay` があれば取得したスポットを報告し、届いたアイテムをゲームに渡す
+fn spawn_tracker(
+    handle: AppHandle,
+    mut session: LauncherSession<IpcStream>,
+    locations: Option<LocationManifest>,
+    relay: Option<RelayBridge<TcpStream>>,
+) {
+    let Some(locations) = locations else {
+        log::info!("Tracker is disabled because the world has no location manifest");
+        return;
+    };
+    thread::spawn(move || {
+        let mut tracker = Tracker::new(locations);
+        let on_update = |update: TrackerUpdate| {
+            if let Err(err) = handle.emit("tracker-update", &update) {
+                error!("Failed to emit tracker update: {err}");
+            }
+        };
+        let err = match relay {
+            Some(mut relay) => poll_tracker_with_relay_until_closed(
+                &mut session,
+                &mut tracker,
+                &mut relay,
+                TRACKER_POLL_INTERVAL,
+                on_update,
+            ),
+            None => poll_tracker_until_closed(
+                &mut session,
+                &mut tracker,
+                TRACKER_POLL_INTERVAL,
+                on_update,
+            ),
+        };
+        log::debug!("Tracker stopped: {err}");
+    });
+}
+
+async fn exists(path: &Path) -> Result<bool> {
+    match fs::metadata(path).await {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
 }
 
 async fn create_randomized_script_dat(
     handle: &AppHandle,
-    mut install_directory: PathBuf,
+    src_file_path: &Path,
     options: RandomizeOptions,
     version: &Version,
     dst_file_path: &Path,
     spoiler_log_file_path: &Path,
+    fingerprint_file_path: &Path,
 ) -> Result<()> {
-    let src_file_path = {
-        install_directory.extend(["data", "script.dat"]);
-        install_directory
-    };
-    let working = read_valid_file(&src_file_path).await?;
+    let working = read_valid_file(src_file_path, options.allow_modded_script).await?;
     let game_structure = match read_game_structure_files(handle).await {
         Ok(ok) => ok,
         Err(err) => bail!("Failed to read game structure files: {}", err),
     };
 
-    let (randomized, spoiler_log) = match randomize(&working, game_structure, &options) {
-        Ok(randomized) => randomized,
-        Err(e) => {
-            error!("{:?}", e);
-            bail!("Randomization failed: {}", e);
-        }
-    };
+    let (randomized, spoiler_log, fingerprint, locations) =
+        match randomize(&working, game_structure, &options) {
+            Ok(randomized) => randomized,
+            Err(e) => {
+                error!("{:?}", e);
+                bail!("Randomization failed: {}", e);
+            }
+        };
 
+    // script.dat の有無で生成済みか判定するので、先に書く
+    if let Err(err) = write_file(fingerprint_file_path, fingerprint.to_string().as_bytes()).await {
+        bail!("Failed to write fingerprint: {err}");
+    }
     if let Err(err) = write_file(dst_file_path, &randomized).await {
         bail!("Failed to write randomized script.dat: {err}");
     }
-    if let Err(err) =
-        write_spoiler_log(spoiler_log_file_path, version, &options.seed, &spoiler_log).await
+    if let Err(err) = write_spoiler_log(
+        spoiler_log_file_path,
+        version,
+        &options.seed,
+        &fingerprint,
+        &spoiler_log,
+        options.spoiler_lock.as_ref(),
+    )
+    .await
     {
         bail!("Failed to write spoiler log: {err}");
     }
+    let locations_file_path = dst_file_path.with_file_name("locations.json");
+    let lock = options.spoiler_lock.as_ref();
+    if let Err(err) = write_location_manifest(&locations_file_path, &locations, lock).await {
+        bail!("Failed to write location manifest: {err}");
+    }
     Ok(())
 }
 
-async fn read_valid_file(src_file_path: &Path) -> Result<Vec<u8>> {
+/// `allow_modded` の場合は randomize 側で構造を検証する
+async fn read_valid_file(src_file_path: &Path, allow_modded: bool) -> Result<Vec<u8>> {
     let working = read_file(src_file_path)
         .await
         .context("Unable to open script.dat.")?;
-    if !is_valid_script_dat(&working) {
+    if !allow_modded && !is_valid_script_dat(&working) {
         bail!("Valid script.dat is not found. Please re-install La-Mulana.");
     }
     Ok(working)
 }
 
-fn to_dir_name(version: &Version, options: &RandomizeOptions) -> String {
+fn to_dir_name(
+    version: &Version,
+    options: &RandomizeOptions,
+    modded_script_hash: Option<&[u8]>,
+) -> String {
     let seed = options
         .seed
         .chars()
@@ -125,12 +278,57 @@ fn to_dir_name(version: &Version, options: &RandomizeOptions) -> String {
             }
         })
         .collect::<String>();
+    let default = RandomizeOptions::default();
+    let priority = if options.priority_items == default.priority_items
+        && options.early_fields == default.early_fields
+    {
+        String::new()
+    } else {
+        let fields = options
+            .early_fields
+            .iter()
+            .map(|x| (*x as u8).to_string())
+            .collect::<Vec<_>>();
+        // アイテム名には ':' が含まれ、数も多くなりうるのでハッシュにする
+        let json = serde_json::to_vec(&options.priority_items).unwrap();
+        let items = hex::encode(&sha3::Sha3_256::digest(json)[..4]);
+        format!(",{}-{}", items, fields.join("+"))
+    };
+    let patches = if options.patches.is_empty() {
+        String::new()
+    } else {
+        let json = serde_json::to_vec(&options.patches).unwrap();
+        format!(",{}", hex::encode(&sha3::Sha3_256::digest(json)[..4]))
+    };
+    let modded = modded_script_hash.map_or(String::new(), |hash| {
+        format!(",modded{}", hex::encode(&hash[..4]))
+    });
+    let map_placement = if options.map_placement == default.map_placement {
+        String::new()
+    } else {
+        format!(",map{}", options.map_placement as u8)
+    };
     format!(
-        "{},{},{}{}{}",
+        "{},{},{}{}{}{}{}{}{}{}{}",
         version,
         seed,
         options.absolutely_shuffle as u8,
         options.need_glitches as u8,
         options.shuffle_secret_roms as u8,
+        map_placement,
+        if options.shuffle_late_game_duplicates {
+            ",duplicates"
+        } else {
+            ""
+        },
+        priority,
+        patches,
+        modded,
+        // 封印していないスポイラーログが残っている場合に使い回さない
+        if options.spoiler_lock.is_some() {
+            ",sealed"
+        } else {
+            ""
+        },
     )
 }
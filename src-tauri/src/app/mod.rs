@@ -2,21 +2,37 @@ mod file;
 mod initial_data;
 #[cfg(target_os = "windows")]
 mod launch;
+mod saves;
 
+use lmorandomizer_shared::relay::RelayWorld;
 use log::error;
-use smol::io;
-use std::path::{Path, PathBuf};
+use semver::Version;
+use smol::{fs, io};
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tauri::{AppHandle, Manager};
 use tauri_plugin_opener::OpenerExt;
 use tauri_plugin_store::StoreExt;
 
 use crate::{
     app::{
-        file::{read_file, read_game_structure_files, write_file, write_spoiler_log},
+        file::{
+            read_file, read_game_structure_files, write_file, write_location_manifest,
+            write_spoiler_log,
+        },
         initial_data::InitialData,
+        saves::{WorldSaves, version_warning},
+    },
+    randomizer::{
+        MultiworldOptions, RandomizeOptions, SealedSpoilerLog, randomize, randomize_multiworld,
+        randomize_patch,
+    },
+    script::file::{
+        datpatch::apply_dat_patch,
+        scriptconverter::{is_valid_script_dat, parse_script_dat},
     },
-    randomizer::{RandomizeOptions, randomize},
-    script::file::scriptconverter::is_valid_script_dat,
 };
 
 #[cfg(test)]
@@ -86,7 +102,17 @@ pub async fn launch(
     options: RandomizeOptions,
 ) -> String {
     match launch::launch(handle, install_directory, options).await {
-        Ok(()) => "Succeeded.".to_owned(),
+        Ok((fingerprint, previous_worlds)) => {
+            let mut result = format!("Succeeded. Fingerprint: {fingerprint}");
+            for (world, backup) in previous_worlds {
+                result += &format!(" Found saves of {world} with the same seed");
+                if let Some(backup) = backup {
+                    result += &format!(" (backup: {backup})");
+                }
+                result += "; migrate them to continue in this world.";
+            }
+            result
+        }
         Err(err) => format!("{err}"),
     }
 }
@@ -101,6 +127,33 @@ pub async fn launch(
     unreachable!()
 }
 
+/// `create_multiworld` で書き出したワールドを起動し、リレーに繋ぐ
+#[cfg(target_os = "windows")]
+#[tauri::command]
+pub async fn launch_multiworld(
+    handle: AppHandle,
+    install_directory: String,
+    world_directory: String,
+    relay_address: Option<String>,
+) -> String {
+    match launch::launch_multiworld(handle, install_directory, world_directory, relay_address).await
+    {
+        Ok(fingerprint) => format!("Succeeded. Fingerprint: {fingerprint}"),
+        Err(err) => format!("{err}"),
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+#[tauri::command]
+pub async fn launch_multiworld(
+    _handle: AppHandle,
+    _install_directory: String,
+    _world_directory: String,
+    _relay_address: Option<String>,
+) -> String {
+    unreachable!()
+}
+
 #[tauri::command]
 pub async fn open_folder(app: AppHandle) {
     let dir = app.path().app_data_dir().unwrap();
@@ -119,48 +172,69 @@ pub async fn apply(
     let target_file_path = PathBuf::from(format!("{}/data/script.dat", install_directory));
     let backup_file_path = PathBuf::from(format!("{}/data/script.dat.bak", install_directory));
 
-    let working = if let Some(working) = read_valid_file_or_null(&backup_file_path).await {
-        working
-    } else {
-        let Some(working) = read_file(&target_file_path).await.ok() else {
-            return "Unable to find La-Mulana install directory.".to_owned();
+    let allow_modded = options.allow_modded_script;
+    let working =
+        if let Some(working) = read_valid_file_or_null(&backup_file_path, allow_modded).await {
+            working
+        } else {
+            let Some(working) = read_file(&target_file_path).await.ok() else {
+                return "Unable to find La-Mulana install directory.".to_owned();
+            };
+            if !allow_modded && !is_valid_script_dat(&working) {
+                return "Valid script is not found. Please re-install La-Mulana.".to_owned();
+            }
+            let result = if allow_modded {
+                write_file(&backup_file_path, &working).await
+            } else {
+                write_valid_script_dat(&backup_file_path, &working).await
+            };
+            if let Err(err) = result {
+                return format!("Failed to backup script.dat: {}", err);
+            }
+            working
         };
-        if !is_valid_script_dat(&working) {
-            return "Valid script is not found. Please re-install La-Mulana.".to_owned();
-        }
-        if let Err(err) = write_valid_script_dat(&backup_file_path, &working).await {
-            return format!("Failed to backup script.dat: {}", err);
-        }
-        working
-    };
     let game_structure = match read_game_structure_files(&handle).await {
         Ok(ok) => ok,
         Err(err) => return format!("Failed to read game structure files: {}", err),
     };
 
-    let (randomized, spoiler_log) = match randomize(&working, game_structure, &options) {
-        Ok(randomized) => randomized,
-        Err(e) => {
-            error!("{:?}", e);
-            return format!("Randomization failed: {}", e);
-        }
-    };
+    let (randomized, spoiler_log, fingerprint, locations) =
+        match randomize(&working, game_structure, &options) {
+            Ok(randomized) => randomized,
+            Err(e) => {
+                error!("{:?}", e);
+                return format!("Randomization failed: {}", e);
+            }
+        };
 
     if let Err(err) = write_file(&target_file_path, &randomized).await {
         return format!("Failed to write randomized script.dat: {}", err);
     }
     let spoiler_log_file_path = PathBuf::from(format!("{}/data/spoilerlog.txt", install_directory));
     let version = &handle.package_info().version;
-    if let Err(err) =
-        write_spoiler_log(&spoiler_log_file_path, version, &options.seed, &spoiler_log).await
+    if let Err(err) = write_spoiler_log(
+        &spoiler_log_file_path,
+        version,
+        &options.seed,
+        &fingerprint,
+        &spoiler_log,
+        options.spoiler_lock.as_ref(),
+    )
+    .await
     {
         return format!("Failed to write spoiler log: {}", err);
     }
-    "Succeeded.".to_owned()
+    let locations_file_path = PathBuf::from(format!("{}/data/locations.json", install_directory));
+    let lock = options.spoiler_lock.as_ref();
+    if let Err(err) = write_location_manifest(&locations_file_path, &locations, lock).await {
+        return format!("Failed to write location manifest: {}", err);
+    }
+    format!("Succeeded. Fingerprint: {fingerprint}")
 }
 
+/// 改造済みの script.dat のバックアップは `allow_modded_script` を指定したときだけ書き戻す
 #[tauri::command]
-pub async fn restore(install_directory: String) -> String {
+pub async fn restore(install_directory: String, allow_modded_script: Option<bool>) -> String {
     let target_file_path = PathBuf::from(format!("{}/data/script.dat", install_directory));
     let backup_file_path = PathBuf::from(format!("{}/data/script.dat.bak", install_directory));
 
@@ -169,20 +243,367 @@ pub async fn restore(install_directory: String) -> String {
     {
         return "Already clean.".to_owned();
     }
-    let Some(working) = read_valid_file_or_null(&backup_file_path).await else {
+    let allow_modded = allow_modded_script.unwrap_or(false);
+    let Some(working) = read_valid_file_or_null(&backup_file_path, allow_modded).await else {
         return "Backup is broken. Please re-install La-Mulana.".to_owned();
     };
-    if let Err(err) = write_valid_script_dat(&target_file_path, &working).await {
+    if let Err(err) = write_file(&target_file_path, &working).await {
         return format!("Failed to restore script.dat: {}", err);
     }
     "Succeeded.".to_owned()
 }
 
-async fn read_valid_file_or_null(path: &Path) -> Option<Vec<u8>> {
+/// 元の script.dat は配布せず、差分だけを `patch_file_path` に書き出す
+#[tauri::command]
+pub async fn create_patch(
+    handle: AppHandle,
+    install_directory: String,
+    options: RandomizeOptions,
+    patch_file_path: String,
+) -> String {
+    let target_file_path = PathBuf::from(format!("{}/data/script.dat", install_directory));
+    let backup_file_path = PathBuf::from(format!("{}/data/script.dat.bak", install_directory));
+    let allow_modded = options.allow_modded_script;
+    let working = if let Some(working) =
+        read_valid_file_or_null(&backup_file_path, allow_modded).await
+    {
+        working
+    } else if let Some(working) = read_valid_file_or_null(&target_file_path, allow_modded).await {
+        working
+    } else {
+        return "Valid script is not found. Please re-install La-Mulana.".to_owned();
+    };
+    let game_structure = match read_game_structure_files(&handle).await {
+        Ok(ok) => ok,
+        Err(err) => return format!("Failed to read game structure files: {}", err),
+    };
+
+    let (patch, spoiler_log, fingerprint, locations) =
+        match randomize_patch(&working, game_structure, &options) {
+            Ok(ok) => ok,
+            Err(e) => {
+                error!("{:?}", e);
+                return format!("Randomization failed: {}", e);
+            }
+        };
+
+    let patch_file_path = PathBuf::from(patch_file_path);
+    if let Err(err) = write_file(&patch_file_path, &patch).await {
+        return format!("Failed to write patch: {}", err);
+    }
+    // 配布する前に適用できることを確かめる
+    if let Err(err) = apply_dat_patch(&working, &patch) {
+        return format!("Failed to verify patch: {}", err);
+    }
+    let spoiler_log_file_path = patch_file_path.with_extension("spoilerlog.txt");
+    let version = &handle.package_info().version;
+    if let Err(err) = write_spoiler_log(
+        &spoiler_log_file_path,
+        version,
+        &options.seed,
+        &fingerprint,
+        &spoiler_log,
+        options.spoiler_lock.as_ref(),
+    )
+    .await
+    {
+        return format!("Failed to write spoiler log: {}", err);
+    }
+    let locations_file_path = patch_file_path.with_extension("locations.json");
+    let lock = options.spoiler_lock.as_ref();
+    if let Err(err) = write_location_manifest(&locations_file_path, &locations, lock).await {
+        return format!("Failed to write location manifest: {}", err);
+    }
+    format!("Succeeded. Fingerprint: {fingerprint}")
+}
+
+/// パッチに埋め込まれたハッシュと一致する script.dat にだけ適用する
+#[tauri::command]
+pub async fn apply_patch(install_directory: String, patch_file_path: String) -> String {
+    let target_file_path = PathBuf::from(format!("{}/data/script.dat", install_directory));
+    let backup_file_path = PathBuf::from(format!("{}/data/script.dat.bak", install_directory));
+    let patch = match read_file(Path::new(&patch_file_path)).await {
+        Ok(ok) => ok,
+        Err(err) => return format!("Unable to open patch: {}", err),
+    };
+
+    let backup = read_file(&backup_file_path).await.ok();
+    let (patched, fingerprint) = if let Some(patched) = backup
+        .as_deref()
+        .and_then(|x| apply_dat_patch(x, &patch).ok())
+    {
+        patched
+    } else {
+        let Ok(working) = read_file(&target_file_path).await else {
+            return "Unable to find La-Mulana install directory.".to_owned();
+        };
+        let patched = match apply_dat_patch(&working, &patch) {
+            Ok(ok) => ok,
+            Err(err) => return format!("Failed to apply patch: {}", err),
+        };
+        // 既存のバックアップは元のファイルかもしれないので上書きしない
+        if backup.is_none()
+            && let Err(err) = write_file(&backup_file_path, &working).await
+        {
+            return format!("Failed to backup script.dat: {}", err);
+        }
+        patched
+    };
+
+    if let Err(err) = write_file(&target_file_path, &patched).await {
+        return format!("Failed to write patched script.dat: {}", err);
+    }
+    format!("Succeeded. Fingerprint: {fingerprint}")
+}
+
+/// プレイヤーごとのファイルを `output_directory` の `world{番号}` に、対応表を `multiworld.json` に書き出す。
+/// 各ワールドにはリレーに参加するための `relay.json` も置く
+#[tauri::command]
+pub async fn create_multiworld(
+    handle: AppHandle,
+    install_directory: String,
+    options: MultiworldOptions,
+    output_directory: String,
+) -> String {
+    let target_file_path = PathBuf::from(format!("{}/data/script.dat", install_directory));
+    let backup_file_path = PathBuf::from(format!("{}/data/script.dat.bak", install_directory));
+    let allow_modded = options.players.iter().any(|x| x.allow_modded_script);
+    let working = if let Some(working) =
+        read_valid_file_or_null(&backup_file_path, allow_modded).await
+    {
+        working
+    } else if let Some(working) = read_valid_file_or_null(&target_file_path, allow_modded).await {
+        working
+    } else {
+        return "Valid script is not found. Please re-install La-Mulana.".to_owned();
+    };
+    let game_structure = match read_game_structure_files(&handle).await {
+        Ok(ok) => ok,
+        Err(err) => return format!("Failed to read game structure files: {}", err),
+    };
+
+    let (worlds, manifest) = match randomize_multiworld(&working, game_structure, &options) {
+        Ok(ok) => ok,
+        Err(e) => {
+            error!("{:?}", e);
+            return format!("Randomization failed: {}", e);
+        }
+    };
+
+    let output_directory = PathBuf::from(output_directory);
+    let version = &handle.package_info().version;
+    for (i, (randomized, spoiler_log, fingerprint, locations)) in worlds.iter().enumerate() {
+        let player = &options.players[i];
+        let dir = output_directory.join(format!("world{i}"));
+        if let Err(err) = fs::create_dir_all(&dir).await {
+            return format!("Failed to create directory: {}", err);
+        }
+        if let Err(err) = write_file(&dir.join("script.dat"), randomized).await {
+            return format!("Failed to write randomized script.dat: {}", err);
+        }
+        let lock = player.spoiler_lock.as_ref();
+        if let Err(err) = write_spoiler_log(
+            &dir.join("spoilerlog.txt"),
+            version,
+            &player.seed,
+            fingerprint,
+            spoiler_log,
+            lock,
+        )
+        .await
+        {
+            return format!("Failed to write spoiler log: {}", err);
+        }
+        if let Err(err) =
+            write_location_manifest(&dir.join("locations.json"), locations, lock).await
+        {
+            return format!("Failed to write location manifest: {}", err);
+        }
+        let relay_world = RelayWorld::new(&manifest, i as u32);
+        let relay_world = serde_json::to_vec_pretty(&relay_world).unwrap();
+        if let Err(err) = write_file(&dir.join("relay.json"), &relay_world).await {
+            return format!("Failed to write relay.json: {}", err);
+        }
+    }
+    let manifest = serde_json::to_vec_pretty(&manifest).unwrap();
+    if let Err(err) = write_file(&output_directory.join("multiworld.json"), &manifest).await {
+        return format!("Failed to write multiworld manifest: {}", err);
+    }
+    let fingerprints: Vec<_> = worlds.iter().map(|x| x.2.to_string()).collect();
+    format!("Succeeded. Fingerprints: {}", fingerprints.join(" / "))
+}
+
+/// 封印されたスポイラーログを開き、拡張子を `txt` にして書き出す
+#[tauri::command]
+pub async fn unlock_spoiler_log(sealed_file_path: String, password: Option<String>) -> String {
+    let sealed_file_path = PathBuf::from(sealed_file_path);
+    let sealed = match read_file(&sealed_file_path).await {
+        Ok(ok) => ok,
+        Err(err) => return format!("Unable to open sealed spoiler log: {}", err),
+    };
+    let sealed = match SealedSpoilerLog::parse(&sealed) {
+        Ok(ok) => ok,
+        Err(err) => return format!("Sealed spoiler log is broken: {}", err),
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let spoiler_log = match sealed.unseal(password.as_deref(), now) {
+        Ok(ok) => ok,
+        Err(err) => return format!("Failed to unlock spoiler log: {}", err),
+    };
+    let spoiler_log_file_path = sealed_file_path.with_extension("txt");
+    if let Err(err) = write_file(&spoiler_log_file_path, spoiler_log.as_bytes()).await {
+        return format!("Failed to write spoiler log: {}", err);
+    }
+    "Succeeded.".to_owned()
+}
+
+fn worlds_dir(handle: &AppHandle) -> PathBuf {
+    handle.path().app_data_dir().unwrap().join("worlds")
+}
+
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+}
+
+/// 別のバージョンで生成したワールドのセーブを扱う場合は警告を付ける
+fn succeeded_with_warnings<'a>(
+    version: &Version,
+    worlds: impl IntoIterator<Item = &'a str>,
+) -> String {
+    let mut warnings: Vec<_> = worlds
+        .into_iter()
+        .filter_map(|world| version_warning(world, version))
+        .collect();
+    warnings.dedup();
+    if warnings.is_empty() {
+        return "Succeeded.".to_owned();
+    }
+    format!("Succeeded. Warning: {}", warnings.join(" "))
+}
+
+/// ワールドごとのセーブとバックアップの一覧
+#[tauri::command]
+pub async fn list_saves(handle: AppHandle) -> Result<Vec<WorldSaves>, String> {
+    let version = &handle.package_info().version;
+    saves::list_saves(&worlds_dir(&handle), version)
+        .await
+        .map_err(|err| format!("Failed to list saves: {err}"))
+}
+
+#[tauri::command]
+pub async fn copy_save(
+    handle: AppHandle,
+    src_world: String,
+    src_slot: u8,
+    dst_world: String,
+    dst_slot: u8,
+) -> String {
+    let worlds_dir = worlds_dir(&handle);
+    let backup = match saves::copy_save(
+        &worlds_dir,
+        &src_world,
+        src_slot,
+        &dst_world,
+        dst_slot,
+        now_millis(),
+    )
+    .await
+    {
+        Ok(ok) => ok,
+        Err(err) => return format!("Failed to copy save: {err}"),
+    };
+    let version = &handle.package_info().version;
+    let result = succeeded_with_warnings(version, [src_world.as_str(), dst_world.as_str()]);
+    with_backup(result, backup)
+}
+
+/// 同じシードの別のワールドのセーブを、すべて同じスロットに移す
+#[tauri::command]
+pub async fn migrate_saves(handle: AppHandle, src_world: String, dst_world: String) -> String {
+    let worlds_dir = worlds_dir(&handle);
+    let backup = match saves::migrate_saves(&worlds_dir, &src_world, &dst_world, now_millis()).await
+    {
+        Ok(ok) => ok,
+        Err(err) => return format!("Failed to migrate saves: {err}"),
+    };
+    let version = &handle.package_info().version;
+    let result = succeeded_with_warnings(version, [src_world.as_str(), dst_world.as_str()]);
+    with_backup(result, backup)
+}
+
+/// 上書きする前に取ったバックアップを結果に添える
+fn with_backup(result: String, backup: Option<String>) -> String {
+    match backup {
+        Some(backup) => format!("{result} Backup: {backup}"),
+        None => result,
+    }
+}
+
+#[tauri::command]
+pub async fn backup_saves(handle: AppHandle, world: String) -> String {
+    match saves::backup_saves(&worlds_dir(&handle), &world, now_millis()).await {
+        Ok(Some(backup)) => format!("Succeeded. Backup: {backup}"),
+        Ok(None) => "No saves to back up.".to_owned(),
+        Err(err) => format!("Failed to back up saves: {err}"),
+    }
+}
+
+/// 今のセーブは戻す前にバックアップされる
+#[tauri::command]
+pub async fn restore_saves(handle: AppHandle, world: String, backup: String) -> String {
+    let worlds_dir = worlds_dir(&handle);
+    if let Err(err) = saves::restore_saves(&worlds_dir, &world, &backup, now_millis()).await {
+        return format!("Failed to restore saves: {err}");
+    }
+    let version = &handle.package_info().version;
+    succeeded_with_warnings(version, [world.as_str()])
+}
+
+/// インストール先にあるバニラのセーブをワールドに取り込む
+#[tauri::command]
+pub async fn import_vanilla_save(
+    handle: AppHandle,
+    install_directory: String,
+    src_slot: u8,
+    world: String,
+    dst_slot: u8,
+) -> String {
+    let install_directory = PathBuf::from(install_directory);
+    let worlds_dir = worlds_dir(&handle);
+    let backup = match saves::import_save(
+        &install_directory,
+        src_slot,
+        &worlds_dir,
+        &world,
+        dst_slot,
+        now_millis(),
+    )
+    .await
+    {
+        Ok(ok) => ok,
+        Err(err) => return format!("Failed to import save: {err}"),
+    };
+    let version = &handle.package_info().version;
+    with_backup(succeeded_with_warnings(version, [world.as_str()]), backup)
+}
+
+/// `allow_modded` の場合はハッシュの代わりに読めるかどうかで判定する
+async fn read_valid_file_or_null(path: &Path, allow_modded: bool) -> Option<Vec<u8>> {
     let Ok(working) = read_file(path).await else {
         return None;
     };
-    if !is_valid_script_dat(&working) {
+    let valid = if allow_modded {
+        parse_script_dat(&working).is_ok()
+    } else {
+        is_valid_script_dat(&working)
+    };
+    if !valid {
         return None;
     }
     Some(working)
@@ -0,0 +1,348 @@
+use anyhow::{Result, bail};
+use semver::Version;
+use smol::{fs, io, stream::StreamExt};
+use std::{
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// `lamulana.sa0` から `lamulana.sa4` まで
+pub const SAVE_SLOTS: u8 = 5;
+/// ワールドのディレクトリの中に作る
+const BACKUPS_DIR_NAME: &str = "backups";
+
+#[derive(Debug, Eq, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveFile {
+    pub slot: u8,
+    pub size: u64,
+    /// UNIX 時間 (秒)
+    pub modified: u64,
+}
+
+#[derive(Debug, Eq, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorldSaves {
+    /// `worlds` の中のディレクトリ名
+    pub world: String,
+    pub version: String,
+    /// 今のランダマイザーと異なるバージョンで生成されたワールド
+    pub different_version: bool,
+    pub saves: Vec<SaveFile>,
+    /// 古い順
+    pub backups: Vec<String>,
+}
+
+pub fn save_file_name(slot: u8) -> String {
+    format!("lamulana.sa{slot}")
+}
+
+/// ディレクトリ名の先頭はランダマイザーのバージョン。シード中の `,` はエスケープされている
+pub fn world_version(world: &str) -> &str {
+    world.split_once(',').map_or(world, |(version, _)| version)
+}
+
+/// バージョンの次はエスケープされたシード
+#[cfg(any(target_os = "windows", test))]
+fn world_seed(world: &str) -> Option<&str> {
+    world.split(',').nth(1)
+}
+
+pub fn version_warning(world: &str, version: &Version) -> Option<String> {
+    let world_version = world_version(world);
+    (world_version != version.to_string()).then(|| {
+        format!(
+            "The save belongs to a world generated by randomizer {world_version}, but this is {version}."
+        )
+    })
+}
+
+/// フロントエンドから渡される名前で、ディレクトリの外を指させない
+fn child_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        bail!("Invalid name: {name}");
+    }
+    Ok(dir.join(name))
+}
+
+fn save_path(dir: &Path, slot: u8) -> Result<PathBuf> {
+    if slot >= SAVE_SLOTS {
+        bail!("Invalid save slot: {slot}");
+    }
+    Ok(dir.join(save_file_name(slot)))
+}
+
+async fn read_dir_names(dir: &Path) -> Result<Vec<String>> {
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(ok) => ok,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut names = Vec::new();
+    while let Some(entry) = entries.try_next().await? {
+        if entry.file_type().await?.is_dir() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+async fn read_saves(dir: &Path) -> Result<Vec<SaveFile>> {
+    let mut saves = Vec::new();
+    for slot in 0..SAVE_SLOTS {
+        let metadata = match fs::metadata(save_path(dir, slot)?).await {
+            Ok(ok) => ok,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+        saves.push(SaveFile {
+            slot,
+            size: metadata.len(),
+            modified,
+        });
+    }
+    Ok(saves)
+}
+
+pub async fn list_saves(worlds_dir: &Path, version: &Version) -> Result<Vec<WorldSaves>> {
+    let mut list = Vec::new();
+    for world in read_dir_names(worlds_dir).await? {
+        let world_dir = worlds_dir.join(&world);
+        let mut backups = read_dir_names(&world_dir.join(BACKUPS_DIR_NAME)).await?;
+        // 名前は作成時刻なので数値として並べる
+        backups.sort_by_key(|x| x.parse::<u128>().unwrap_or_default());
+        list.push(WorldSaves {
+            version: world_version(&world).to_owned(),
+            different_version: version_warning(&world, version).is_some(),
+            saves: read_saves(&world_dir).await?,
+            backups,
+            world,
+        });
+    }
+    Ok(list)
+}
+
+async fn copy_file(src: &Path, dst: &Path) -> Result<()> {
+    match fs::copy(src, dst).await {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            bail!("Save file is not found: {}", src.display())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// 上書きするセーブがあれば、先にワールドのセーブを `now` の名前でバックアップする。
+/// バックアップした場合はその名前を返す
+async fn overwrite_save(
+    src: &Path,
+    worlds_dir: &Path,
+    world: &str,
+    slot: u8,
+    now: u128,
+) -> Result<Option<String>> {
+    let dst_dir = child_path(worlds_dir, world)?;
+    if fs::metadata(&dst_dir).await.is_err() {
+        bail!("World is not found: {world}");
+    }
+    let dst = save_path(&dst_dir, slot)?;
+    if fs::metadata(src).await.is_err() {
+        bail!("Save file is not found: {}", src.display());
+    }
+    let backup = if fs::metadata(&dst).await.is_ok() {
+        backup_saves(worlds_dir, world, now).await?
+    } else {
+        None
+    };
+    copy_file(src, &dst).await?;
+    Ok(backup)
+}
+
+pub async fn copy_save(
+    worlds_dir: &Path,
+    src_world: &str,
+    src_slot: u8,
+    dst_world: &str,
+    dst_slot: u8,
+    now: u128,
+) -> Result<Option<String>> {
+    if src_world == dst_world && src_slot == dst_slot {
+        bail!("Cannot copy a save onto itself");
+    }
+    let src = save_path(&child_path(worlds_dir, src_world)?, src_slot)?;
+    overwrite_save(&src, worlds_dir, dst_world, dst_slot, now).await
+}
+
+/// バニラのゲームのセーブをワールドに取り込む
+pub async fn import_save(
+    install_directory: &Path,
+    src_slot: u8,
+    worlds_dir: &Path,
+    world: &str,
+    dst_slot: u8,
+    now: u128,
+) -> Result<Option<String>> {
+    let src = save_path(install_directory, src_slot)?;
+    if src == save_path(&child_path(worlds_dir, world)?, dst_slot)? {
+        bail!("Cannot copy a save onto itself");
+    }
+    overwrite_save(&src, worlds_dir, world, dst_slot, now).await
+}
+
+/// ディレクトリ名の付け方やバージョンが変わると、同じシードでも別のワールドになる。
+/// 同じシードでセーブのある他のワールドを返す
+#[cfg(any(target_os = "windows", test))]
+pub async fn find_previous_worlds(worlds_dir: &Path, world: &str) -> Result<Vec<String>> {
+    let Some(seed) = world_seed(world) else {
+        return Ok(Vec::new());
+    };
+    let mut list = Vec::new();
+    for other in read_dir_names(worlds_dir).await? {
+        if other == world || world_seed(&other) != Some(seed) {
+            continue;
+        }
+        if !read_saves(&worlds_dir.join(&other)).await?.is_empty() {
+            list.push(other);
+        }
+    }
+    Ok(list)
+}
+
+/// `src_world` のセーブを、すべて `dst_world` の同じスロットに写す。
+/// `dst_world` にセーブがあれば先に `now` の名前でバックアップし、その名前を返す
+pub async fn migrate_saves(
+    worlds_dir: &Path,
+    src_world: &str,
+    dst_world: &str,
+    now: u128,
+) -> Result<Option<String>> {
+    if src_world == dst_world {
+        bail!("Cannot migrate saves onto the same world");
+    }
+    let src_dir = child_path(worlds_dir, src_world)?;
+    let dst_dir = child_path(worlds_dir, dst_world)?;
+    if fs::metadata(&dst_dir).await.is_err() {
+        bail!("World is not found: {dst_world}");
+    }
+    let saves = read_saves(&src_dir).await?;
+    if saves.is_empty() {
+        bail!("No saves to migrate: {src_world}");
+    }
+    let backup = backup_saves(worlds_dir, dst_world, now).await?;
+    for save in saves {
+        copy_file(
+            &save_path(&src_dir, save.slot)?,
+            &save_path(&dst_dir, save.slot)?,
+        )
+        .await?;
+    }
+    Ok(backup)
+}
+
+/// ワールドのセーブをすべて `backups/{now}` に写す。セーブがなければ `None`
+pub async fn backup_saves(worlds_dir: &Path, world: &str, now: u128) -> Result<Option<String>> {
+    let world_dir = child_path(worlds_dir, world)?;
+    let saves = read_saves(&world_dir).await?;
+    if saves.is_empty() {
+        return Ok(None);
+    }
+    let name = now.to_string();
+    let backup_dir = world_dir.join(BACKUPS_DIR_NAME).join(&name);
+    if fs::metadata(&backup_dir).await.is_ok() {
+        bail!("Backup already exists: {name}");
+    }
+    fs::create_dir_all(&backup_dir).await?;
+    for save in saves {
+        let file_name = save_file_name(save.slot);
+        copy_file(&world_dir.join(&file_name), &backup_dir.join(file_name)).await?;
+    }
+    Ok(Some(name))
+}
+
+/// 戻す前に今のセーブを `now` の名前でバックアップする。戻した後のスロットはバックアップと同じになる
+pub async fn restore_saves(worlds_dir: &Path, world: &str, backup: &str, now: u128) -> Result<()> {
+    let world_dir = child_path(worlds_dir, world)?;
+    let backup_dir = child_path(&world_dir.join(BACKUPS_DIR_NAME), backup)?;
+    let backup_saves_list = read_saves(&backup_dir).await?;
+    if backup_saves_list.is_empty() {
+        bail!("Backup is not found: {backup}");
+    }
+    backup_saves(worlds_dir, world, now).await?;
+    for slot in 0..SAVE_SLOTS {
+        let dst = save_path(&world_dir, slot)?;
+        if backup_saves_list.iter().any(|x| x.slot == slot) {
+            copy_file(&save_path(&backup_dir, slot)?, &dst).await?;
+            continue;
+        }
+        match fs::remove_file(&dst).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_backup_and_restore() -> Result<()> {
+        let worlds_dir =
+            std::env::temp_dir().join(format!("lmorandomizer_saves_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&worlds_dir).await;
+        let old = "0.9.0,seed,00000";
+        let new = "1.0.0,seed%2cx,00000";
+        fs::create_dir_all(worlds_dir.join(old)).await?;
+        fs::create_dir_all(worlds_dir.join(new)).await?;
+        fs::write(worlds_dir.join(old).join("lamulana.sa1"), b"run").await?;
+
+        assert_eq!(copy_save(&worlds_dir, old, 1, new, 0, 0).await?, None);
+        assert!(copy_save(&worlds_dir, old, 2, new, 0, 0).await.is_err());
+        assert!(copy_save(&worlds_dir, "..", 1, new, 0, 0).await.is_err());
+        assert!(copy_save(&worlds_dir, old, 1, new, 5, 0).await.is_err());
+        assert!(copy_save(&worlds_dir, old, 1, old, 1, 0).await.is_err());
+
+        let backup = backup_saves(&worlds_dir, new, 1).await?.unwrap();
+        fs::write(worlds_dir.join(new).join("lamulana.sa0"), b"lost").await?;
+        fs::write(worlds_dir.join(new).join("lamulana.sa3"), b"new").await?;
+        restore_saves(&worlds_dir, new, &backup, 2).await?;
+        let restored = fs::read(worlds_dir.join(new).join("lamulana.sa0")).await?;
+        assert_eq!(restored, b"run");
+        // 上書きする前にバックアップする
+        let backup = copy_save(&worlds_dir, old, 1, new, 0, 3).await?;
+        assert_eq!(backup.as_deref(), Some("3"));
+
+        let version = Version::new(1, 0, 0);
+        let list = list_saves(&worlds_dir, &version).await?;
+        assert_eq!(list.len(), 2);
+        assert_eq!(
+            (list[0].version.as_str(), list[0].different_version),
+            ("0.9.0", true)
+        );
+        let new_saves = &list[1];
+        assert!(!new_saves.different_version);
+        assert_eq!(
+            new_saves.saves.iter().map(|x| x.slot).collect::<Vec<_>>(),
+            [0]
+        );
+        assert_eq!(new_saves.backups, ["1", "2", "3"]);
+
+        // ディレクトリ名の付け方が変わったワールドへ移す
+        let renamed = "1.0.0,seed,00000,duplicates";
+        fs::create_dir_all(worlds_dir.join(renamed)).await?;
+        assert_eq!(find_previous_worlds(&worlds_dir, renamed).await?, [old]);
+        assert_eq!(migrate_saves(&worlds_dir, old, renamed, 4).await?, None);
+        let migrated = fs::read(worlds_dir.join(renamed).join("lamulana.sa1")).await?;
+        assert_eq!(migrated, b"run");
+        let backup = migrate_saves(&worlds_dir, old, renamed, 5).await?;
+        assert_eq!(backup.as_deref(), Some("5"));
+        assert!(migrate_saves(&worlds_dir, old, old, 6).await.is_err());
+
+        fs::remove_dir_all(&worlds_dir).await?;
+        Ok(())
+    }
+}
@@ -1,3 +1,15 @@
+#[cfg(not(test))]
+use std::path::Path;
+
+#[cfg(not(test))]
+use crate::script::{
+    diff::diff_script,
+    file::{
+        scriptconverter::parse_script_dat,
+        scriptdecompiler::{DocumentFormat, compile_script, decompile_script},
+    },
+};
+
 #[allow(unused)]
 #[cfg(not(test))]
 mod dataset;
@@ -12,11 +24,14 @@ mod script;
 fn main() {
     let args: Vec<String> = std::env::args().collect();
     if args.len() <= 3 {
-        eprintln!("Usage: lmocodec.exe [decode|encode] [input file] [output file]",);
+        eprintln!(
+            "Usage: lmocodec.exe [decode|encode|decompile|compile] [input file] [output file]",
+        );
+        eprintln!("       lmocodec.exe diff [old file] [new file]");
         std::process::exit(1);
     }
     let mode = &args[1];
-    if mode != "decode" && mode != "encode" {
+    if !["decode", "encode", "decompile", "compile", "diff"].contains(&mode.as_str()) {
         eprintln!("Invalid mode: {}", mode);
         std::process::exit(1);
     }
@@ -26,9 +41,27 @@ fn main() {
         let input_file = std::fs::read(input_file_path).unwrap();
         let output = script::file::dat::cipher_to_text(&input_file);
         std::fs::write(output_file_path, output).unwrap();
-    } else {
+    } else if mode == "encode" {
         let input_file = std::fs::read_to_string(input_file_path).unwrap();
         let output = script::file::dat::text_to_cipher(&input_file);
         std::fs::write(output_file_path, output).unwrap();
+    } else if mode == "decompile" {
+        let input_file = std::fs::read(input_file_path).unwrap();
+        let script = parse_script_dat(&input_file).unwrap();
+        let format = DocumentFormat::from_path(Path::new(output_file_path));
+        let output = decompile_script(&script, format).unwrap();
+        std::fs::write(output_file_path, output).unwrap();
+    } else if mode == "diff" {
+        let old_script = parse_script_dat(&std::fs::read(input_file_path).unwrap()).unwrap();
+        let new_script = parse_script_dat(&std::fs::read(output_file_path).unwrap()).unwrap();
+        for line in diff_script(&old_script, &new_script) {
+            println!("{}", line);
+        }
+    } else {
+        let input_file = std::fs::read_to_string(input_file_path).unwrap();
+        let format = DocumentFormat::from_path(Path::new(input_file_path));
+        let script = compile_script(&input_file, format).unwrap();
+        let output = script::file::scriptconverter::build_script_dat(&script);
+        std::fs::write(output_file_path, output).unwrap();
     }
 }
@@ -263,7 +263,9 @@ fn rom_location(region: Region, key: String, value: FieldYamlAccessRule) -> Resu
 }
 
 fn seals_location(region: Region, key: String, value: FieldYamlAccessRule) -> Result<SealSpot> {
-    let seal = Seal::from_str(&to_pascal_case(&key.replace("Seal", "")))?;
+    let seal = Seal::from_str(&to_pascal_case(
+        &key.split(":").next().unwrap().replace("Seal", ""),
+    ))?;
     let name = SpotName::new(key.clone());
     let requirements = value.try_into_any_of_all_requirements()?;
     let spot = SealSpot::new(region, name, seal, requirements);
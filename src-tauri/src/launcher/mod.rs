@@ -4,7 +4,6 @@ mod find_func_addr;
 mod utils;
 
 use std::ffi::{OsStr, c_void};
-use std::io::Write;
 use std::mem::{forget, size_of};
 use std::os::windows::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
@@ -13,7 +12,8 @@ use std::{env, iter};
 
 use anyhow::Result;
 use lmorandomizer_shared::Command;
-use lmorandomizer_shared::ipc::IpcStream;
+use lmorandomizer_shared::ipc::{IpcStream, NamedPipeConnector, connect_with_retry};
+use lmorandomizer_shared::protocol::LauncherSession;
 use windows::Win32::Foundation::HANDLE;
 use windows::Win32::System::Memory::{
     MEM_COMMIT, MEM_RELEASE, PAGE_READWRITE, VirtualAllocEx, VirtualFreeEx,
@@ -47,19 +47,29 @@ impl Drop for VirtualAllocatedMem<'_> {
     }
 }
 
-pub fn launch(exe_dir_path: &Path, exe_file_name: &str, custom_path: PathBuf) -> Result<()> {
+/// 初期化したセッションを返す。閉じるとフックとの接続が切れるので、トラッカーに使い続ける
+pub fn launch(
+    exe_dir_path: &Path,
+    exe_file_name: &str,
+    custom_path: PathBuf,
+) -> Result<LauncherSession<IpcStream>> {
     let current_exe = env::current_exe().unwrap();
     let dll_path = current_exe.parent().unwrap().join("hook.dll");
     let process_id = launch_and_inject(exe_dir_path, exe_file_name, &dll_path)?;
 
     let pipe_name = format!("lmorandomizer_for_{}", process_id);
-    let mut stream = connect(&pipe_name)?;
+    let connector = NamedPipeConnector::new(&pipe_name);
+    let stream = connect_with_retry(&connector, 50, Duration::from_millis(500))?;
+    let (mut session, hello) = LauncherSession::connect(stream, env!("CARGO_PKG_VERSION"))?;
+    if hello.randomizer_version != env!("CARGO_PKG_VERSION") {
+        log::warn!("hook version differs: {}", hello.randomizer_version);
+    }
 
     let cmd = Command::Init(custom_path);
-    println!("{cmd:?}");
-    stream.write_all(&rmp_serde::to_vec(&cmd).unwrap())?;
+    log::trace!("{cmd:?}");
+    session.request(cmd)?;
 
-    Ok(())
+    Ok(session)
 }
 
 fn launch_and_inject(exe_dir_path: &Path, exe_file_name: &str, dll_path: &Path) -> Result<u32> {
@@ -114,24 +124,3 @@ fn create_process_with_suspend_debug(
 fn to_wide_null(s: &OsStr) -> Vec<u16> {
     s.encode_wide().chain(iter::once(0)).collect()
 }
-
-fn connect(pipe_name: &str) -> Result<IpcStream> {
-    let mut i = 0;
-    let stream = loop {
-        match IpcStream::connect(pipe_name) {
-            Ok(stream) => {
-                break stream;
-            }
-            Err(err) => {
-                if i < 50 {
-                    std::thread::sleep(Duration::from_millis(500));
-                    i += 1;
-                    continue;
-                }
-                return Err(err.into());
-            }
-        }
-    };
-
-    Ok(stream)
-}
@@ -39,6 +39,17 @@ fn main() {
             app::open_folder,
             app::apply,
             app::restore,
+            app::create_patch,
+            app::apply_patch,
+            app::create_multiworld,
+            app::launch_multiworld,
+            app::unlock_spoiler_log,
+            app::list_saves,
+            app::copy_save,
+            app::migrate_saves,
+            app::backup_saves,
+            app::restore_saves,
+            app::import_vanilla_save,
         ])
         .run(context)
         .expect("error while running tauri application");
@@ -0,0 +1,96 @@
+use std::fmt::{self, Write};
+
+use sha3::Digest;
+
+use super::storage::Storage;
+
+/// 256 を割り切れる数にして、ハッシュの各バイトから偏りなく選ぶ
+const NAMES: [&str; 32] = [
+    "Feather",
+    "Boots",
+    "Glove",
+    "Scanner",
+    "Grail",
+    "Pistol",
+    "Shuriken",
+    "Bomb",
+    "Whip",
+    "Knife",
+    "Axe",
+    "Katana",
+    "Key Sword",
+    "Mace",
+    "Ankh Jewel",
+    "Sacred Orb",
+    "Helmet",
+    "Bracelet",
+    "Cape",
+    "Scalesphere",
+    "Crucifix",
+    "Perfume",
+    "Ring",
+    "Lamp",
+    "Pochette",
+    "Map",
+    "Dragon Bone",
+    "Crystal Skull",
+    "Magatama",
+    "Serpent Staff",
+    "Vessel",
+    "Diary",
+];
+const LEN: usize = 5;
+
+/// 同じワールドを生成したかを参加者同士で確認するための、アイテム名の並び
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Fingerprint(Vec<&'static str>);
+
+/// スポット名とアイテム名だけの、型の `Debug` 表現に依らない配置の表現
+fn placement_text(storage: &Storage) -> String {
+    let mut text = String::new();
+    let mut push = |kind: &str, spot: &str, item: &str| {
+        writeln!(text, "{kind}\t{spot}\t{item}").unwrap();
+    };
+    for x in storage.main_weapons.values() {
+        push("mainWeapon", x.spot.name().get(), x.item.name.get());
+    }
+    for x in storage.sub_weapons.values() {
+        push("subWeapon", x.spot.name().get(), x.item.name.get());
+    }
+    for x in storage.chests.values() {
+        push("chest", x.spot.name().get(), x.item.name.get());
+    }
+    for x in storage.seals.values() {
+        push("seal", x.spot.name().get(), x.item.name.get());
+    }
+    for x in storage.roms.values() {
+        push("rom", x.spot.name().get(), x.item.name.get());
+    }
+    for x in &storage.talks {
+        push("talk", x.spot.name().get(), x.item.name.get());
+    }
+    for x in &storage.shops {
+        let spot = format!("{}#{}", x.spot.name().get(), x.idx);
+        push("shop", &spot, x.item.name.get());
+    }
+    text
+}
+
+impl Fingerprint {
+    /// `storage` はシャッフル後の配置
+    pub fn new(storage: &Storage) -> Self {
+        let hash = sha3::Sha3_512::digest(placement_text(storage));
+        Self(
+            hash.iter()
+                .take(LEN)
+                .map(|&x| NAMES[x as usize % NAMES.len()])
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join(", "))
+    }
+}
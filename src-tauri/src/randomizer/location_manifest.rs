@@ -0,0 +1,135 @@
+use anyhow::Result;
+use lmorandomizer_shared::tracker::{LocationManifest, TrackedLocation};
+
+use crate::script::data::{item::Item, script::Script};
+
+use super::storage::{Storage, item};
+
+/// 補充品のショップ枠などはフラグを立てない
+const NO_FLAG: u16 = 65279;
+
+/// スポット名と、そこに置いたアイテム
+fn spots(storage: &Storage) -> impl Iterator<Item = (String, &item::Item)> {
+    let main_weapons = storage
+        .main_weapons
+        .values()
+        .map(|x| (x.spot.to_string(), &x.item));
+    let sub_weapons = storage
+        .sub_weapons
+        .values()
+        .map(|x| (x.spot.to_string(), &x.item));
+    let chests = storage
+        .chests
+        .values()
+        .map(|x| (x.spot.to_string(), &x.item));
+    let seals = storage
+        .seals
+        .values()
+        .map(|x| (x.spot.to_string(), &x.item));
+    let roms = storage.roms.values().map(|x| (x.spot.to_string(), &x.item));
+    let talks = storage.talks.iter().map(|x| (x.spot.to_string(), &x.item));
+    let shops = storage
+        .shops
+        .iter()
+        .map(|x| (format!("{}[{}]", x.spot, x.idx), &x.item));
+    main_weapons
+        .chain(sub_weapons)
+        .chain(chests)
+        .chain(seals)
+        .chain(roms)
+        .chain(talks)
+        .chain(shops)
+}
+
+/// `script` は書き換える前のもの。スポットを取ると、そこに置いたアイテム本来のフラグが立つ
+pub fn create_location_manifest(script: &Script, shuffled: &Storage) -> Result<LocationManifest> {
+    let locations = spots(shuffled)
+        .map(|(name, item)| Ok((name, flag(script, item)?)))
+        .filter_map(|result: Result<_>| match result {
+            Ok((_, NO_FLAG)) => None,
+            Ok((name, flag)) => Some(Ok(TrackedLocation {
+                name,
+                flags: vec![flag],
+            })),
+            Err(err) => Some(Err(err)),
+        })
+        .collect::<Result<_>>()?;
+    Ok(LocationManifest::new(locations))
+}
+
+fn flag(script: &Script, item: &item::Item) -> Result<u16> {
+    Ok(Item::new(&item.src, script)?.flag())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{
+        app::read_game_structure_files_debug,
+        randomizer::{RandomizeOptions, randomize, storage::create_source::create_source},
+        script::{
+            data::object::ItemShop,
+            file::scriptconverter::{parse_script_dat, read_script_dat},
+            fixture,
+        },
+    };
+
+    use super::*;
+
+    /// 宝箱、封印、ショップ、会話、ROM、武器を取ると立つフラグ
+    fn item_set_flags(script: &Script) -> Result<BTreeSet<u16>> {
+        let fields = || script.worlds.iter().flat_map(|x| &x.fields);
+        let mut flags: BTreeSet<_> = fields()
+            .flat_map(|x| &x.maps)
+            .flat_map(|x| &x.objects)
+            .filter_map(|x| x.set_flag().ok())
+            .collect();
+        let field_objects = fields().flat_map(|x| &x.objects);
+        flags.extend(field_objects.filter_map(|x| x.set_flag().ok()));
+        for shop in script.shops() {
+            if let Some(shop) = ItemShop::try_from_shop_object(shop, &script.talks)? {
+                let items = shop.items();
+                flags.extend([items.0.flag(), items.1.flag(), items.2.flag()]);
+            }
+        }
+        let talks = script.talks.iter().filter_map(|x| x.item().ok().flatten());
+        flags.extend(talks.map(|(_, flag)| flag));
+        Ok(flags)
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the vanilla script.dat"]
+    async fn test_location_manifest_matches_the_edited_script() -> Result<()> {
+        let game_structure = read_game_structure_files_debug().await?;
+        let opts = RandomizeOptions {
+            seed: "test".to_owned(),
+            shuffle_secret_roms: true,
+            ..Default::default()
+        };
+        let source = create_source(&game_structure, &opts)?;
+        let vanilla_dat = fixture::vanilla_script_dat()?;
+        let vanilla = read_script_dat(&vanilla_dat)?;
+        let (dat, _, _, manifest) = randomize(&vanilla_dat, game_structure, &opts)?;
+        let edited = parse_script_dat(&dat)?;
+
+        // シャッフルはアイテムを入れ替えるだけなので、フラグのないアイテムの数は変わらない
+        let names: BTreeSet<_> = spots(&source).map(|(name, _)| name).collect();
+        let no_flag_count = spots(&source)
+            .map(|(_, item)| flag(&vanilla, item))
+            .filter(|x| x.as_ref().is_ok_and(|&x| x == NO_FLAG))
+            .count();
+        assert_eq!(manifest.locations.len() + no_flag_count, names.len());
+        let set_flags = item_set_flags(&edited)?;
+        for location in &manifest.locations {
+            assert!(names.contains(&location.name), "{}", location.name);
+            assert!(
+                location.flags.iter().all(|x| set_flags.contains(x)),
+                "{} {:?}",
+                location.name,
+                location.flags
+            );
+        }
+        Ok(())
+    }
+}
@@ -1,39 +1,79 @@
+mod fingerprint;
+mod location_manifest;
+mod multiworld;
 mod randomize_items;
+mod sealed_spoiler_log;
 mod spoiler;
 mod spoiler_log;
 pub mod storage;
+mod validate_script;
 
 use std::mem::take;
 
 use anyhow::Result;
+pub use fingerprint::Fingerprint;
+use lmorandomizer_shared::tracker::LocationManifest;
 use log::trace;
+pub use multiworld::{MultiworldOptions, randomize_multiworld};
 use randomize_items::randomize_items;
+pub use sealed_spoiler_log::{SealedSpoilerLog, SpoilerLock};
 pub use spoiler_log::SpoilerLog;
 use storage::{Storage, create_source::create_source};
+use validate_script::count_mismatches;
+pub use validate_script::validate_script;
 
 use crate::{
-    dataset::{
-        NIGHT_SURFACE_CHEST_COUNT, NIGHT_SURFACE_SEAL_COUNT, NIGHT_SURFACE_SUB_WEAPON_COUNT,
-        TRUE_SHRINE_OF_THE_MOTHER_SEAL_COUNT, game_structure::GameStructure,
-    },
+    dataset::game_structure::GameStructure,
     script::{
         data::script::Script,
-        editor::{add_starting_items::add_starting_items, normalize_shuriken_sale},
-        enums::Rom,
-        file::scriptconverter::{build_script_dat, read_script_dat},
+        editor::{
+            FlagAllocator, FlagUsage, PatchPhase, ScriptPatch,
+            add_starting_items::add_starting_items, apply_script_patches, builtin_script_patches,
+            remap_boots_flag, remap_late_game_duplicate_flags,
+        },
+        enums::{FieldNumber, Rom},
+        file::{
+            datpatch::create_dat_patch,
+            scriptconverter::{build_script_dat, parse_script_dat, read_script_dat},
+        },
     },
 };
 
-pub fn assert_eq_elem_count(source: &Storage, script: &Script) {
-    let main_weapon_count = source.main_weapons.len();
-    debug_assert_eq!(main_weapon_count, script.main_weapons().count());
-    let sub_weapon_count = source.sub_weapons.len() + NIGHT_SURFACE_SUB_WEAPON_COUNT;
-    debug_assert_eq!(sub_weapon_count, script.sub_weapons().count());
-    let chest_count = source.chests.len() + NIGHT_SURFACE_CHEST_COUNT;
-    debug_assert_eq!(chest_count, script.chests().count());
-    let seal_count =
-        source.seals.len() + TRUE_SHRINE_OF_THE_MOTHER_SEAL_COUNT + NIGHT_SURFACE_SEAL_COUNT;
-    debug_assert_eq!(seal_count, script.seals().count());
+pub fn assert_eq_elem_count(source: &Storage, script: &Script, options: &RandomizeOptions) {
+    let mismatches = count_mismatches(source, script, options);
+    debug_assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+fn default_priority_items() -> Vec<String> {
+    [
+        "handScanner",
+        "shellHorn",
+        "holyGrail",
+        "gameMaster",
+        "glyphReader",
+    ]
+    .map(|x| x.to_owned())
+    .to_vec()
+}
+
+fn default_early_fields() -> Vec<FieldNumber> {
+    vec![FieldNumber::Surface, FieldNumber::GateOfGuidance]
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[repr(u8)]
+pub enum MapPlacement {
+    /// 地図はそれぞれ自分のフィールドに置く
+    #[default]
+    OwnField,
+    /// 地図を他のアイテムと混ぜる
+    Anywhere,
+    /// 地図は元の宝箱に置いたままにする
+    Vanilla,
+    /// 地図を取り除き、その宝箱からは代わりに `FILLER_COIN_AMOUNT` 枚のコインを出す。
+    /// どの宝箱かはスポイラーログに書く
+    Removed,
 }
 
 #[derive(serde::Deserialize)]
@@ -43,33 +83,113 @@ pub struct RandomizeOptions {
     pub shuffle_secret_roms: bool,
     pub need_glitches: bool,
     pub absolutely_shuffle: bool,
+    /// 通常のスフィアより先に `early_fields` へ置くアイテム
+    #[serde(default = "default_priority_items")]
+    pub priority_items: Vec<String>,
+    #[serde(default = "default_early_fields")]
+    pub early_fields: Vec<FieldNumber>,
+    #[serde(default)]
+    pub map_placement: MapPlacement,
+    /// 夜の地上と真・母の祠の複製を別のスポットとして扱う
+    #[serde(default)]
+    pub shuffle_late_game_duplicates: bool,
+    /// ランダマイズの前後にスクリプトへ適用するユーザーの調整
+    #[serde(default)]
+    pub patches: Vec<ScriptPatch>,
+    /// ハッシュが未知の script.dat も、構造の検証を通れば受け付ける
+    #[serde(default)]
+    pub allow_modded_script: bool,
+    /// レース用に、スポイラーログを平文で書かずに封印する
+    #[serde(default)]
+    pub spoiler_lock: Option<SpoilerLock>,
 }
 
-pub fn randomize(
+impl Default for RandomizeOptions {
+    fn default() -> Self {
+        Self {
+            seed: String::new(),
+            shuffle_secret_roms: false,
+            need_glitches: false,
+            absolutely_shuffle: false,
+            priority_items: default_priority_items(),
+            early_fields: default_early_fields(),
+            map_placement: MapPlacement::default(),
+            shuffle_late_game_duplicates: false,
+            patches: Vec::new(),
+            allow_modded_script: false,
+            spoiler_lock: None,
+        }
+    }
+}
+
+/// シャッフルに依らない書き換えを済ませた script と、空きフラグの割り当て
+fn prepare_script(
     script_dat: &[u8],
-    game_structure: GameStructure,
+    source: &Storage,
     options: &RandomizeOptions,
-) -> Result<(Vec<u8>, SpoilerLog)> {
+) -> Result<(Script, FlagAllocator)> {
     let start = std::time::Instant::now();
-    let mut script = read_script_dat(script_dat)?;
+    let mut script = if options.allow_modded_script {
+        parse_script_dat(script_dat)?
+    } else {
+        read_script_dat(script_dat)?
+    };
     trace!("Read script.dat in {:?}", start.elapsed());
 
-    normalize_shuriken_sale(&mut script.talks);
-
-    let source = create_source(&game_structure, options)?;
+    apply_script_patches(
+        &mut script,
+        &options.patches,
+        PatchPhase::BeforeRandomization,
+    )?;
+    apply_script_patches(
+        &mut script,
+        &builtin_script_patches(),
+        PatchPhase::BeforeRandomization,
+    )?;
+    let mut flag_allocator = FlagAllocator::new(FlagUsage::new(&script));
+    remap_boots_flag(&mut script.worlds, &mut flag_allocator)?;
+    if options.shuffle_late_game_duplicates {
+        remap_late_game_duplicate_flags(&mut script.worlds, &mut flag_allocator)?;
+    }
 
-    if cfg!(debug_assertions) {
+    if options.allow_modded_script {
+        validate_script(source, &script, options)?;
+    } else if cfg!(debug_assertions) {
         let start = std::time::Instant::now();
-        assert_eq_elem_count(&source, &script);
+        assert_eq_elem_count(source, &script, options);
         trace!("assert_eq_elem_count {:?}", start.elapsed());
     }
+    Ok((script, flag_allocator))
+}
+
+fn finish_script(mut script: Script, options: &RandomizeOptions) -> Result<Vec<u8>> {
+    apply_script_patches(
+        &mut script,
+        &options.patches,
+        PatchPhase::AfterRandomization,
+    )?;
 
     let start = std::time::Instant::now();
-    let spoiler_log = randomize_items(&mut script, &source, options)?;
+    let dat = build_script_dat(&script);
+    trace!("Built script.dat in {:?}", start.elapsed());
+    Ok(dat)
+}
+
+pub fn randomize(
+    script_dat: &[u8],
+    game_structure: GameStructure,
+    options: &RandomizeOptions,
+) -> Result<(Vec<u8>, SpoilerLog, Fingerprint, LocationManifest)> {
+    let source = create_source(&game_structure, options)?;
+    let (mut script, mut flag_allocator) = prepare_script(script_dat, &source, options)?;
+
+    let start = std::time::Instant::now();
+    let (spoiler_log, fingerprint, locations) = randomize_items(&mut script, &source, options)?;
     if false {
         let worlds = take(&mut script.worlds);
         script.worlds = add_starting_items(
             worlds,
+            &mut flag_allocator,
             &[
                 // crate::script::data::items::Equipment::Boots,
                 // crate::script::data::items::Equipment::Feather,
@@ -81,13 +201,22 @@ pub fn randomize(
             &[
                 // crate::script::data::items::SubWeapon::Pistol
             ],
-        );
+        )?;
     }
     trace!("Randomized items in {:?}", start.elapsed());
 
-    let start = std::time::Instant::now();
-    let dat = build_script_dat(&script);
-    trace!("Built script.dat in {:?}", start.elapsed());
+    let dat = finish_script(script, options)?;
+    Ok((dat, spoiler_log.to_owned(), fingerprint, locations))
+}
 
-    Ok((dat, spoiler_log.to_owned()))
+/// 配布用に、`script.dat` 全体ではなく元のファイルとの差分を返す
+pub fn randomize_patch(
+    script_dat: &[u8],
+    game_structure: GameStructure,
+    options: &RandomizeOptions,
+) -> Result<(Vec<u8>, SpoilerLog, Fingerprint, LocationManifest)> {
+    let (dat, spoiler_log, fingerprint, locations) =
+        randomize(script_dat, game_structure, options)?;
+    let patch = create_dat_patch(script_dat, &dat, &fingerprint.to_string());
+    Ok((patch, spoiler_log, fingerprint, locations))
 }
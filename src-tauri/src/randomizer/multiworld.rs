@@ -0,0 +1,572 @@
+use std::{
+    collections::BTreeMap,
+    mem::{replace, take},
+};
+
+use anyhow::{Result, anyhow, bail};
+use lmorandomizer_shared::{
+    lmo::GameItem,
+    multiworld::{MultiworldEntry, MultiworldManifest},
+    tracker::LocationManifest,
+};
+use num_traits::FromPrimitive;
+use rand::{Rng, seq::SliceRandom};
+
+use crate::{
+    dataset::game_structure::GameStructure,
+    script::{
+        consts::SAVE_FLAG_RANGE,
+        data::{item, script::Script},
+        editor::apply_storage,
+        enums::{ChestItem, FieldNumber},
+    },
+};
+
+use super::{
+    Fingerprint, RandomizeOptions, SpoilerLog, finish_script,
+    location_manifest::create_location_manifest,
+    prepare_script,
+    randomize_items::shuffle,
+    spoiler::{initial_state, make_rng, regions::Regions, spots::SpotRef},
+    spoiler_log::{CheckpointRef, SphereRef, SpoilerLogRef},
+    storage::{
+        Event, ShopRef, Storage,
+        create_source::create_source,
+        item::{Item, ItemSource, StrategyFlag},
+    },
+};
+
+fn default_foreign_items() -> usize {
+    20
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiworldOptions {
+    pub seed: String,
+    /// 並び順がワールドの番号
+    pub players: Vec<RandomizeOptions>,
+    /// 各ワールドに置く、ほかのプレイヤーのアイテムのおおよその数
+    #[serde(default = "default_foreign_items")]
+    pub foreign_items: usize,
+}
+
+type ChestKey = (FieldNumber, ChestItem);
+
+/// 別のワールドのプレイヤー宛てのアイテムを置いたチェスト
+#[derive(Clone, Debug)]
+pub struct ForeignChest {
+    pub world: usize,
+    pub key: ChestKey,
+    pub recipient: usize,
+}
+
+pub struct MultiworldPlan {
+    /// `ForeignChest` の中身は、受け取るワールドのアイテム
+    pub shuffled: Vec<Storage>,
+    pub spoiler_logs: Vec<SpoilerLog>,
+    pub foreign_chests: Vec<ForeignChest>,
+}
+
+/// 普通のチェストのアイテムだけを交換する
+fn is_tradable(item: &Item) -> bool {
+    !item.name.is_map()
+        && !item.name.is_consumable()
+        && !item.name.is_late_game_duplicate()
+        && !matches!(item.src, ItemSource::Seal(_))
+}
+
+fn pick_chest(
+    rng: &mut impl Rng,
+    storage: &Storage,
+    world: usize,
+    foreign_chests: &[ForeignChest],
+) -> Option<ChestKey> {
+    let candidates: Vec<_> = storage
+        .chests
+        .iter()
+        .filter(|(_, chest)| is_tradable(&chest.item))
+        .filter(|(key, _)| {
+            foreign_chests
+                .iter()
+                .all(|x| x.world != world || x.key != **key)
+        })
+        .map(|(key, _)| *key)
+        .collect();
+    candidates.choose(rng).copied()
+}
+
+fn swap_chest_items(
+    shuffled: &mut [Storage],
+    (a, key_a): (usize, ChestKey),
+    (b, key_b): (usize, ChestKey),
+) {
+    let item_a = shuffled[a].chests[&key_a].item.clone();
+    let item_b = replace(
+        &mut shuffled[b].chests.get_mut(&key_b).unwrap().item,
+        item_a,
+    );
+    shuffled[a].chests.get_mut(&key_a).unwrap().item = item_b;
+}
+
+fn recipient(world: usize, key: &ChestKey, foreign_chests: &[ForeignChest]) -> usize {
+    foreign_chests
+        .iter()
+        .find(|x| x.world == world && x.key == *key)
+        .map_or(world, |x| x.recipient)
+}
+
+/// 全ワールドを同時に探索し、取ったアイテムは受け取るワールドの状態に加える。
+/// ワールドごとに探索の各周で取ったものと、全ワールドを攻略できるかを返す。
+/// `labels` はスポイラーログに書く、チェストのアイテムの表示を差し替える
+fn explore<'a>(
+    shuffled: &'a [Storage],
+    labels: &'a [BTreeMap<ChestKey, Item>],
+    players: &[RandomizeOptions],
+    foreign_chests: &[ForeignChest],
+) -> (Vec<Vec<SphereRef<'a>>>, bool) {
+    let all_regions: Vec<_> = shuffled
+        .iter()
+        .map(|storage| Regions::new(storage.regions.iter().collect()))
+        .collect();
+    let mut states: Vec<_> = all_regions
+        .iter()
+        .zip(players)
+        .map(|(regions, options)| initial_state(regions, options))
+        .collect();
+    let mut remaining: Vec<Vec<(SpotRef, usize, &Item, CheckpointRef)>> = shuffled
+        .iter()
+        .zip(labels)
+        .enumerate()
+        .map(|(world, (storage, labels))| {
+            let checkpoint = |spot, item| {
+                (
+                    spot,
+                    world,
+                    item,
+                    CheckpointRef::from_field_spot_item(spot, item),
+                )
+            };
+            let chests = storage.chests.iter().map(|(key, x)| {
+                let recipient = recipient(world, key, foreign_chests);
+                let spot = SpotRef::Chest(&x.spot);
+                let label = labels.get(key).unwrap_or(&x.item);
+                let checkpoint = CheckpointRef::from_field_spot_item(spot, label);
+                (spot, recipient, &x.item, checkpoint)
+            });
+            let shops = storage.shops.iter().map(|x| {
+                let checkpoint = CheckpointRef::Shop(ShopRef {
+                    spot: &x.spot,
+                    idx: x.idx,
+                    item: &x.item,
+                });
+                (SpotRef::Shop(&x.spot), world, &x.item, checkpoint)
+            });
+            storage
+                .main_weapons
+                .values()
+                .map(|x| checkpoint(SpotRef::MainWeapon(&x.spot), &x.item))
+                .chain(
+                    storage
+                        .sub_weapons
+                        .values()
+                        .map(|x| checkpoint(SpotRef::SubWeapon(&x.spot), &x.item)),
+                )
+                .chain(chests)
+                .chain(
+                    storage
+                        .seals
+                        .values()
+                        .map(|x| checkpoint(SpotRef::Seal(&x.spot), &x.item)),
+                )
+                .chain(
+                    storage
+                        .roms
+                        .values()
+                        .map(|x| checkpoint(SpotRef::Rom(&x.spot), &x.item)),
+                )
+                .chain(
+                    storage
+                        .talks
+                        .iter()
+                        .map(|x| checkpoint(SpotRef::Talk(&x.spot), &x.item)),
+                )
+                .chain(shops)
+                .collect()
+        })
+        .collect();
+    let mut events: Vec<Vec<&Event>> = shuffled
+        .iter()
+        .map(|storage| storage.events.iter().collect())
+        .collect();
+    let mut spheres: Vec<Vec<SphereRef>> = shuffled.iter().map(|_| Vec::new()).collect();
+
+    loop {
+        let mut progressed = false;
+        for world in 0..shuffled.len() {
+            let state = &mut states[world];
+            state.explore_regions(&all_regions[world]);
+            let (achieved, unachieved): (Vec<_>, Vec<_>) =
+                take(&mut events[world]).into_iter().partition(|event| {
+                    if let Some(region) = &event.region {
+                        state.is_reachable(region, event.requirements.as_ref())
+                    } else {
+                        state.is_reachable_without_region(event.requirements.as_ref())
+                    }
+                });
+            events[world] = unachieved;
+            let mut checkpoints = Vec::new();
+            for event in achieved {
+                state.insert_flag(&event.name);
+                checkpoints.push(CheckpointRef::Event(&event.name));
+            }
+            let (reached, unreached): (Vec<_>, Vec<_>) = take(&mut remaining[world])
+                .into_iter()
+                .partition(|(spot, ..)| state.is_reachable(spot.region(), spot.requirements()));
+            remaining[world] = unreached;
+            for (_, recipient, item, checkpoint) in reached {
+                states[recipient].insert_flag(&item.name);
+                checkpoints.push(checkpoint);
+            }
+            if !checkpoints.is_empty() {
+                spheres[world].push(SphereRef::new(Vec::new(), checkpoints));
+                progressed = true;
+            }
+        }
+        if !progressed {
+            break;
+        }
+    }
+    // 消耗品の店は単独のワールドでも必須ではない
+    let completable = remaining
+        .iter()
+        .flatten()
+        .all(|(_, _, item, _)| item.name.is_consumable());
+    (spheres, completable)
+}
+
+pub fn is_completable(
+    shuffled: &[Storage],
+    players: &[RandomizeOptions],
+    foreign_chests: &[ForeignChest],
+) -> bool {
+    let labels = vec![BTreeMap::new(); shuffled.len()];
+    explore(shuffled, &labels, players, foreign_chests).1
+}
+
+/// 交換後の配置からスポイラーログを作り直す。別のワールド宛てのアイテムには宛先を書き添える
+fn create_spoiler_logs(
+    shuffled: &[Storage],
+    initial_logs: Vec<SpoilerLogRef>,
+    players: &[RandomizeOptions],
+    foreign_chests: &[ForeignChest],
+) -> Vec<SpoilerLog> {
+    let labels: Vec<BTreeMap<_, _>> = (0..shuffled.len())
+        .map(|world| {
+            foreign_chests
+                .iter()
+                .filter(|x| x.world == world)
+                .map(|x| {
+                    let item = &shuffled[world].chests[&x.key].item;
+                    let name = format!("{} (world{})", item.name.get(), x.recipient);
+                    let label = Item {
+                        src: item.src,
+                        name: StrategyFlag::new(name),
+                    };
+                    (x.key, label)
+                })
+                .collect()
+        })
+        .collect();
+    let (spheres, _) = explore(shuffled, &labels, players, foreign_chests);
+    initial_logs
+        .into_iter()
+        .zip(spheres)
+        .map(|(initial_log, spheres)| {
+            // 進行とは別に置いた地図は専用の節にあり、交換では変わらない
+            let separate_maps = !initial_log.maps.is_empty();
+            let progression = spheres
+                .into_iter()
+                .map(|sphere| {
+                    sphere
+                        .into_inner()
+                        .into_iter()
+                        .filter(|x| !separate_maps || !x.name().is_map())
+                        .collect::<Vec<_>>()
+                })
+                .filter(|checkpoints| !checkpoints.is_empty())
+                .map(|checkpoints| SphereRef::new(Vec::new(), checkpoints))
+                .collect();
+            SpoilerLogRef {
+                priority_items: initial_log.priority_items,
+                unplaced_priority_items: initial_log.unplaced_priority_items,
+                progression,
+                maps: initial_log.maps,
+                removed_maps: initial_log.removed_maps,
+            }
+            .to_owned()
+        })
+        .collect()
+}
+
+/// 各ワールドを単独でシャッフルしてから、全ワールドを攻略できる範囲でチェストの中身を交換する
+pub fn plan_multiworld(
+    game_structure: &GameStructure,
+    options: &MultiworldOptions,
+) -> Result<MultiworldPlan> {
+    let players = &options.players;
+    if players.len() < 2 {
+        bail!("Multiworld needs at least 2 players");
+    }
+    let sources = players
+        .iter()
+        .map(|player| create_source(game_structure, player))
+        .collect::<Result<Vec<_>>>()?;
+    let (mut shuffled, initial_logs): (Vec<_>, Vec<_>) = sources
+        .iter()
+        .zip(players)
+        .map(|(source, player)| shuffle(source, player))
+        .unzip();
+
+    let mut rng = make_rng(&options.seed);
+    let mut foreign_chests = Vec::new();
+    let mut worlds: Vec<_> = (0..players.len()).collect();
+    // 1回の交換で2つのワールドに1つずつ置かれる
+    for _ in 0..options.foreign_items * players.len() / 2 {
+        worlds.shuffle(&mut rng);
+        let (a, b) = (worlds[0], worlds[1]);
+        let Some(key_a) = pick_chest(&mut rng, &shuffled[a], a, &foreign_chests) else {
+            continue;
+        };
+        let Some(key_b) = pick_chest(&mut rng, &shuffled[b], b, &foreign_chests) else {
+            continue;
+        };
+        swap_chest_items(&mut shuffled, (a, key_a), (b, key_b));
+        foreign_chests.push(ForeignChest {
+            world: a,
+            key: key_a,
+            recipient: b,
+        });
+        foreign_chests.push(ForeignChest {
+            world: b,
+            key: key_b,
+            recipient: a,
+        });
+        if !is_completable(&shuffled, players, &foreign_chests) {
+            foreign_chests.truncate(foreign_chests.len() - 2);
+            swap_chest_items(&mut shuffled, (a, key_a), (b, key_b));
+        }
+    }
+    let spoiler_logs = create_spoiler_logs(&shuffled, initial_logs, players, &foreign_chests);
+    Ok(MultiworldPlan {
+        shuffled,
+        spoiler_logs,
+        foreign_chests,
+    })
+}
+
+fn from_id<T: FromPrimitive>(id: u8) -> Result<T> {
+    T::from_u8(id).ok_or_else(|| anyhow!("unknown item id: {id}"))
+}
+
+fn to_game_item(item: &Item, script: &Script) -> Result<GameItem> {
+    Ok(match item::Item::new(&item.src, script)? {
+        item::Item::MainWeapon(x) => GameItem::MainWeapon(from_id(x.content as u8)?),
+        item::Item::SubWeapon(x) => GameItem::SubWeapon {
+            sub_weapon: from_id(x.content as u8)?,
+            amount: x.amount,
+        },
+        item::Item::Equipment(x) => GameItem::Equipment(from_id(x.content as u8)?),
+        item::Item::Rom(x) => GameItem::Rom(from_id(x.content as u8)?),
+        item::Item::Seal(x) => bail!("seal cannot be sent to another world: {}", x.content),
+    })
+}
+
+/// ワールドの番号順に、各プレイヤーの `script.dat` などを返す
+#[allow(clippy::type_complexity)]
+pub fn randomize_multiworld(
+    script_dat: &[u8],
+    game_structure: GameStructure,
+    options: &MultiworldOptions,
+) -> Result<(
+    Vec<(Vec<u8>, SpoilerLog, Fingerprint, LocationManifest)>,
+    MultiworldManifest,
+)> {
+    let MultiworldPlan {
+        shuffled,
+        spoiler_logs,
+        foreign_chests,
+    } = plan_multiworld(&game_structure, options)?;
+    let mut scripts = shuffled
+        .iter()
+        .zip(&options.players)
+        .map(|(storage, player)| prepare_script(script_dat, storage, player))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut placeholder_flags = vec![BTreeMap::new(); shuffled.len()];
+    let mut entries = Vec::new();
+    for foreign_chest in &foreign_chests {
+        let (world, recipient) = (foreign_chest.world, foreign_chest.recipient);
+        let location_flag = scripts[world].1.allocate(SAVE_FLAG_RANGE)?;
+        placeholder_flags[world].insert(foreign_chest.key, location_flag);
+        let chest = &shuffled[world].chests[&foreign_chest.key];
+        let recipient_script = &scripts[recipient].0;
+        entries.push(MultiworldEntry {
+            world: world as u32,
+            location: chest.spot.to_string(),
+            location_flag,
+            recipient: recipient as u32,
+            item: to_game_item(&chest.item, recipient_script)?,
+            item_flag: item::Item::new(&chest.item.src, recipient_script)?.flag(),
+        });
+    }
+
+    let mut worlds = Vec::new();
+    for (world, ((mut script, _), spoiler_log)) in scripts.into_iter().zip(spoiler_logs).enumerate()
+    {
+        let storage = &shuffled[world];
+        let placeholder_flags = &placeholder_flags[world];
+        let mut locations = create_location_manifest(&script, storage)?;
+        for (key, &flag) in placeholder_flags {
+            let name = storage.chests[key].spot.to_string();
+            if let Some(location) = locations.locations.iter_mut().find(|x| x.name == name) {
+                location.flags = vec![flag];
+            }
+        }
+        apply_storage(&mut script, storage, placeholder_flags)?;
+        let fingerprint = Fingerprint::new(storage);
+        let dat = finish_script(script, &options.players[world])?;
+        worlds.push((dat, spoiler_log, fingerprint, locations));
+    }
+    let fingerprints = worlds.iter().map(|x| x.2.to_string()).collect();
+    Ok((worlds, MultiworldManifest::new(fingerprints, entries)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{
+        app::read_game_structure_files_debug,
+        script::{
+            data::item::ChestItem,
+            file::scriptconverter::{parse_script_dat, read_script_dat},
+            fixture,
+        },
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_plan_multiworld() -> Result<()> {
+        let game_structure = read_game_structure_files_debug().await?;
+        let player = |seed: &str| RandomizeOptions {
+            seed: seed.to_owned(),
+            ..Default::default()
+        };
+        let options = MultiworldOptions {
+            seed: "test".to_owned(),
+            players: vec![player("a"), player("b"), player("c")],
+            foreign_items: 4,
+        };
+        let plan = plan_multiworld(&game_structure, &options)?;
+        let foreign_chests = &plan.foreign_chests;
+        assert!(!foreign_chests.is_empty());
+        assert!(foreign_chests.iter().all(|x| x.world != x.recipient));
+        for world in 0..options.players.len() {
+            let sent = foreign_chests.iter().filter(|x| x.world == world).count();
+            let received = foreign_chests
+                .iter()
+                .filter(|x| x.recipient == world)
+                .count();
+            assert_eq!(sent, received);
+        }
+        assert!(is_completable(
+            &plan.shuffled,
+            &options.players,
+            foreign_chests
+        ));
+        // スポイラーログは交換後の配置で書かれる
+        for foreign_chest in foreign_chests {
+            let chest = &plan.shuffled[foreign_chest.world].chests[&foreign_chest.key];
+            let line = format!(
+                "{} = {} (world{})",
+                chest.spot,
+                chest.item.name.get(),
+                foreign_chest.recipient
+            );
+            let spoiler_log = plan.spoiler_logs[foreign_chest.world].to_string();
+            assert!(spoiler_log.lines().any(|x| x == line), "{line}");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the vanilla script.dat"]
+    async fn test_randomize_multiworld() -> Result<()> {
+        let game_structure = read_game_structure_files_debug().await?;
+        let player = |seed: &str| RandomizeOptions {
+            seed: seed.to_owned(),
+            ..Default::default()
+        };
+        let options = MultiworldOptions {
+            seed: "test".to_owned(),
+            players: vec![player("a"), player("b")],
+            foreign_items: 4,
+        };
+        let script_dat = fixture::vanilla_script_dat()?;
+        let vanilla = read_script_dat(&script_dat)?;
+
+        // ゲームに与えるアイテムが元のアイテムと同じ番号になる
+        let source = create_source(&game_structure, &options.players[0])?;
+        for item in source.all_items() {
+            let game_item = match to_game_item(item, &vanilla) {
+                Ok(ok) => ok,
+                Err(_) if matches!(item.src, ItemSource::Seal(_)) => continue,
+                Err(err) => return Err(err),
+            };
+            let ids = match (item::Item::new(&item.src, &vanilla)?, game_item) {
+                (item::Item::MainWeapon(x), GameItem::MainWeapon(y)) => (x.content as u8, y as u8),
+                (item::Item::SubWeapon(x), GameItem::SubWeapon { sub_weapon, amount }) => {
+                    assert_eq!(x.amount, amount);
+                    (x.content as u8, sub_weapon as u8)
+                }
+                (item::Item::Equipment(x), GameItem::Equipment(y)) => (x.content as u8, y as u8),
+                (item::Item::Rom(x), GameItem::Rom(y)) => (x.content as u8, y as u8),
+                (_, y) => panic!("{} became {y:?}", item.name.get()),
+            };
+            assert_eq!(ids.0, ids.1, "{}", item.name.get());
+        }
+
+        let (worlds, manifest) = randomize_multiworld(&script_dat, game_structure, &options)?;
+        assert!(!manifest.entries.is_empty());
+        for (world, (dat, _, _, locations)) in worlds.iter().enumerate() {
+            let entries: Vec<_> = manifest
+                .entries
+                .iter()
+                .filter(|x| x.world as usize == world)
+                .collect();
+            let flags: BTreeSet<_> = entries.iter().map(|x| x.location_flag).collect();
+            assert_eq!(flags.len(), entries.len());
+
+            let script = parse_script_dat(dat)?;
+            let placeholders: BTreeSet<_> = script
+                .chests()
+                .filter_map(|x| match x.item() {
+                    ChestItem::None(flag) => u16::try_from(*flag).ok(),
+                    _ => None,
+                })
+                .collect();
+            for entry in entries {
+                assert!(placeholders.contains(&entry.location_flag), "{entry:?}");
+                let location = locations
+                    .locations
+                    .iter()
+                    .find(|x| x.name == entry.location)
+                    .unwrap();
+                assert_eq!(location.flags, [entry.location_flag]);
+            }
+        }
+        Ok(())
+    }
+}
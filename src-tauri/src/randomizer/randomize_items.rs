@@ -1,16 +1,23 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 use anyhow::Result;
+use lmorandomizer_shared::tracker::LocationManifest;
 use log::{info, trace};
 use rand::Rng;
 
 use crate::{
-    randomizer::spoiler::{items::Items, regions::Regions, spots::Spots},
+    randomizer::spoiler::{
+        items::{Items, validate_priority_item_names},
+        regions::Regions,
+        spots::Spots,
+    },
     script::{data::script::Script, editor::apply_storage},
 };
 
 use super::{
     RandomizeOptions,
+    fingerprint::Fingerprint,
+    location_manifest::create_location_manifest,
     spoiler::{make_rng, spoiler},
     spoiler_log::{CheckpointRef, SpoilerLogRef},
     storage::{Storage, item::StrategyFlag},
@@ -20,20 +27,23 @@ pub fn randomize_items<'a>(
     script: &mut Script,
     source: &'a Storage,
     options: &RandomizeOptions,
-) -> Result<SpoilerLogRef<'a>> {
+) -> Result<(SpoilerLogRef<'a>, Fingerprint, LocationManifest)> {
     let start = std::time::Instant::now();
     assert_unique(source);
     trace!("Assertion in {:?}", start.elapsed());
 
+    validate_priority_item_names(source, &options.priority_items)?;
+
     let start = std::time::Instant::now();
     let (shuffled, spoiler_log) = shuffle(source, options);
     trace!("Randomized items in {:?}", start.elapsed());
 
     let start = std::time::Instant::now();
     assert_unique(&shuffled);
-    apply_storage(script, &shuffled)?;
+    let locations = create_location_manifest(script, &shuffled)?;
+    apply_storage(script, &shuffled, &BTreeMap::new())?;
     trace!("Replaced items in {:?}", start.elapsed());
-    Ok(spoiler_log)
+    Ok((spoiler_log, Fingerprint::new(&shuffled), locations))
 }
 
 fn create_shuffled_storage(source: &Storage, spoiler_log: &SpoilerLogRef) -> Storage {
@@ -61,8 +71,8 @@ fn create_shuffled_storage(source: &Storage, spoiler_log: &SpoilerLogRef) -> Sto
                 storage.chests.get_mut(&key).unwrap().item = chest.item.clone();
             }
             CheckpointRef::Seal(seal) => {
-                let content = seal.spot.seal();
-                storage.seals.get_mut(&content).unwrap().item = seal.item.clone();
+                let key = (seal.spot.seal(), seal.spot.region().field_number());
+                storage.seals.get_mut(&key).unwrap().item = seal.item.clone();
             }
             CheckpointRef::Shop(shop) => {
                 let spot = &mut storage
@@ -98,7 +108,7 @@ fn random_spoiler<'a>(
 ) -> SpoilerLogRef<'a> {
     let start = std::time::Instant::now();
     let all_regions = &Regions::new(source.regions.iter().collect());
-    let items = &Items::new(source);
+    let items = &Items::new(source, &options.priority_items, options.map_placement);
     let spots = &Spots::new(source);
     debug_assert_eq!(
         spots.shops.len() - items.consumable_items().len(),
@@ -136,7 +146,10 @@ fn random_spoiler<'a>(
     })
 }
 
-fn shuffle<'a>(source: &'a Storage, options: &RandomizeOptions) -> (Storage, SpoilerLogRef<'a>) {
+pub fn shuffle<'a>(
+    source: &'a Storage,
+    options: &RandomizeOptions,
+) -> (Storage, SpoilerLogRef<'a>) {
     let mut rng = make_rng(&options.seed);
     let spoiler_log = random_spoiler(&mut rng, source, options);
     let storage = create_shuffled_storage(source, &spoiler_log);
@@ -198,20 +211,26 @@ mod tests {
             shuffle_secret_roms: true,
             need_glitches: false,
             absolutely_shuffle: false,
+            ..Default::default()
         };
         let source = create_source(&game_structure, &opts)?;
         let (shuffled, spoiler_log) = shuffle(&source, &opts);
 
         let shuffled_str = format!("{:?}", shuffled);
         let shuffled_hash = hex::encode(sha3::Sha3_512::digest(shuffled_str));
-        const EXPECTED_SHUFFLED_HASH: &str = "ad3ee870c69287f1de7af5c4c274683d239b794d62218abd3e424f86ae3cdb5e8039870c7aff86339f9546b77f3a37c8c2f9acb4d5a2384a3e18ae125a4685a8";
+        const EXPECTED_SHUFFLED_HASH: &str = "3eac381df333128ecc32c6d0f6642891192a09a566527ac9dd48f1daba99624380570ea75daeea2d985ebf533c810f492199aeb7f1bd5eb23cf0d6934f34ae4a";
         assert_eq!(shuffled_hash, EXPECTED_SHUFFLED_HASH);
 
         let spoiler_log_str = format!("{}", spoiler_log.to_owned());
         let spoiler_log_hash = hex::encode(sha3::Sha3_512::digest(spoiler_log_str));
-        const EXPECTED_SPOILER_LOG_HASH: &str = "a7e32162cb4f025c220d8a74c123a29d0ccc4b2f264172c37efd3a5fe618671807f29f60c2e4612fd89682df9fadd26c4239dca77b4470de6240c2501206919e";
+        const EXPECTED_SPOILER_LOG_HASH: &str = "c203a3f32c6071d6e8c2a8ce553094becd28177dfd8e4773411671a78f7df417044c017b55b57075d478f5340ca5acf6ced108d81d7851e2f66d36de9e47dd6a";
         assert_eq!(spoiler_log_hash, EXPECTED_SPOILER_LOG_HASH);
 
+        assert_eq!(
+            Fingerprint::new(&shuffled).to_string(),
+            "Ankh Jewel, Axe, Boots, Crystal Skull, Diary"
+        );
+
         Ok(())
     }
 
@@ -224,13 +243,211 @@ mod tests {
                 shuffle_secret_roms: true,
                 need_glitches: true,
                 absolutely_shuffle: false,
+                ..Default::default()
+            };
+            let source = create_source(&game_structure, &opts)?;
+            let (_, spoiler_log) = shuffle(&source, &opts);
+            assert_eq!(
+                spoiler_log.count_checkpoints(),
+                source.all_items().count() + source.events.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shuffle_custom_priority_items() -> Result<()> {
+        let game_structure = read_game_structure_files_debug().await?;
+        let beginner = ["handScanner", "feather", "grappleClaw"].map(|x| x.to_owned());
+        for (i, priority_items) in [vec![], beginner.to_vec()].into_iter().enumerate() {
+            let opts = RandomizeOptions {
+                seed: i.to_string(),
+                shuffle_secret_roms: true,
+                need_glitches: true,
+                priority_items,
+                ..Default::default()
             };
             let source = create_source(&game_structure, &opts)?;
+            validate_priority_item_names(&source, &opts.priority_items)?;
             let (_, spoiler_log) = shuffle(&source, &opts);
             assert_eq!(
                 spoiler_log.count_checkpoints(),
                 source.all_items().count() + source.events.len()
             );
+            assert_eq!(
+                spoiler_log.priority_items.len() + spoiler_log.unplaced_priority_items.len(),
+                opts.priority_items.len()
+            );
+            let log = spoiler_log.to_owned().to_string();
+            assert_eq!(
+                log.contains("[Priority Items]"),
+                !spoiler_log.priority_items.is_empty()
+            );
+        }
+
+        let opts = RandomizeOptions {
+            priority_items: vec!["map:gateOfGuidance".to_owned()],
+            ..Default::default()
+        };
+        let source = create_source(&game_structure, &opts)?;
+        assert!(validate_priority_item_names(&source, &opts.priority_items).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shuffle_map_placements() -> Result<()> {
+        use crate::randomizer::MapPlacement;
+
+        let game_structure = read_game_structure_files_debug().await?;
+        for map_placement in [
+            MapPlacement::OwnField,
+            MapPlacement::Anywhere,
+            MapPlacement::Vanilla,
+            MapPlacement::Removed,
+        ] {
+            let opts = RandomizeOptions {
+                seed: "test".to_owned(),
+                shuffle_secret_roms: true,
+                need_glitches: true,
+                map_placement,
+                ..Default::default()
+            };
+            let source = create_source(&game_structure, &opts)?;
+            let (shuffled, spoiler_log) = shuffle(&source, &opts);
+            assert_eq!(
+                spoiler_log.count_checkpoints(),
+                source.all_items().count() + source.events.len()
+            );
+            let map_count = source
+                .chests
+                .values()
+                .filter(|x| x.item.name.is_map())
+                .count();
+            match map_placement {
+                MapPlacement::OwnField | MapPlacement::Vanilla => {
+                    assert_eq!(spoiler_log.maps.len(), map_count)
+                }
+                MapPlacement::Anywhere => assert!(spoiler_log.maps.is_empty()),
+                MapPlacement::Removed => {
+                    assert_eq!(map_count, 0);
+                    assert!(spoiler_log.maps.is_empty());
+                    assert!(!source.removed_maps.is_empty());
+                    assert_eq!(spoiler_log.removed_maps.len(), source.removed_maps.len());
+                    let text = spoiler_log.to_owned().to_string();
+                    assert!(text.contains("[Removed Maps]"));
+                }
+            }
+            if map_placement == MapPlacement::Vanilla {
+                for chest in source.chests.values().filter(|x| x.item.name.is_map()) {
+                    let key = (chest.spot.region().field_number(), chest.spot.item());
+                    assert_eq!(shuffled.chests[&key].item.name, chest.item.name);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shuffle_late_game_duplicates() -> Result<()> {
+        let game_structure = read_game_structure_files_debug().await?;
+        for shuffle_late_game_duplicates in [false, true] {
+            let opts = RandomizeOptions {
+                seed: "test".to_owned(),
+                shuffle_secret_roms: true,
+                need_glitches: true,
+                shuffle_late_game_duplicates,
+                ..Default::default()
+            };
+            let source = create_source(&game_structure, &opts)?;
+            assert_eq!(
+                source.has_late_game_duplicates(),
+                shuffle_late_game_duplicates
+            );
+            let (shuffled, spoiler_log) = shuffle(&source, &opts);
+            assert_eq!(
+                spoiler_log.count_checkpoints(),
+                source.all_items().count() + source.events.len()
+            );
+            assert_eq!(
+                shuffled.has_late_game_duplicates(),
+                shuffle_late_game_duplicates
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_late_game_duplicates_do_not_change_logic() -> Result<()> {
+        use crate::randomizer::spoiler::State;
+
+        let game_structure = read_game_structure_files_debug().await?;
+        let opts = RandomizeOptions {
+            shuffle_late_game_duplicates: true,
+            ..Default::default()
+        };
+        let source = create_source(&game_structure, &opts)?;
+        let duplicates: Vec<_> = source
+            .all_items()
+            .map(|x| &x.name)
+            .filter(|x| x.is_late_game_duplicate())
+            .collect();
+        assert!(duplicates.iter().any(|x| x.is_sacred_orb()));
+        assert!(
+            source
+                .seals
+                .values()
+                .any(|x| x.item.name.is_late_game_duplicate())
+        );
+
+        // 2 つ目の聖杯や封印を持っていても、どの条件の結果も変わらない
+        let region = &game_structure.regions[0];
+        let empty = State::new(region);
+        let mut with_duplicates = State::new(region);
+        for name in duplicates {
+            with_duplicates.insert_flag(name);
+        }
+        let requirements = game_structure
+            .regions
+            .iter()
+            .filter_map(|x| x.access_rule())
+            .chain(
+                game_structure
+                    .chests
+                    .iter()
+                    .filter_map(|x| x.requirements()),
+            )
+            .chain(game_structure.seals.iter().filter_map(|x| x.requirements()))
+            .chain(
+                game_structure
+                    .sub_weapon_shutters
+                    .iter()
+                    .filter_map(|x| x.requirements()),
+            )
+            .chain(
+                game_structure
+                    .main_weapon_shutters
+                    .iter()
+                    .filter_map(|x| x.requirements()),
+            )
+            .chain(
+                game_structure
+                    .roadside_roms
+                    .iter()
+                    .map(|x| x.requirements()),
+            )
+            .chain(game_structure.shops.iter().filter_map(|x| x.requirements()))
+            .chain(game_structure.talks.iter().filter_map(|x| x.requirements()));
+        for requirements in requirements {
+            assert_eq!(
+                with_duplicates.is_reachable_without_region(Some(requirements)),
+                empty.is_reachable_without_region(Some(requirements)),
+                "{:?}",
+                requirements
+            );
         }
 
         Ok(())
@@ -0,0 +1,167 @@
+use anyhow::{Result, anyhow, bail};
+use argon2::Argon2;
+use chacha20poly1305::{
+    XChaCha20Poly1305, XNonce,
+    aead::{Aead, KeyInit},
+};
+use rand::Rng;
+
+const VERSION: u32 = 1;
+const NONCE_LEN: usize = 24;
+
+/// レース用にスポイラーログを封印する方法
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum SpoilerLock {
+    /// 主催者のパスワードで開ける
+    Password { password: String },
+    /// 指定した UNIX 時刻 (秒) 以降に開ける。
+    /// 鍵はファイルに平文で含まれるので、うっかり見るのを防ぐだけで秘密は守れない
+    Timestamp { unlock_at: u64 },
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+enum SealedLock {
+    Password { salt: String },
+    Timestamp { unlock_at: u64, key: String },
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SealedSpoilerLog {
+    version: u32,
+    /// 全員が同じシードか確認するための値。封印されていない
+    pub fingerprint: String,
+    lock: SealedLock,
+    nonce: String,
+    /// XChaCha20-Poly1305 の暗号文。認証タグを末尾に含む
+    ciphertext: String,
+}
+
+/// Argon2id (既定のパラメーター) でパスワードから鍵を作る
+fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0; 32];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|err| anyhow!("Failed to derive key: {}", err))?;
+    Ok(key)
+}
+
+impl SealedSpoilerLog {
+    pub fn seal(spoiler_log: &str, lock: &SpoilerLock, fingerprint: String) -> Result<Self> {
+        let mut rng = rand::thread_rng();
+        let (key, lock) = match lock {
+            SpoilerLock::Password { password } => {
+                let salt: [u8; 16] = rng.r#gen();
+                let key = derive_key(password, &salt)?;
+                let salt = hex::encode(salt);
+                (key, SealedLock::Password { salt })
+            }
+            SpoilerLock::Timestamp { unlock_at } => {
+                let key: [u8; 32] = rng.r#gen();
+                let lock = SealedLock::Timestamp {
+                    unlock_at: *unlock_at,
+                    key: hex::encode(key),
+                };
+                (key, lock)
+            }
+        };
+        let nonce: [u8; NONCE_LEN] = rng.r#gen();
+        let ciphertext = XChaCha20Poly1305::new(&key.into())
+            .encrypt(&XNonce::from(nonce), spoiler_log.as_bytes())
+            .map_err(|err| anyhow!("Failed to seal spoiler log: {}", err))?;
+        Ok(Self {
+            version: VERSION,
+            fingerprint,
+            lock,
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    pub fn parse(json: &[u8]) -> Result<Self> {
+        let zelf: Self = serde_json::from_slice(json)?;
+        if zelf.version != VERSION {
+            bail!("Unsupported sealed spoiler log version: {}", zelf.version);
+        }
+        Ok(zelf)
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).unwrap()
+    }
+
+    /// `now` は UNIX 時刻 (秒)
+    pub fn unseal(&self, password: Option<&str>, now: u64) -> Result<String> {
+        let key: [u8; 32] = match &self.lock {
+            SealedLock::Password { salt } => {
+                let Some(password) = password else {
+                    bail!("Password is required");
+                };
+                derive_key(password, &hex::decode(salt)?)?
+            }
+            SealedLock::Timestamp { unlock_at, key } => {
+                if now < *unlock_at {
+                    bail!("Spoiler log is locked for {} more seconds", unlock_at - now);
+                }
+                hex::decode(key)?
+                    .try_into()
+                    .map_err(|_| anyhow!("Broken spoiler log key"))?
+            }
+        };
+        let nonce: [u8; NONCE_LEN] = hex::decode(&self.nonce)?
+            .try_into()
+            .map_err(|_| anyhow!("Broken spoiler log nonce"))?;
+        let ciphertext = hex::decode(&self.ciphertext)?;
+        let data = XChaCha20Poly1305::new(&key.into())
+            .decrypt(&XNonce::from(nonce), ciphertext.as_slice())
+            .map_err(|_| anyhow!("Wrong password or broken spoiler log"))?;
+        Ok(String::from_utf8(data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_spoiler_log() -> Result<()> {
+        let lock = SpoilerLock::Password {
+            password: "organizer".to_owned(),
+        };
+        let sealed = SealedSpoilerLog::seal("spoiler", &lock, "Feather, Boots".to_owned())?;
+        let json = sealed.to_json();
+        assert!(!String::from_utf8_lossy(&json).contains("organizer"));
+        let sealed = SealedSpoilerLog::parse(&json)?;
+        assert_eq!(sealed.fingerprint, "Feather, Boots");
+        assert!(sealed.unseal(None, 0).is_err());
+        assert!(sealed.unseal(Some("racer"), 0).is_err());
+        assert_eq!(sealed.unseal(Some("organizer"), 0)?, "spoiler");
+
+        let lock = SpoilerLock::Timestamp { unlock_at: 100 };
+        let sealed = SealedSpoilerLog::seal("spoiler", &lock, String::new())?;
+        assert!(sealed.unseal(None, 99).is_err());
+        assert_eq!(sealed.unseal(None, 100)?, "spoiler");
+        Ok(())
+    }
+
+    #[test]
+    fn test_unseal_tampered_spoiler_log() -> Result<()> {
+        let lock = SpoilerLock::Timestamp { unlock_at: 0 };
+        let mut sealed = SealedSpoilerLog::seal("spoiler", &lock, String::new())?;
+        let mut ciphertext = hex::decode(&sealed.ciphertext)?;
+        ciphertext[0] ^= 1;
+        sealed.ciphertext = hex::encode(ciphertext);
+        assert!(sealed.unseal(None, 0).is_err());
+        Ok(())
+    }
+}
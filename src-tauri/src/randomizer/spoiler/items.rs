@@ -1,9 +1,14 @@
 use std::collections::BTreeMap;
 
+use anyhow::{Result, bail};
 use rand::Rng;
 
 use crate::{
-    randomizer::storage::{Storage, item::Item},
+    dataset::spot::ChestSpot,
+    randomizer::{
+        MapPlacement,
+        storage::{Storage, item::Item},
+    },
     script::enums::FieldNumber,
 };
 
@@ -12,16 +17,37 @@ use super::items_pool::{ItemsPool, UnorderedItems};
 pub struct Items<'a> {
     priority_items: Vec<&'a Item>,
     maps: BTreeMap<FieldNumber, &'a Item>,
+    removed_maps: Vec<&'a ChestSpot>,
     consumable_items: Vec<&'a Item>,
     general_items: Vec<&'a Item>,
 }
 
+/// 優先アイテムは通常のスフィアより先に置くため、
+/// 各アイテムプールから抜いても困らない普通のアイテムに限る
+pub fn validate_priority_item_names(
+    source: &Storage,
+    priority_item_names: &[String],
+) -> Result<()> {
+    for name in priority_item_names {
+        let Some(item) = source.all_items().find(|item| item.name.get() == name) else {
+            bail!("unknown priority item: {}", name);
+        };
+        if item.name.is_map() || item.name.is_consumable() {
+            bail!("invalid priority item: {}", name);
+        }
+    }
+    Ok(())
+}
+
 impl<'a> Items<'a> {
-    pub fn new(source: &'a Storage) -> Self {
-        let (maps, chests) = source
-            .chests
-            .values()
-            .partition::<Vec<_>, _>(|x| x.item.name.is_map());
+    pub fn new(
+        source: &'a Storage,
+        priority_item_names: &[String],
+        map_placement: MapPlacement,
+    ) -> Self {
+        let (maps, chests) = source.chests.values().partition::<Vec<_>, _>(|x| {
+            map_placement != MapPlacement::Anywhere && x.item.name.is_map()
+        });
         let maps: BTreeMap<FieldNumber, &Item> = maps
             .into_iter()
             .map(|x| (x.spot.region().field_number(), &x.item))
@@ -38,24 +64,18 @@ impl<'a> Items<'a> {
             .chain(source.roms.values().map(|x| &x.item))
             .chain(source.talks.iter().map(|x| &x.item));
         let (priority_items, remaining_items) = items.partition::<Vec<_>, _>(|item| {
-            [
-                "handScanner",
-                "shellHorn",
-                "holyGrail",
-                "gameMaster",
-                "glyphReader",
-            ]
-            .contains(&item.name.get())
+            priority_item_names
+                .iter()
+                .any(|name| name == item.name.get())
         });
         let (consumable_items, general_items): (Vec<_>, Vec<_>) = remaining_items
             .into_iter()
             .partition(|x| x.can_display_in_shop() && x.name.is_consumable());
 
-        debug_assert!(priority_items.iter().all(|item| item.can_display_in_shop()));
-
         Self {
             priority_items,
             maps,
+            removed_maps: source.removed_maps.iter().map(|x| &x.spot).collect(),
             consumable_items,
             general_items,
         }
@@ -64,6 +84,9 @@ impl<'a> Items<'a> {
     pub fn maps(&self) -> &BTreeMap<FieldNumber, &'a Item> {
         &self.maps
     }
+    pub fn removed_maps(&self) -> &[&'a ChestSpot] {
+        &self.removed_maps
+    }
     pub fn consumable_items(&self) -> &[&'a Item] {
         &self.consumable_items
     }
@@ -88,7 +111,8 @@ impl<'a> Items<'a> {
         let field_items = UnorderedItems::new(list).shuffle(rng);
 
         ItemsPool {
-            priority_items: Some(UnorderedItems::new(self.priority_items.clone())),
+            priority_items: (!self.priority_items.is_empty())
+                .then(|| UnorderedItems::new(self.priority_items.clone())),
             consumable_items: UnorderedItems::new(self.consumable_items.clone()),
             field_items,
             talk_items,
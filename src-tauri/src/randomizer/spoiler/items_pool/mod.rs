@@ -4,6 +4,8 @@ use std::mem::take;
 
 use rand::Rng;
 
+use crate::randomizer::storage::item::Item;
+
 use super::spots::{SpotRef, Spots};
 
 use items::{fill_items_from, move_one_required_item};
@@ -33,6 +35,15 @@ impl<'a> ItemsPool<'a> {
             .shuffle(rng);
     }
 
+    pub fn append_field_items(&mut self, rng: &mut impl Rng, items: Vec<&'a Item>) {
+        if items.is_empty() {
+            return;
+        }
+        let mut field_items = take(&mut self.field_items).into_inner();
+        field_items.extend(items);
+        self.field_items = UnorderedItems::new(field_items).shuffle(rng);
+    }
+
     pub fn pick_items_randomly(
         &mut self,
         rng: &mut impl Rng,
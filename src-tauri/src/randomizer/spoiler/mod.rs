@@ -12,19 +12,18 @@ use rand_seeder::Seeder;
 use rand_xoshiro::Xoshiro256PlusPlus;
 use spots::SpotRef;
 
-use crate::{
-    randomizer::spoiler::{regions::Regions, sphere::State},
-    script::enums::FieldNumber,
-};
+use crate::{randomizer::spoiler::regions::Regions, script::enums::FieldNumber};
 
 use super::{
-    RandomizeOptions,
+    MapPlacement, RandomizeOptions,
     spoiler_log::{CheckpointRef, SpoilerLogRef},
-    storage::item::{Item, StrategyFlag},
+    storage::item::{Item, ItemSource, StrategyFlag},
 };
 
 use {items::Items, sphere::sphere, spots::Spots};
 
+pub use sphere::State;
+
 static GLITCH: LazyLock<StrategyFlag> = LazyLock::new(|| StrategyFlag::new("option:glitch".into()));
 
 pub fn make_rng<H: Hash>(seed: H) -> Xoshiro256PlusPlus {
@@ -44,6 +43,28 @@ fn ptr_eq<'a>(a: SpotRef<'a>, b: &CheckpointRef<'a>) -> bool {
     }
 }
 
+fn vanilla_maps<'a>(
+    maps: &BTreeMap<FieldNumber, &'a Item>,
+    spots: &mut Spots<'a>,
+) -> Vec<CheckpointRef<'a>> {
+    maps.values()
+        .map(|&item| {
+            let ItemSource::Chest(key) = item.src else {
+                unreachable!()
+            };
+            let idx = spots
+                .field_item_spots
+                .iter()
+                .position(|spot| match spot {
+                    SpotRef::Chest(spot) => (spot.region().field_number(), spot.item()) == key,
+                    _ => false,
+                })
+                .unwrap();
+            CheckpointRef::from_field_spot_item(spots.field_item_spots.swap_remove(idx), item)
+        })
+        .collect()
+}
+
 fn maps<'a>(
     rng: &mut impl Rng,
     maps: &BTreeMap<FieldNumber, &'a Item>,
@@ -84,6 +105,20 @@ fn maps<'a>(
         .collect()
 }
 
+/// `surface/main` から始め、グリッチを使う場合はそのフラグを立てた状態
+pub fn initial_state<'a>(all_regions: &Regions<'a>, options: &RandomizeOptions) -> State<'a> {
+    let mut state = State::new(
+        all_regions
+            .iter()
+            .find(|x| matches!(x.name().get(), "surface/main"))
+            .unwrap(),
+    );
+    if options.need_glitches {
+        state.insert_flag(&GLITCH);
+    }
+    state
+}
+
 pub fn spoiler<'a>(
     seed: u64,
     options: &RandomizeOptions,
@@ -95,19 +130,17 @@ pub fn spoiler<'a>(
     let mut rng = make_rng(seed);
     let mut items_pool = items.to_items_pool(&mut rng, spots.talk_spots.len(), spots.shops.len());
     let mut remaining_spots = spots.clone();
-    let maps = maps(&mut rng, items.maps(), &mut remaining_spots);
+    let maps = if options.map_placement == MapPlacement::Vanilla {
+        vanilla_maps(items.maps(), &mut remaining_spots)
+    } else {
+        maps(&mut rng, items.maps(), &mut remaining_spots)
+    };
 
-    let mut state = State::new(
-        all_regions
-            .iter()
-            .find(|x| matches!(x.name().get(), "surface/main"))
-            .unwrap(),
-    );
+    let mut state = initial_state(all_regions, options);
     let mut progression = Vec::new();
-
-    if options.need_glitches {
-        state.insert_flag(&GLITCH);
-    }
+    let mut priority_items = Vec::new();
+    let mut unplaced_priority_items = Vec::new();
+    let has_priority_items = items_pool.priority_items.is_some();
 
     for i in 0..100 {
         let Some(sphere) = sphere(
@@ -116,6 +149,8 @@ pub fn spoiler<'a>(
             &mut remaining_spots,
             &mut state,
             all_regions,
+            &options.early_fields,
+            &mut unplaced_priority_items,
         ) else {
             let reachable_names: std::collections::HashSet<_> =
                 state.reachable_regions().map(|r| r.name().get()).collect();
@@ -132,6 +167,10 @@ pub fn spoiler<'a>(
             );
             return None;
         };
+        // 優先アイテムがあれば最初のスフィアはそれだけを置いたもの
+        if i == 0 && has_priority_items {
+            priority_items = sphere.iter().map(|checkpoint| checkpoint.name()).collect();
+        }
         progression.push(sphere);
 
         if !remaining_spots.is_empty() {
@@ -150,7 +189,16 @@ pub fn spoiler<'a>(
             continue;
         }
         info!("Sphere: {}, time: {:?}", i, start.elapsed());
-        return Some(SpoilerLogRef { progression, maps });
+        return Some(SpoilerLogRef {
+            priority_items,
+            unplaced_priority_items: unplaced_priority_items
+                .into_iter()
+                .map(|item| &item.name)
+                .collect(),
+            progression,
+            maps,
+            removed_maps: items.removed_maps().to_vec(),
+        });
     }
     unreachable!();
 }
@@ -11,8 +11,12 @@ use crate::{
     randomizer::{
         spoiler::regions::Regions,
         spoiler_log::{CheckpointRef, SphereRef},
-        storage::{Event, ShopRef, item::StrategyFlag},
+        storage::{
+            Event, ShopRef,
+            item::{Item, StrategyFlag},
+        },
     },
+    script::enums::FieldNumber,
 };
 
 use super::{
@@ -165,6 +169,8 @@ pub fn sphere<'a>(
     remaining_spots: &mut Spots<'a>,
     state: &mut State<'a>,
     all_regions: &Regions<'a>,
+    early_fields: &[FieldNumber],
+    unplaced_priority_items: &mut Vec<&'a Item>,
 ) -> Option<SphereRef<'a>> {
     debug_assert_eq!(
         items_pool.shop_items.len() + items_pool.consumable_items.len(),
@@ -174,7 +180,8 @@ pub fn sphere<'a>(
     state.explore_regions(all_regions);
 
     if let Some(priority_items) = items_pool.priority_items.take() {
-        let sphere = pre_sphere(rng, priority_items, remaining_spots, state);
+        let (sphere, unplaced) =
+            pre_sphere(rng, priority_items, remaining_spots, state, early_fields);
         let shop_count = sphere
             .iter()
             .filter(|x| match x {
@@ -189,6 +196,8 @@ pub fn sphere<'a>(
             })
             .count();
         items_pool.move_shop_items_to_field_items(rng, shop_count);
+        items_pool.append_field_items(rng, unplaced.clone());
+        *unplaced_priority_items = unplaced;
         debug_assert_eq!(
             remaining_spots.field_item_spots.len(),
             items_pool.field_items.len(),
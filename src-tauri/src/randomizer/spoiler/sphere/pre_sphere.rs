@@ -20,26 +20,18 @@ use super::explore;
 fn explorer_neighborhood<'a>(
     remaining_spots: &Spots<'a>,
     state: &State<'a>,
+    early_fields: &[FieldNumber],
 ) -> (Spots<'a>, Spots<'a>) {
     let (mut working, mut remainings) = explore(remaining_spots, state);
-    let is_early = |region: &Region| {
-        matches!(
-            region.field_number(),
-            FieldNumber::Surface | FieldNumber::GateOfGuidance
-        )
-    };
+    let is_early = |region: &Region| early_fields.contains(&region.field_number());
     let (reachables, mut unreachables) = working
         .field_item_spots
         .into_iter()
         .partition(|x| is_early(x.region()));
     working.field_item_spots = reachables;
     remainings.field_item_spots.append(&mut unreachables);
-    let (reachables, mut unreachables) = working
-        .talk_spots
-        .into_iter()
-        .partition(|x| is_early(x.region()));
-    working.talk_spots = reachables;
-    remainings.talk_spots.append(&mut unreachables);
+    // 会話のアイテムプールは優先アイテムの分を調整していないので、会話には置かない
+    remainings.talk_spots.append(&mut working.talk_spots);
     let (reachables, mut unreachables) = working
         .shops
         .into_iter()
@@ -49,32 +41,37 @@ fn explorer_neighborhood<'a>(
     (working, remainings)
 }
 
+/// 近くに置ききれなかったアイテムは置かずに返す
 fn place_items<'a>(
     rng: &mut impl Rng,
     priority_items: impl Iterator<Item = &'a Item>,
     working: &mut Spots<'a>,
-) -> Vec<CheckpointRef<'a>> {
-    priority_items
-        .map(|item| {
-            let dice = rng.gen_range(
-                0..(working.field_item_spots.len()
-                    + working.talk_spots.len()
-                    + working.shops.len()),
-            );
-            if dice < working.field_item_spots.len() {
-                let spot = working.field_item_spots.swap_remove(dice);
-                CheckpointRef::from_field_spot_item(spot, item)
-            } else if dice < working.field_item_spots.len() + working.talk_spots.len() {
-                unreachable!()
-            } else {
-                let idx = dice - working.field_item_spots.len() - working.talk_spots.len();
-                let item_spot = working.shops.swap_remove(idx);
-                let spot = &item_spot.spot;
-                let idx = item_spot.idx;
-                CheckpointRef::Shop(ShopRef { spot, idx, item })
-            }
-        })
-        .collect()
+) -> (Vec<CheckpointRef<'a>>, Vec<&'a Item>) {
+    let mut checkpoints = Vec::new();
+    let mut unplaced = Vec::new();
+    for item in priority_items {
+        let shop_count = if item.can_display_in_shop() {
+            working.shops.len()
+        } else {
+            0
+        };
+        if working.field_item_spots.len() + shop_count == 0 {
+            unplaced.push(item);
+            continue;
+        }
+        let dice = rng.gen_range(0..(working.field_item_spots.len() + shop_count));
+        if dice < working.field_item_spots.len() {
+            let spot = working.field_item_spots.swap_remove(dice);
+            checkpoints.push(CheckpointRef::from_field_spot_item(spot, item));
+        } else {
+            let idx = dice - working.field_item_spots.len();
+            let item_spot = working.shops.swap_remove(idx);
+            let spot = &item_spot.spot;
+            let idx = item_spot.idx;
+            checkpoints.push(CheckpointRef::Shop(ShopRef { spot, idx, item }));
+        }
+    }
+    (checkpoints, unplaced)
 }
 
 pub fn pre_sphere<'a>(
@@ -82,9 +79,11 @@ pub fn pre_sphere<'a>(
     priority_items: UnorderedItems<'a>,
     remaining_spots: &mut Spots<'a>,
     state: &mut State<'a>,
-) -> SphereRef<'a> {
+    early_fields: &[FieldNumber],
+) -> (SphereRef<'a>, Vec<&'a Item>) {
     let mut priority_items = priority_items.into_inner();
     let mut spheres = Vec::new();
+    let mut unplaced = Vec::new();
 
     // Placing a Hand Scanner
     if let Some(idx) = priority_items
@@ -92,21 +91,25 @@ pub fn pre_sphere<'a>(
         .position(|x| x.name == StrategyFlag::new("handScanner".into()))
     {
         let item = priority_items.swap_remove(idx);
-        let (mut working, remainings) = explorer_neighborhood(remaining_spots.deref(), state);
+        let (mut working, remainings) =
+            explorer_neighborhood(remaining_spots.deref(), state, early_fields);
         *remaining_spots = remainings;
-        let checkpoints = place_items(rng, [item].into_iter(), &mut working);
+        let (checkpoints, mut rest) = place_items(rng, [item].into_iter(), &mut working);
+        unplaced.append(&mut rest);
         let checkpoints = SphereRef::new(state.reachable_regions().collect(), checkpoints);
         remaining_spots.extend(working);
         state.append_flags(&checkpoints);
         spheres.append(&mut checkpoints.into_inner());
     }
-    let (mut working, remainings) = explorer_neighborhood(remaining_spots.deref(), state);
+    let (mut working, remainings) =
+        explorer_neighborhood(remaining_spots.deref(), state, early_fields);
     *remaining_spots = remainings;
-    let checkpoints: Vec<_> = place_items(rng, priority_items.iter().copied(), &mut working);
+    let (checkpoints, mut rest) = place_items(rng, priority_items.iter().copied(), &mut working);
+    unplaced.append(&mut rest);
     remaining_spots.extend(working);
     let reachable_regions: Vec<_> = state.reachable_regions().collect();
     let checkpoints = SphereRef::new(reachable_regions.clone(), checkpoints);
     state.append_flags(&checkpoints);
     spheres.append(&mut checkpoints.into_inner());
-    SphereRef::new(reachable_regions, spheres)
+    (SphereRef::new(reachable_regions, spheres), unplaced)
 }
@@ -5,11 +5,7 @@ use crate::{
         game_structure::RegionName,
         spot::{AnyOfAllRequirements, Region},
     },
-    randomizer::{
-        spoiler::regions::Regions,
-        spoiler_log::{CheckpointRef, SphereRef},
-        storage::item::StrategyFlag,
-    },
+    randomizer::{spoiler::regions::Regions, spoiler_log::SphereRef, storage::item::StrategyFlag},
 };
 
 pub struct State<'a> {
@@ -105,21 +101,13 @@ impl<'a> State<'a> {
     }
 
     pub fn append_flags(&mut self, sphere: &SphereRef<'a>) {
-        self.append_flags_internal(sphere.iter().map(|checkpoint| match checkpoint {
-            CheckpointRef::MainWeapon(checkpoint) => &checkpoint.item.name,
-            CheckpointRef::SubWeapon(checkpoint) => &checkpoint.item.name,
-            CheckpointRef::Chest(checkpoint) => &checkpoint.item.name,
-            CheckpointRef::Seal(checkpoint) => &checkpoint.item.name,
-            CheckpointRef::Shop(checkpoint) => &checkpoint.item.name,
-            CheckpointRef::Rom(checkpoint) => &checkpoint.item.name,
-            CheckpointRef::Talk(checkpoint) => &checkpoint.item.name,
-            CheckpointRef::Event(flag) => flag,
-        }));
+        self.append_flags_internal(sphere.iter().map(|checkpoint| checkpoint.name()));
     }
 
     fn append_flags_internal(&mut self, flags: impl Iterator<Item = &'a StrategyFlag>) {
         for flag in flags {
-            if flag.is_sacred_orb() {
+            // 複製で最大 HP は 2 度は増えない
+            if flag.is_sacred_orb() && !flag.is_late_game_duplicate() {
                 self.sacred_orb_count += 1;
             }
             self.strategy_flags.insert(flag);
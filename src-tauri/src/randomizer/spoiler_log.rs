@@ -1,8 +1,11 @@
 use std::fmt;
 
 use crate::{
-    dataset::spot::{Region, ShopSpot},
-    script::enums::{ChestItem, FieldNumber},
+    dataset::spot::{ChestSpot, Region, ShopSpot},
+    script::{
+        consts::FILLER_COIN_AMOUNT,
+        enums::{ChestItem, FieldNumber},
+    },
 };
 
 use super::{
@@ -34,6 +37,21 @@ pub enum Checkpoint {
     Event(StrategyFlag),
 }
 
+impl Checkpoint {
+    fn name(&self) -> &StrategyFlag {
+        match self {
+            Self::MainWeapon(checkpoint) => &checkpoint.item.name,
+            Self::SubWeapon(checkpoint) => &checkpoint.item.name,
+            Self::Chest(checkpoint) => &checkpoint.item.name,
+            Self::Seal(checkpoint) => &checkpoint.item.name,
+            Self::Rom(checkpoint) => &checkpoint.item.name,
+            Self::Talk(checkpoint) => &checkpoint.item.name,
+            Self::Shop(checkpoint) => &checkpoint.item.name,
+            Self::Event(flag) => flag,
+        }
+    }
+}
+
 fn fmt_checkpoints(checkpoints: &[&Checkpoint], f: &mut fmt::Formatter<'_>) -> fmt::Result {
     let fmt_shop = |f: &mut fmt::Formatter<'_>, spot: &ShopSpot, shop: &Vec<&Shop>| {
         let item0 = shop.iter().find(|x| x.idx == 0);
@@ -112,6 +130,19 @@ impl<'a> CheckpointRef<'a> {
         }
     }
 
+    pub fn name(&self) -> &'a StrategyFlag {
+        match self {
+            Self::MainWeapon(checkpoint) => &checkpoint.item.name,
+            Self::SubWeapon(checkpoint) => &checkpoint.item.name,
+            Self::Chest(checkpoint) => &checkpoint.item.name,
+            Self::Seal(checkpoint) => &checkpoint.item.name,
+            Self::Shop(checkpoint) => &checkpoint.item.name,
+            Self::Rom(checkpoint) => &checkpoint.item.name,
+            Self::Talk(checkpoint) => &checkpoint.item.name,
+            Self::Event(flag) => flag,
+        }
+    }
+
     pub fn to_owned(&self) -> Checkpoint {
         match self {
             Self::MainWeapon(checkpoint) => Checkpoint::MainWeapon(MainWeapon {
@@ -190,12 +221,38 @@ impl<'a> SphereRef<'a> {
 
 #[derive(Debug)]
 pub struct SpoilerLog {
+    priority_items: Vec<StrategyFlag>,
+    unplaced_priority_items: Vec<StrategyFlag>,
     progression: Vec<Sphere>,
     maps: Vec<Checkpoint>,
+    removed_maps: Vec<ChestSpot>,
 }
 
 impl fmt::Display for SpoilerLog {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // マルチワールドで他のワールドに移ったものは載せない
+        let priority_items: Vec<_> = self
+            .priority_items
+            .iter()
+            .filter_map(|name| {
+                self.progression
+                    .iter()
+                    .flat_map(|sphere| &sphere.checkpoints)
+                    .find(|checkpoint| checkpoint.name() == name)
+            })
+            .collect();
+        if !priority_items.is_empty() {
+            writeln!(f, "[Priority Items]")?;
+            fmt_checkpoints(&priority_items, f)?;
+            writeln!(f)?;
+        }
+        if !self.unplaced_priority_items.is_empty() {
+            writeln!(f, "[Unplaced Priority Items]")?;
+            for name in &self.unplaced_priority_items {
+                writeln!(f, "{}", name.get())?;
+            }
+            writeln!(f)?;
+        }
         for (i, sphere) in self.progression.iter().enumerate() {
             if i != 0 {
                 writeln!(f)?;
@@ -251,20 +308,47 @@ impl fmt::Display for SpoilerLog {
             });
             fmt_checkpoints(&checkpoints, f)?;
         }
-        writeln!(f)?;
-        writeln!(f, "[Maps]")?;
-        fmt_checkpoints(&self.maps.iter().collect::<Vec<_>>(), f)
+        // 他のアイテムと混ぜた地図は節を持たない
+        if !self.maps.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "[Maps]")?;
+            fmt_checkpoints(&self.maps.iter().collect::<Vec<_>>(), f)?;
+        }
+        if !self.removed_maps.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "[Removed Maps]")?;
+            for spot in &self.removed_maps {
+                writeln!(f, "{} = {} coins", spot, FILLER_COIN_AMOUNT)?;
+            }
+        }
+        Ok(())
     }
 }
 
 pub struct SpoilerLogRef<'a> {
+    /// 序盤のフィールドに置いた優先アイテム
+    pub priority_items: Vec<&'a StrategyFlag>,
+    /// 序盤のフィールドに置けず、通常のアイテムに混ぜた優先アイテム
+    pub unplaced_priority_items: Vec<&'a StrategyFlag>,
     pub progression: Vec<SphereRef<'a>>,
     pub maps: Vec<CheckpointRef<'a>>,
+    /// 中身をコインに差し替えた地図の宝箱
+    pub removed_maps: Vec<&'a ChestSpot>,
 }
 
 impl SpoilerLogRef<'_> {
     pub fn to_owned(&self) -> SpoilerLog {
         SpoilerLog {
+            priority_items: self
+                .priority_items
+                .iter()
+                .map(|&name| name.to_owned())
+                .collect(),
+            unplaced_priority_items: self
+                .unplaced_priority_items
+                .iter()
+                .map(|&name| name.to_owned())
+                .collect(),
             progression: self
                 .progression
                 .iter()
@@ -288,6 +372,11 @@ impl SpoilerLogRef<'_> {
                 .iter()
                 .map(|checkpoint| checkpoint.to_owned())
                 .collect(),
+            removed_maps: self
+                .removed_maps
+                .iter()
+                .map(|&spot| spot.to_owned())
+                .collect(),
         }
     }
 
@@ -7,7 +7,7 @@ use anyhow::{Result, bail};
 
 use crate::{
     dataset::game_structure::GameStructure,
-    randomizer::{RandomizeOptions, storage::Talk},
+    randomizer::{MapPlacement, RandomizeOptions, storage::Talk},
 };
 
 use super::{
@@ -49,9 +49,22 @@ pub fn create_source(
     }
     let mut seals = BTreeMap::new();
     for spot in game_structure.seals.iter().cloned() {
-        let item = Item::seal(spot.seal(), spot.name().clone().into());
-        seals.insert(spot.seal(), Seal { spot, item });
+        let field_number = spot.region().field_number();
+        let item = Item::seal(field_number, spot.seal(), spot.name().clone().into());
+        let key = (spot.seal(), field_number);
+        seals.insert(key, Seal { spot, item });
     }
+    if !options.shuffle_late_game_duplicates {
+        sub_weapons.retain(|_, x| !x.item.name.is_late_game_duplicate());
+        chests.retain(|_, x| !x.item.name.is_late_game_duplicate());
+        seals.retain(|_, x| !x.item.name.is_late_game_duplicate());
+    }
+    let removed_maps = if options.map_placement == MapPlacement::Removed {
+        let maps = chests.extract_if(.., |_, chest| chest.item.name.is_map());
+        maps.map(|(_, chest)| chest).collect()
+    } else {
+        Vec::new()
+    };
     let mut roms = BTreeMap::new();
     for spot in game_structure.roadside_roms.iter().cloned() {
         let item = Item::rom(spot.rom(), spot.name().clone().into());
@@ -128,5 +141,6 @@ pub fn create_source(
         talks,
         shops,
         events,
+        removed_maps,
     )
 }
@@ -27,6 +27,10 @@ impl StrategyFlag {
     pub fn is_map(&self) -> bool {
         self.0.starts_with("map:")
     }
+    /// 元のスポットを複製した、夜の地上か真・母の祠のスポットのアイテム
+    pub fn is_late_game_duplicate(&self) -> bool {
+        self.0.ends_with(":surfaceNight") || self.0.ends_with(":trueShrineOfTheMother")
+    }
 
     pub fn is_consumable(&self) -> bool {
         [
@@ -70,7 +74,7 @@ pub enum ItemSource {
     MainWeapon(MainWeapon),
     SubWeapon((FieldNumber, SubWeapon)),
     Chest((FieldNumber, ChestItem)),
-    Seal(Seal),
+    Seal((FieldNumber, Seal)),
     Rom(Rom),
     Talk(TalkItem),
     Shop([Option<ShopItem>; 3], usize),
@@ -99,8 +103,8 @@ impl Item {
         let src = ItemSource::Chest((field_number, item));
         Self { src, name }
     }
-    pub fn seal(seal: Seal, name: StrategyFlag) -> Self {
-        let src = ItemSource::Seal(seal);
+    pub fn seal(field_number: FieldNumber, seal: Seal, name: StrategyFlag) -> Self {
+        let src = ItemSource::Seal((field_number, seal));
         Self { src, name }
     }
     pub fn rom(rom: Rom, name: StrategyFlag) -> Self {
@@ -126,12 +130,7 @@ impl Item {
             ItemSource::Shop(..) | ItemSource::Rom(_) => true,
             ItemSource::SubWeapon(_) => self.name.get() == "pistol",
             ItemSource::Chest(_) | ItemSource::Talk(_) => {
-                !self.name.is_map()
-                    && !self.name.is_sacred_orb()
-                    && (
-                        // Boots with set flag 768 (multiples of 256) cannot be sold in shops
-                        self.name.get() != "boots"
-                    )
+                !self.name.is_map() && !self.name.is_sacred_orb()
             }
         }
     }
@@ -140,8 +139,8 @@ impl Item {
         match &self.src {
             ItemSource::MainWeapon(_) | ItemSource::SubWeapon(_) | ItemSource::Seal(_) => false,
             ItemSource::Chest((_, ChestItem::Equipment(equipment))) => {
-                // Boots with set flag 768 (multiples of 256) cannot be sold in shops
-                *equipment != Equipment::Boots
+                // ショップと同じく、地図はフィールドでだけ渡す
+                *equipment != Equipment::Map
             }
             ItemSource::Shop(items, idx) => match &items[*idx] {
                 None | Some(ShopItem::SubWeapon(_)) => false,
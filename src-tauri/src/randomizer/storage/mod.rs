@@ -111,11 +111,13 @@ pub struct Storage {
     pub main_weapons: BTreeMap<enums::MainWeapon, MainWeapon>,
     pub sub_weapons: BTreeMap<(FieldNumber, enums::SubWeapon), SubWeapon>,
     pub chests: BTreeMap<(FieldNumber, enums::ChestItem), Chest>,
-    pub seals: BTreeMap<enums::Seal, Seal>,
+    pub seals: BTreeMap<(enums::Seal, FieldNumber), Seal>,
     pub roms: BTreeMap<enums::Rom, Rom>,
     pub talks: Vec<Talk>,
     pub shops: Vec<Shop>,
     pub events: Vec<Event>,
+    /// `MapPlacement::Removed` でアイテムプールから取り除いた地図の宝箱
+    pub removed_maps: Vec<Chest>,
 }
 
 impl Storage {
@@ -125,11 +127,12 @@ impl Storage {
         main_weapons: BTreeMap<enums::MainWeapon, MainWeapon>,
         sub_weapons: BTreeMap<(FieldNumber, enums::SubWeapon), SubWeapon>,
         chests: BTreeMap<(FieldNumber, enums::ChestItem), Chest>,
-        seals: BTreeMap<enums::Seal, Seal>,
+        seals: BTreeMap<(enums::Seal, FieldNumber), Seal>,
         roms: BTreeMap<enums::Rom, Rom>,
         talks: Vec<Talk>,
         shops: Vec<Shop>,
         events: Vec<Event>,
+        removed_maps: Vec<Chest>,
     ) -> Result<Self> {
         let zelf = Self {
             regions,
@@ -141,6 +144,7 @@ impl Storage {
             talks,
             shops,
             events,
+            removed_maps,
         };
         if cfg!(debug_assertions) {
             ware_missing_requirements(&zelf)?;
@@ -148,6 +152,11 @@ impl Storage {
         Ok(zelf)
     }
 
+    pub fn has_late_game_duplicates(&self) -> bool {
+        self.all_items()
+            .any(|item| item.name.is_late_game_duplicate())
+    }
+
     pub fn all_items(&self) -> impl Iterator<Item = &Item> {
         self.main_weapons
             .values()
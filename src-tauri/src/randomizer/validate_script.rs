@@ -0,0 +1,244 @@
+use std::collections::BTreeSet;
+
+use anyhow::{Result, bail};
+
+use crate::{
+    dataset::{
+        NIGHT_SURFACE_CHEST_COUNT, NIGHT_SURFACE_SEAL_COUNT, NIGHT_SURFACE_SUB_WEAPON_COUNT,
+        TRUE_SHRINE_OF_THE_MOTHER_SEAL_COUNT,
+    },
+    script::{
+        data::{
+            item::{ChestItem, Equipment, Rom},
+            object::ItemShop,
+            script::Script,
+            shop_items_data::ShopItem,
+        },
+        editor::find_item_set_flag,
+        enums::{self, FieldNumber},
+    },
+};
+
+use super::{MapPlacement, RandomizeOptions, storage::Storage};
+
+/// ソースとスクリプトで要素数が食い違うごとにメッセージを返す
+pub fn count_mismatches(
+    source: &Storage,
+    script: &Script,
+    options: &RandomizeOptions,
+) -> Vec<String> {
+    // 複製はシャッフルするときだけソースにある
+    let duplicated = !options.shuffle_late_game_duplicates as usize;
+    let removed_map_count = if options.map_placement == MapPlacement::Removed {
+        script.chests().filter(|x| x.item().is_map()).count()
+    } else {
+        0
+    };
+    [
+        (
+            "main weapons",
+            source.main_weapons.len(),
+            script.main_weapons().count(),
+        ),
+        (
+            "sub weapons",
+            source.sub_weapons.len() + NIGHT_SURFACE_SUB_WEAPON_COUNT * duplicated,
+            script.sub_weapons().count(),
+        ),
+        (
+            "chests",
+            source.chests.len() + NIGHT_SURFACE_CHEST_COUNT * duplicated + removed_map_count,
+            script.chests().count(),
+        ),
+        (
+            "seals",
+            source.seals.len()
+                + (TRUE_SHRINE_OF_THE_MOTHER_SEAL_COUNT + NIGHT_SURFACE_SEAL_COUNT) * duplicated,
+            script.seals().count(),
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, expected, actual)| expected != actual)
+    .map(|(name, expected, actual)| format!("{name}: expected {expected}, found {actual}"))
+    .collect()
+}
+
+fn field_number_in_source(field_number: FieldNumber, late_game_duplicates: bool) -> FieldNumber {
+    if late_game_duplicates {
+        return field_number;
+    }
+    match field_number {
+        FieldNumber::SurfaceNight => FieldNumber::Surface,
+        FieldNumber::TrueShrineOfTheMother => FieldNumber::ShrineOfTheMother,
+        _ => field_number,
+    }
+}
+
+fn missing_spots(source: &Storage, script: &Script) -> Vec<String> {
+    let late_game_duplicates = source.has_late_game_duplicates();
+    let fields = || script.worlds.iter().flat_map(|x| &x.fields);
+    let mut errors = Vec::new();
+
+    let main_weapons: BTreeSet<_> = script
+        .main_weapons()
+        .map(|x| x.main_weapon().content)
+        .collect();
+    for main_weapon in source.main_weapons.values() {
+        if !main_weapons.contains(&main_weapon.spot.main_weapon()) {
+            errors.push(format!(
+                "main weapon spot not found: {}",
+                main_weapon.spot.name().get()
+            ));
+        }
+    }
+    let sub_weapons: BTreeSet<_> = fields()
+        .flat_map(|field| {
+            let field_number = field_number_in_source(field.number(), late_game_duplicates);
+            field
+                .sub_weapons()
+                .map(move |x| (field_number, x.sub_weapon().content))
+        })
+        .collect();
+    for (key, sub_weapon) in &source.sub_weapons {
+        if !sub_weapons.contains(key) {
+            errors.push(format!(
+                "sub weapon spot not found: {}",
+                sub_weapon.spot.name().get()
+            ));
+        }
+    }
+    let chests: BTreeSet<_> = fields()
+        .flat_map(|field| {
+            let field_number = field_number_in_source(field.number(), late_game_duplicates);
+            field.chests().filter_map(move |x| match x.item() {
+                ChestItem::Equipment(Equipment { content, .. }) => {
+                    Some((field_number, enums::ChestItem::Equipment(*content)))
+                }
+                ChestItem::Rom(Rom { content, .. }) => {
+                    Some((field_number, enums::ChestItem::Rom(*content)))
+                }
+                ChestItem::None(_) => None,
+            })
+        })
+        .collect();
+    for (key, chest) in &source.chests {
+        if !chests.contains(key) {
+            errors.push(format!("chest spot not found: {}", chest.spot.name().get()));
+        }
+    }
+    let seals: BTreeSet<_> = fields()
+        .flat_map(|field| {
+            let field_number = field_number_in_source(field.number(), late_game_duplicates);
+            field.seals().map(move |x| (x.seal().content, field_number))
+        })
+        .collect();
+    for (key, seal) in &source.seals {
+        if !seals.contains(key) {
+            errors.push(format!("seal spot not found: {}", seal.spot.name().get()));
+        }
+    }
+    let roms: BTreeSet<_> = script.roms().map(|x| x.rom().content).collect();
+    for rom in source.roms.values() {
+        if !roms.contains(&rom.spot.rom()) {
+            errors.push(format!("rom spot not found: {}", rom.spot.name().get()));
+        }
+    }
+    for talk in &source.talks {
+        match find_item_set_flag(script, talk.spot.item()) {
+            Ok(Some(_)) => {}
+            Ok(None) => errors.push(format!("talk spot not found: {}", talk.spot.name().get())),
+            Err(err) => errors.push(format!("talk spot {}: {}", talk.spot.name().get(), err)),
+        }
+    }
+    errors
+}
+
+fn broken_shops(source: &Storage, script: &Script) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut item_shops = Vec::new();
+    for shop in script.shops() {
+        match ItemShop::try_from_shop_object(shop, &script.talks) {
+            Ok(Some(item_shop)) => item_shops.push(item_shop),
+            Ok(None) => {}
+            Err(err) => errors.push(format!("shop at ({},{}): {}", shop.x(), shop.y(), err)),
+        }
+    }
+    let mut checked = BTreeSet::new();
+    for shop in &source.shops {
+        if !checked.insert(shop.spot.name().get()) {
+            continue;
+        }
+        let found = item_shops.iter().any(|item_shop| {
+            let items = ShopItem::to_spot_shop_items(item_shop.items());
+            enums::ShopItem::matches_items(items, shop.spot.items())
+        });
+        if !found {
+            errors.push(format!("shop spot not found: {}", shop.spot.name().get()));
+        }
+    }
+    errors
+}
+
+/// 翻訳やほかの改造など、ハッシュが未知の `script.dat` の構造を検証する
+pub fn validate_script(
+    source: &Storage,
+    script: &Script,
+    options: &RandomizeOptions,
+) -> Result<()> {
+    let errors: Vec<_> = count_mismatches(source, script, options)
+        .into_iter()
+        .chain(missing_spots(source, script))
+        .chain(broken_shops(source, script))
+        .collect();
+    if !errors.is_empty() {
+        bail!("Unsupported script.dat:\n{}", errors.join("\n"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        app::read_game_structure_files_debug,
+        randomizer::storage::create_source::create_source,
+        script::{data::object::Object, fixture},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_validate_script_reports_failures() -> Result<()> {
+        let game_structure = read_game_structure_files_debug().await?;
+        let opts = RandomizeOptions::default();
+        let source = create_source(&game_structure, &opts)?;
+        let script = Script {
+            talks: vec![],
+            worlds: vec![],
+        };
+        let err = validate_script(&source, &script, &opts)
+            .unwrap_err()
+            .to_string();
+        let expected = format!(
+            "main weapons: expected {}, found 0",
+            source.main_weapons.len()
+        );
+        assert!(err.lines().any(|x| x == expected), "{err}");
+        assert!(err.contains("main weapon spot not found: "), "{err}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_script_reports_broken_talks() -> Result<()> {
+        let game_structure = read_game_structure_files_debug().await?;
+        let opts = RandomizeOptions::default();
+        let source = create_source(&game_structure, &opts)?;
+        // 語り部の会話が存在しない
+        let script = fixture::script(vec![Object::new(14, 0, 0, 100, 5, 252, 253, vec![])?]);
+        let err = validate_script(&source, &script, &opts)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("main weapon spot not found: "), "{err}");
+        assert!(err.contains("script broken: talk_number=252"), "{err}");
+        Ok(())
+    }
+}
@@ -1,6 +1,14 @@
+use std::ops::Range;
+
 pub use lmorandomizer_shared::lmo::MEMO_FLAG_BASE_NO;
 
 pub const BLANK_TALK_NUMBER: i32 = 772;
 
 pub const ALWAYS_ON_FLAG_NO: u16 = 40;
 pub const UNUSED_PR3_FLAG_NO: u16 = 114;
+/// 取り除いた地図の代わりに宝箱から出るコインの数
+pub const FILLER_COIN_AMOUNT: i32 = 10;
+/// セーブデータに残す必要のあるアイテム用の未使用フラグ
+pub const SAVE_FLAG_RANGE: Range<u16> = 6000..7400;
+/// 一度きりのイベント用の未使用フラグ
+pub const ONE_TIME_FLAG_RANGE: Range<u16> = 7400..MEMO_FLAG_BASE_NO;
@@ -55,6 +55,16 @@ impl ChestItem {
             ChestItem::None(flag) => *flag,
         }
     }
+
+    pub fn is_map(&self) -> bool {
+        matches!(
+            self,
+            ChestItem::Equipment(Equipment {
+                content: enums::Equipment::Map,
+                ..
+            })
+        )
+    }
 }
 
 #[derive(Clone)]
@@ -146,9 +156,14 @@ impl Item {
         };
         Ok(Self::Rom(item.clone()))
     }
-    fn seal(script: &Script, seal: enums::Seal) -> Result<Self> {
-        let Some(obj) = script.seals().find(|x| x.seal().content == seal) else {
-            bail!("invalid seal: {}", seal)
+    fn seal(script: &Script, field_number: FieldNumber, seal: enums::Seal) -> Result<Self> {
+        let Some(obj) = script
+            .field(field_number)
+            .unwrap()
+            .seals()
+            .find(|x| x.seal().content == seal)
+        else {
+            bail!("seal not found: {:?} {}", field_number, seal)
         };
         Ok(Self::Seal(obj.seal().clone()))
     }
@@ -239,7 +254,7 @@ impl Item {
             ItemSource::Chest((field_number, enums::ChestItem::Rom(rom))) => {
                 Self::chest_rom(script, *field_number, *rom)
             }
-            ItemSource::Seal(seal) => Self::seal(script, *seal),
+            ItemSource::Seal((field_number, seal)) => Self::seal(script, *field_number, *seal),
             ItemSource::Rom(rom) => Self::roadside_rom(script, *rom),
             ItemSource::Talk(talk_item) => Self::talk(script, *talk_item),
             ItemSource::Shop(items, item_idx) => Self::shop(script, *items, *item_idx),
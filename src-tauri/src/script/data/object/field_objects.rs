@@ -1,6 +1,8 @@
+use anyhow::{Result, bail};
+
 use crate::script::data::item::{ChestItem, Rom, Seal};
 
-use super::Start;
+use super::{ObjectKind, Start};
 
 #[derive(Clone)]
 pub struct ChestObject {
@@ -116,3 +118,29 @@ pub struct UnknownObject {
     pub op4: i32,
     pub starts: Vec<Start>,
 }
+
+impl UnknownObject {
+    pub fn kind(&self) -> Option<ObjectKind> {
+        ObjectKind::from_number(self.number)
+    }
+
+    /// オブジェクトが立てるフラグ
+    pub fn set_flag(&self) -> Result<u16> {
+        Ok(match self.kind() {
+            Some(ObjectKind::Hitbox) => u16::try_from(self.op1)?,
+            Some(ObjectKind::LemezaDetector) => u16::try_from(self.op3)?,
+            Some(ObjectKind::Memo) => u16::try_from(self.op2)?,
+            Some(ObjectKind::BreakableWall) => u16::try_from((self.op4 % 10000) / 10)?,
+            _ => bail!("object {} has no set flag", self.number),
+        })
+    }
+
+    /// オブジェクトが作動する条件のフラグ
+    pub fn trigger_flag(&self) -> Result<u16> {
+        Ok(match self.kind() {
+            Some(ObjectKind::MapRewrite) => u16::try_from(self.op3)?,
+            Some(ObjectKind::Trap) => u16::try_from(self.op1)?,
+            _ => bail!("object {} has no trigger flag", self.number),
+        })
+    }
+}
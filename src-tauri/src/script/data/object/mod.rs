@@ -1,4 +1,5 @@
 mod field_objects;
+mod object_kind;
 mod shop_object;
 pub mod starts;
 mod weapon_objects;
@@ -14,6 +15,7 @@ use super::{
 };
 
 pub use field_objects::{ChestObject, RomObject, SealObject, UnknownObject};
+pub use object_kind::{InstantItemContent, ObjectKind};
 pub use shop_object::{ItemShop, Shop, ShopObject};
 pub use weapon_objects::{MainWeaponObject, SubWeaponObject};
 
@@ -161,13 +163,25 @@ impl Object {
         op4: i32,
         starts: Vec<Start>,
     ) -> Result<Object> {
-        Ok(match number {
-            1 => Object::Chest(create_chest_object(x, y, op1, op2, op3, op4, starts)?),
-            13 => Object::SubWeapon(create_sub_weapon_object(x, y, op1, op2, op3, op4, starts)?),
-            14 => Object::Shop(ShopObject::new(x, y, op1, op2, op3, op4, starts)?),
-            32 => Object::Rom(create_rom_object(x, y, op1, op2, op3, op4, starts)?),
-            71 => Object::Seal(create_seal_object(x, y, op1, op2, op3, op4, starts)?),
-            77 => Object::MainWeapon(create_main_weapon_object(x, y, op1, op2, op3, op4, starts)?),
+        Ok(match ObjectKind::from_number(number) {
+            Some(ObjectKind::Chest) => {
+                Object::Chest(create_chest_object(x, y, op1, op2, op3, op4, starts)?)
+            }
+            Some(ObjectKind::SubWeapon) => {
+                Object::SubWeapon(create_sub_weapon_object(x, y, op1, op2, op3, op4, starts)?)
+            }
+            Some(ObjectKind::Shop) => {
+                Object::Shop(ShopObject::new(x, y, op1, op2, op3, op4, starts)?)
+            }
+            Some(ObjectKind::Rom) => {
+                Object::Rom(create_rom_object(x, y, op1, op2, op3, op4, starts)?)
+            }
+            Some(ObjectKind::Seal) => {
+                Object::Seal(create_seal_object(x, y, op1, op2, op3, op4, starts)?)
+            }
+            Some(ObjectKind::MainWeapon) => {
+                Object::MainWeapon(create_main_weapon_object(x, y, op1, op2, op3, op4, starts)?)
+            }
             _ => Object::Unknown(UnknownObject {
                 number,
                 x,
@@ -183,15 +197,18 @@ impl Object {
 
     pub fn number(&self) -> u16 {
         match self {
-            Self::Chest(_) => 1,
-            Self::SubWeapon(_) => 13,
-            Self::Shop(_) => 14,
-            Self::Rom(_) => 32,
-            Self::Seal(_) => 71,
-            Self::MainWeapon(_) => 77,
+            Self::Chest(_) => ObjectKind::Chest.number(),
+            Self::SubWeapon(_) => ObjectKind::SubWeapon.number(),
+            Self::Shop(_) => ObjectKind::Shop.number(),
+            Self::Rom(_) => ObjectKind::Rom.number(),
+            Self::Seal(_) => ObjectKind::Seal.number(),
+            Self::MainWeapon(_) => ObjectKind::MainWeapon.number(),
             Self::Unknown(obj) => obj.number,
         }
     }
+    pub fn kind(&self) -> Option<ObjectKind> {
+        ObjectKind::from_number(self.number())
+    }
     pub fn x(&self) -> i32 {
         match self {
             Self::Chest(obj) => obj.x(),
@@ -282,7 +299,7 @@ impl Object {
             Self::Rom(obj) => obj.rom().flag,
             Self::Seal(obj) => obj.seal().flag,
             Self::MainWeapon(obj) => obj.main_weapon().flag,
-            Self::Unknown(UnknownObject { number, .. }) => bail!("invalid number: {}", number),
+            Self::Unknown(obj) => obj.set_flag()?,
         })
     }
 }
@@ -0,0 +1,59 @@
+use std::fmt;
+
+use num_traits::FromPrimitive;
+
+/// script.dat の OBJECT の番号のうち、オペランドの意味がわかっていてランダマイザーが読み書きするもの。
+/// 会話する NPC は Shop として扱う。
+/// ドア、エレベーター、スイッチは番号もオペランドの意味も確かめられていないので登録しておらず、
+/// すべての番号を網羅した一覧にはなっていない。
+/// ここにない番号は UnknownObject の生の op1..op4 のまま扱う
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, num_derive::FromPrimitive)]
+#[repr(u16)]
+pub enum ObjectKind {
+    /// 宝箱
+    Chest = 1,
+    /// お金やおもりを直接与える。op1 が種類 (InstantItemContent)、op2 が量
+    InstantItem = 7,
+    SubWeapon = 13,
+    /// 店、および会話する NPC
+    Shop = 14,
+    /// 攻撃が当たると op1 のフラグを立てる。シャッターの開閉判定に使われる
+    Hitbox = 20,
+    /// レメゼが範囲に入ると op3 のフラグを立てる
+    LemezaDetector = 22,
+    Rom = 32,
+    /// 石碑などの調べられるメモ。op1 が会話番号、op2 が立てるフラグ
+    Memo = 37,
+    /// op3 のフラグでマップを書き換える
+    MapRewrite = 59,
+    /// 壊せる壁。op4 の下位 4 桁 / 10 が壊したときに立てるフラグ
+    BreakableWall = 70,
+    Seal = 71,
+    MainWeapon = 77,
+    /// op1 のフラグで作動する罠
+    Trap = 140,
+    /// ヴィマーナ。starts だけを書き換えるので、op1..op4 は扱わない
+    Vimana = 186,
+}
+
+impl ObjectKind {
+    pub fn from_number(number: u16) -> Option<Self> {
+        Self::from_u16(number)
+    }
+
+    pub fn number(self) -> u16 {
+        self as u16
+    }
+}
+
+/// InstantItem の op1。ランダマイザーはコインしか出さない (6 はおもり)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InstantItemContent {
+    Coins = 7,
+}
+
+impl fmt::Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
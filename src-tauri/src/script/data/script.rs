@@ -9,8 +9,8 @@ use crate::script::{
 use super::{
     item::{ChestItem, Equipment},
     object::{
-        ChestObject, MainWeaponObject, Object, RomObject, SealObject, ShopObject, SubWeaponObject,
-        UnknownObject,
+        ChestObject, MainWeaponObject, Object, ObjectKind, RomObject, SealObject, ShopObject,
+        SubWeaponObject, UnknownObject,
     },
     talk::Talk,
 };
@@ -57,6 +57,15 @@ impl Field {
             Some(x)
         })
     }
+
+    pub fn seals(&self) -> impl Iterator<Item = &SealObject> {
+        self.maps.iter().flat_map(|x| &x.objects).filter_map(|x| {
+            let Object::Seal(x) = x else {
+                return None;
+            };
+            Some(x)
+        })
+    }
 }
 
 #[derive(Clone)]
@@ -112,7 +121,7 @@ impl Script {
         self.view_objects()
             // without 2nd twinStatue
             .filter(|x| {
-                !(x.number() == 1
+                !(x.number() == ObjectKind::Chest.number()
                     && x.x() == 8192
                     && x.y() == 6144
                     && x.op1() == 420
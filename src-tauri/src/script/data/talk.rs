@@ -1,5 +1,5 @@
 use core::fmt;
-use std::ops::Range;
+use std::{mem::take, ops::Range};
 
 use anyhow::{Result, anyhow, bail};
 use num_traits::FromPrimitive;
@@ -17,6 +17,101 @@ pub fn write_u16(flag: u16) -> (u8, u8) {
     ((flag / 0x100) as u8 + 1, (flag % 0x100) as u8)
 }
 
+/// 会話データを文字列と制御コマンドに分解したもの
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TalkSegment {
+    Text(String),
+    /// 2: フラグを立てる
+    SetFlag(u16),
+    /// 4: 装備品を与える
+    GiveEquipment(enums::Equipment),
+    /// 5: ROM を与える
+    GiveRom(enums::Rom),
+    /// 意味の分かっていない制御コマンドと、その引数
+    Command(u8, Vec<u8>),
+}
+
+impl TalkSegment {
+    fn from_command(cmd: &[u8]) -> Self {
+        match *cmd {
+            [2, hi @ 1..=u8::MAX, lo] => Self::SetFlag(read_u16(hi, lo)),
+            [4, code @ 1..=u8::MAX] => enums::Equipment::from_u8(code - 1)
+                .map(Self::GiveEquipment)
+                .unwrap_or_else(|| Self::Command(cmd[0], cmd[1..].to_vec())),
+            [5, code @ 1..=u8::MAX] => enums::Rom::from_u8(code - 1)
+                .map(Self::GiveRom)
+                .unwrap_or_else(|| Self::Command(cmd[0], cmd[1..].to_vec())),
+            _ => Self::Command(cmd[0], cmd[1..].to_vec()),
+        }
+    }
+
+    /// `{setFlag 123}` の中身を読む。Display の逆変換
+    fn parse_command(cmd: &str) -> Result<Self> {
+        let mut words = cmd.split(' ');
+        let name = words.next().unwrap_or_default();
+        let args: Vec<_> = words.collect();
+        Ok(match (name, args.as_slice()) {
+            ("setFlag", [flag]) => Self::SetFlag(flag.parse()?),
+            ("equipment", [equipment]) => Self::GiveEquipment(
+                equipment
+                    .parse()
+                    .map_err(|_| anyhow!("invalid equipment: {}", equipment))?,
+            ),
+            ("rom", [rom]) => {
+                Self::GiveRom(rom.parse().map_err(|_| anyhow!("invalid rom: {}", rom))?)
+            }
+            _ => {
+                let Some(code) = name.strip_prefix("cmd") else {
+                    bail!("unknown talk command: {{{}}}", cmd);
+                };
+                let args = args.iter().map(|x| x.parse()).collect::<Result<_, _>>()?;
+                Self::Command(code.parse()?, args)
+            }
+        })
+    }
+
+    fn write_bytes(&self, bytes: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Self::Text(text) => {
+                let char_to_code = reverse_code_map();
+                for c in text.chars() {
+                    let Some(&code) = char_to_code.get(&c) else {
+                        bail!("unsupported character in talk: {:?}", c);
+                    };
+                    bytes.push(code);
+                }
+            }
+            Self::SetFlag(flag) => {
+                let (hi, lo) = write_u16(*flag);
+                bytes.extend([2, hi, lo]);
+            }
+            Self::GiveEquipment(equipment) => bytes.extend([4, *equipment as u8 + 1]),
+            Self::GiveRom(rom) => bytes.extend([5, *rom as u8 + 1]),
+            Self::Command(code, args) => {
+                bytes.push(*code);
+                bytes.extend(args);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TalkSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => write!(f, "{}", text),
+            Self::SetFlag(flag) => write!(f, "{{setFlag {}}}", flag),
+            Self::GiveEquipment(equipment) => write!(f, "{{equipment {}}}", equipment),
+            Self::GiveRom(rom) => write!(f, "{{rom {}}}", rom),
+            Self::Command(code, args) => {
+                write!(f, "{{cmd{}", code)?;
+                args.iter().try_for_each(|x| write!(f, " {}", x))?;
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Talk(Vec<u8>);
 
@@ -69,6 +164,66 @@ impl Talk {
         vec
     }
 
+    /// 改行 (10) は制御コマンドではなく文字列として扱う
+    pub fn segments(&self) -> Vec<TalkSegment> {
+        let code_map = code_map();
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut i = 0;
+        for range in self.control_talk_command_ranges() {
+            let range = range.start..range.end.min(self.0.len());
+            let cmd = &self.0[range.clone()];
+            text.extend(self.0[i..range.start].iter().map(|&x| code_map[x as usize]));
+            i = range.end;
+            if cmd[0] == b'\n' {
+                text.push('\n');
+                continue;
+            }
+            if !text.is_empty() {
+                segments.push(TalkSegment::Text(take(&mut text)));
+            }
+            segments.push(TalkSegment::from_command(cmd));
+        }
+        text.extend(self.0[i..].iter().map(|&x| code_map[x as usize]));
+        if !text.is_empty() {
+            segments.push(TalkSegment::Text(text));
+        }
+        segments
+    }
+
+    pub fn from_segments(segments: &[TalkSegment]) -> Result<Self> {
+        let mut bytes = Vec::new();
+        for segment in segments {
+            segment.write_bytes(&mut bytes)?;
+        }
+        Ok(Self(bytes))
+    }
+
+    /// 制御コマンドを `{setFlag 123}` のような形で埋め込んだ文字列
+    pub fn to_readable_string(&self) -> String {
+        self.segments().iter().map(|x| x.to_string()).collect()
+    }
+
+    /// to_readable_string の逆変換。`{` と `}` は会話の文字にないので、そのまま区切りに使える
+    pub fn from_readable_string(text: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(TalkSegment::Text(rest[..start].to_owned()));
+            }
+            let Some(len) = rest[start..].find('}') else {
+                bail!("unclosed command in talk: {:?}", text);
+            };
+            segments.push(TalkSegment::parse_command(&rest[start + 1..start + len])?);
+            rest = &rest[start + len + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(TalkSegment::Text(rest.to_owned()));
+        }
+        Self::from_segments(&segments)
+    }
+
     pub fn item(&self) -> Result<Option<(TalkItem, u16)>> {
         let mut set_flag = None;
         let mut item = None;
@@ -112,3 +267,39 @@ impl fmt::Display for Talk {
             .try_for_each(|&x| write!(f, "{}", code_map[x as usize]))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segments_round_trip() -> Result<()> {
+        let bytes = vec![
+            b'A', 2, 2, 44, 4, 1, b'\n', b'B', 5, 255, 1, 3, 1, 0, b'C', 2, 1,
+        ];
+        let talk = Talk(bytes.clone());
+        let segments = talk.segments();
+        assert_eq!(
+            segments,
+            [
+                TalkSegment::Text("A".to_owned()),
+                TalkSegment::SetFlag(300),
+                TalkSegment::GiveEquipment(enums::Equipment::from_u8(0).unwrap()),
+                TalkSegment::Text("\nB".to_owned()),
+                TalkSegment::Command(5, vec![255]),
+                TalkSegment::Command(1, vec![]),
+                TalkSegment::Command(3, vec![1, 0]),
+                TalkSegment::Text("C".to_owned()),
+                TalkSegment::Command(2, vec![1]),
+            ]
+        );
+        assert_eq!(Talk::from_segments(&segments)?.as_bytes(), bytes);
+        let readable = talk.to_readable_string();
+        assert!(readable.starts_with("A{setFlag 300}{equipment "));
+        assert!(readable.ends_with("\nB{cmd5 255}{cmd1}{cmd3 1 0}C{cmd2 1}"));
+        assert_eq!(Talk::from_readable_string(&readable)?.as_bytes(), bytes);
+        assert!(Talk::from_readable_string("{setFlag 1").is_err());
+        assert!(Talk::from_readable_string("{unknown}").is_err());
+        Ok(())
+    }
+}
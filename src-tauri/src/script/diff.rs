@@ -0,0 +1,283 @@
+use std::collections::BTreeSet;
+
+use crate::script::data::{
+    object::{ItemShop, Object, ObjectKind, Start, UnknownObject},
+    script::Script,
+    shop_items_data::{self, ShopItem},
+    talk::Talk,
+};
+
+#[derive(PartialEq)]
+struct ObjectParams<'a> {
+    number: u16,
+    x: i32,
+    y: i32,
+    ops: [i32; 4],
+    starts: &'a [Start],
+}
+
+impl<'a> ObjectParams<'a> {
+    fn from_object(obj: &'a Object) -> Self {
+        Self {
+            number: obj.number(),
+            x: obj.x(),
+            y: obj.y(),
+            ops: [obj.op1(), obj.op2(), obj.op3(), obj.op4()],
+            starts: obj.starts(),
+        }
+    }
+
+    fn from_unknown_object(obj: &'a UnknownObject) -> Self {
+        Self {
+            number: obj.number,
+            x: obj.x,
+            y: obj.y,
+            ops: [obj.op1, obj.op2, obj.op3, obj.op4],
+            starts: &obj.starts,
+        }
+    }
+
+    /// 差分の対応付けに使う。番号と座標が同じなら同じオブジェクトとみなす
+    fn key(&self) -> (u16, i32, i32) {
+        (self.number, self.x, self.y)
+    }
+
+    fn label(&self) -> String {
+        match ObjectKind::from_number(self.number) {
+            Some(kind) => format!("object {}:{} ({},{})", self.number, kind, self.x, self.y),
+            None => format!("object {} ({},{})", self.number, self.x, self.y),
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{} ops=[{}] starts=[{}]",
+            self.label(),
+            self.ops.map(|x| x.to_string()).join(","),
+            stringify_starts(self.starts),
+        )
+    }
+}
+
+fn stringify_starts(starts: &[Start]) -> String {
+    starts
+        .iter()
+        .map(|x| format!("{}:{}", x.flag, if x.run_when { 1 } else { 0 }))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn stringify_shop_item(item: &ShopItem) -> String {
+    format!(
+        "{:?} price={} flag={}",
+        item.to_spot_shop_item(),
+        item.price(),
+        item.flag()
+    )
+}
+
+fn describe_talk(talk: &Talk, is_shop_items_data: bool) -> String {
+    if is_shop_items_data && let Ok(items) = shop_items_data::parse(talk) {
+        return format!(
+            "[{}]",
+            [items.0, items.1, items.2]
+                .iter()
+                .map(stringify_shop_item)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    format!("{:?}", talk.to_string())
+}
+
+fn shop_items_data_talk_numbers(script: &Script) -> BTreeSet<usize> {
+    script
+        .shops()
+        .filter_map(|shop| ItemShop::try_from_shop_object(shop, &script.talks).ok()?)
+        .map(|x| x.item_data_talk_number() as usize)
+        .collect()
+}
+
+fn diff_talks(old: &Script, new: &Script, output: &mut Vec<String>) {
+    let shop_talk_numbers: BTreeSet<_> = shop_items_data_talk_numbers(old)
+        .into_iter()
+        .chain(shop_items_data_talk_numbers(new))
+        .collect();
+    for i in 0..old.talks.len().max(new.talks.len()) {
+        let is_shop = shop_talk_numbers.contains(&i);
+        let label = if is_shop { "shop" } else { "talk" };
+        match (old.talks.get(i), new.talks.get(i)) {
+            (Some(old_talk), Some(new_talk)) => {
+                if old_talk.as_bytes() == new_talk.as_bytes() {
+                    continue;
+                }
+                output.push(format!(
+                    "~ {label} {i}: {} -> {}",
+                    describe_talk(old_talk, is_shop),
+                    describe_talk(new_talk, is_shop),
+                ));
+            }
+            (Some(old_talk), None) => {
+                output.push(format!(
+                    "- {label} {i}: {}",
+                    describe_talk(old_talk, is_shop)
+                ));
+            }
+            (None, Some(new_talk)) => {
+                output.push(format!(
+                    "+ {label} {i}: {}",
+                    describe_talk(new_talk, is_shop)
+                ));
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+fn diff_object(path: &str, old: &ObjectParams, new: &ObjectParams, output: &mut Vec<String>) {
+    let mut changes = Vec::new();
+    for (i, (old_op, new_op)) in old.ops.iter().zip(&new.ops).enumerate() {
+        if old_op != new_op {
+            changes.push(format!("op{}: {} -> {}", i + 1, old_op, new_op));
+        }
+    }
+    if old.starts != new.starts {
+        changes.push(format!(
+            "starts: [{}] -> [{}]",
+            stringify_starts(old.starts),
+            stringify_starts(new.starts),
+        ));
+    }
+    if changes.is_empty() {
+        return;
+    }
+    output.push(format!("~ {path} {}: {}", old.label(), changes.join(", ")));
+}
+
+/// 番号と座標をキーにした LCS でオブジェクトを対応付ける
+fn diff_objects(path: &str, old: &[ObjectParams], new: &[ObjectParams], output: &mut Vec<String>) {
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i].key() == new[j].key() {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i].key() == new[j].key() {
+            diff_object(path, &old[i], &new[j], output);
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            output.push(format!("- {path} {}", old[i].describe()));
+            i += 1;
+        } else {
+            output.push(format!("+ {path} {}", new[j].describe()));
+            j += 1;
+        }
+    }
+}
+
+fn diff_worlds(old: &Script, new: &Script, output: &mut Vec<String>) {
+    for (old_world, new_world) in old.worlds.iter().zip(&new.worlds) {
+        let world_path = format!("world {}", old_world.number);
+        if old_world.fields.len() != new_world.fields.len() {
+            output.push(format!(
+                "~ {world_path}: field count {} -> {}",
+                old_world.fields.len(),
+                new_world.fields.len()
+            ));
+        }
+        for (old_field, new_field) in old_world.fields.iter().zip(&new_world.fields) {
+            let field_path = format!("{world_path} field {}", old_field.attrs.0);
+            let old_objects: Vec<_> = old_field
+                .objects
+                .iter()
+                .map(ObjectParams::from_unknown_object)
+                .collect();
+            let new_objects: Vec<_> = new_field
+                .objects
+                .iter()
+                .map(ObjectParams::from_unknown_object)
+                .collect();
+            diff_objects(&field_path, &old_objects, &new_objects, output);
+            if old_field.maps.len() != new_field.maps.len() {
+                output.push(format!(
+                    "~ {field_path}: map count {} -> {}",
+                    old_field.maps.len(),
+                    new_field.maps.len()
+                ));
+            }
+            for (old_map, new_map) in old_field.maps.iter().zip(&new_field.maps) {
+                let (a, b, c) = old_map.attrs;
+                let map_path = format!("{field_path} map {a},{b},{c}");
+                let old_objects: Vec<_> = old_map
+                    .objects
+                    .iter()
+                    .map(ObjectParams::from_object)
+                    .collect();
+                let new_objects: Vec<_> = new_map
+                    .objects
+                    .iter()
+                    .map(ObjectParams::from_object)
+                    .collect();
+                diff_objects(&map_path, &old_objects, &new_objects, output);
+            }
+        }
+    }
+    if old.worlds.len() != new.worlds.len() {
+        output.push(format!(
+            "~ world count {} -> {}",
+            old.worlds.len(),
+            new.worlds.len()
+        ));
+    }
+}
+
+/// `-` は削除、`+` は追加、`~` は変更を表す行を返す
+pub fn diff_script(old: &Script, new: &Script) -> Vec<String> {
+    let mut output = Vec::new();
+    diff_talks(old, new, &mut output);
+    diff_worlds(old, new, &mut output);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::script::fixture::script;
+
+    use super::*;
+
+    #[test]
+    fn test_diff_script() -> Result<()> {
+        let start = || Start {
+            flag: 99999,
+            run_when: true,
+        };
+        let old = script(vec![
+            Object::new(1, 10, 20, 400, 1, 768, 0, vec![start()])?,
+            Object::new(2, 0, 0, 1, 2, 3, 4, vec![])?,
+        ]);
+        let mut new = script(vec![
+            Object::new(1, 10, 20, 400, 2, 769, 0, vec![])?,
+            Object::new(3, 0, 0, 1, 2, 3, 4, vec![])?,
+        ]);
+        new.talks[0] = Talk::from_text("abd");
+        assert_eq!(
+            diff_script(&old, &new),
+            [
+                r#"~ talk 0: "abc" -> "abd""#,
+                "~ world 0 field 1 map 0,1,2 object 1:Chest (10,20): op2: 1 -> 2, op3: 768 -> 769, starts: [99999:1] -> []",
+                "- world 0 field 1 map 0,1,2 object 2 (0,0) ops=[1,2,3,4] starts=[]",
+                "+ world 0 field 1 map 0,1,2 object 3 (0,0) ops=[1,2,3,4] starts=[]",
+            ]
+        );
+        Ok(())
+    }
+}
@@ -1,20 +1,26 @@
+use anyhow::Result;
+
 use crate::script::{
+    consts::{ONE_TIME_FLAG_RANGE, SAVE_FLAG_RANGE},
     data::{
         item,
-        object::{ChestObject, Object, Start, UnknownObject},
+        object::{ChestObject, Object, ObjectKind, Start, UnknownObject},
         script::{Field, Map, World},
     },
     enums::{Equipment, Rom, SubWeapon},
 };
 
+use super::FlagAllocator;
+
 pub fn add_starting_items(
     worlds: Vec<World>,
+    flag_allocator: &mut FlagAllocator,
     equipment_list: &[Equipment],
     rom_list: &[Rom],
     sub_weapon_list: &[SubWeapon],
-) -> Vec<World> {
-    let unused_one_time_flag_no = 7400;
-    let unused_save_flag_no = 6000;
+) -> Result<Vec<World>> {
+    let unused_one_time_flag_no = flag_allocator.allocate(ONE_TIME_FLAG_RANGE)? as i32;
+    let unused_save_flag_no = flag_allocator.allocate(SAVE_FLAG_RANGE)? as u32;
     let x = 26624;
     let y = 14336;
     let starting_items: Vec<_> = [
@@ -41,7 +47,7 @@ pub fn add_starting_items(
         //     starts: vec![],
         // }),
         Object::Unknown(UnknownObject {
-            number: 22,
+            number: ObjectKind::LemezaDetector.number(),
             x: 26624,
             y: 10240,
             op1: 2,
@@ -55,7 +61,7 @@ pub fn add_starting_items(
     .chain(sub_weapon_list.iter().flat_map(|sub_weapon| {
         [
             Object::Unknown(UnknownObject {
-                number: 13,
+                number: ObjectKind::SubWeapon.number(),
                 x,
                 y,
                 op1: *sub_weapon as i32,
@@ -68,7 +74,7 @@ pub fn add_starting_items(
                 }],
             }),
             Object::Unknown(UnknownObject {
-                number: 13,
+                number: ObjectKind::SubWeapon.number(),
                 x,
                 y,
                 op1: *sub_weapon as i32,
@@ -111,7 +117,7 @@ pub fn add_starting_items(
         ))
     }))
     .collect();
-    worlds
+    Ok(worlds
         .into_iter()
         .map(|world| World {
             number: world.number,
@@ -147,5 +153,5 @@ pub fn add_starting_items(
                 })
                 .collect(),
         })
-        .collect()
+        .collect())
 }
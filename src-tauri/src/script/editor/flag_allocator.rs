@@ -0,0 +1,204 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+};
+
+use anyhow::{Result, bail};
+
+use crate::script::{
+    consts::{MEMO_FLAG_BASE_NO, UNUSED_PR3_FLAG_NO},
+    data::{
+        object::Start,
+        script::Script,
+        shop_items_data,
+        talk::{Talk, read_u16},
+    },
+    enums::{FieldNumber, Rom},
+};
+
+/// ゲームのフラグは 8000 個 (1000 バイト)
+pub const FLAG_COUNT: u16 = 8000;
+
+fn to_flag(value: i64) -> Option<u16> {
+    (0..FLAG_COUNT as i64)
+        .contains(&value)
+        .then_some(value as u16)
+}
+
+fn start_flags(starts: &[Start]) -> impl Iterator<Item = u16> + '_ {
+    starts.iter().filter_map(|start| to_flag(start.flag as i64))
+}
+
+fn talk_flags(talk: &Talk) -> Vec<u16> {
+    let data = talk.as_bytes();
+    let mut flags: Vec<_> = talk
+        .control_talk_command_ranges()
+        .into_iter()
+        .filter_map(|range| data.get(range))
+        // 2: フラグを立てる, 3: フラグを調べる
+        .filter(|cmd| matches!(cmd[0], 2 | 3) && cmd[1] > 0)
+        .map(|cmd| read_u16(cmd[1], cmd[2]))
+        .collect();
+    if let Ok(items) = shop_items_data::parse(talk) {
+        flags.extend([items.0.flag(), items.1.flag(), items.2.flag()]);
+    }
+    flags
+}
+
+/// ランダマイザーが割り当てずに書き込むフラグ
+fn reserved_flags() -> impl Iterator<Item = u16> {
+    // 会話のダミーの立てるフラグ。Talk::item を参照
+    let pr3 = [UNUSED_PR3_FLAG_NO];
+    // ハンドスキャナーのメモを開くフラグ。to_objects_for_hand_scanner を参照
+    let memos = MEMO_FLAG_BASE_NO..=MEMO_FLAG_BASE_NO + Rom::A1Spirit as u16;
+    pr3.into_iter().chain(memos)
+}
+
+/// フラグを参照している場所
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum FlagReference {
+    Field(FieldNumber),
+    Talk,
+    /// ランダマイザーが予約または割り当てたフラグ
+    Randomizer,
+}
+
+/// スクリプトから参照されているフラグと、ランダマイザーが予約したフラグ。
+/// フラグの範囲にあるオブジェクトのオペランドはすべてフラグとみなすので、
+/// フラグでない数も含むことがあるが、フラグを見落とすことはない
+pub struct FlagUsage(BTreeMap<u16, BTreeSet<FlagReference>>);
+
+impl FlagUsage {
+    pub fn new(script: &Script) -> Self {
+        let mut zelf = Self(BTreeMap::new());
+        zelf.extend(FlagReference::Randomizer, reserved_flags());
+        for field in script.worlds.iter().flat_map(|world| &world.fields) {
+            let reference = FlagReference::Field(field.number());
+            for obj in &field.objects {
+                let ops = [obj.op1, obj.op2, obj.op3, obj.op4];
+                zelf.extend(
+                    reference,
+                    ops.into_iter().filter_map(|op| to_flag(op as i64)),
+                );
+                zelf.extend(reference, start_flags(&obj.starts));
+            }
+            for obj in field.maps.iter().flat_map(|map| &map.objects) {
+                let ops = [obj.op1(), obj.op2(), obj.op3(), obj.op4()];
+                zelf.extend(
+                    reference,
+                    ops.into_iter().filter_map(|op| to_flag(op as i64)),
+                );
+                zelf.extend(reference, start_flags(obj.starts()));
+            }
+        }
+        for talk in &script.talks {
+            zelf.extend(FlagReference::Talk, talk_flags(talk));
+        }
+        zelf
+    }
+
+    fn extend(&mut self, reference: FlagReference, flags: impl IntoIterator<Item = u16>) {
+        for flag in flags {
+            self.0.entry(flag).or_default().insert(reference);
+        }
+    }
+
+    pub fn is_used(&self, flag: u16) -> bool {
+        self.0.contains_key(&flag)
+    }
+
+    pub fn references(&self, flag: u16) -> impl Iterator<Item = FlagReference> + '_ {
+        self.0.get(&flag).into_iter().flatten().copied()
+    }
+}
+
+/// ゲームにもほかのエディターにも使われていないフラグを割り当てる
+pub struct FlagAllocator(FlagUsage);
+
+impl FlagAllocator {
+    pub fn new(usage: FlagUsage) -> Self {
+        Self(usage)
+    }
+
+    pub fn allocate(&mut self, range: Range<u16>) -> Result<u16> {
+        // 256 の倍数は店や会話に書けない
+        let Some(flag) = range
+            .clone()
+            .find(|&flag| flag % 0x100 != 0 && !self.0.is_used(flag))
+        else {
+            bail!("no unused flag in {:?}", range)
+        };
+        self.0.extend(FlagReference::Randomizer, [flag]);
+        Ok(flag)
+    }
+
+    pub fn usage(&self) -> &FlagUsage {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script::{
+        consts::{ALWAYS_ON_FLAG_NO, ONE_TIME_FLAG_RANGE, SAVE_FLAG_RANGE},
+        data::item::ChestItem,
+        fixture::{script, vanilla_script},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_reserved_flags() {
+        let usage = FlagUsage::new(&script(vec![]));
+        assert!(usage.is_used(UNUSED_PR3_FLAG_NO));
+        assert!(usage.is_used(MEMO_FLAG_BASE_NO));
+        assert!(usage.is_used(MEMO_FLAG_BASE_NO + Rom::A1Spirit as u16));
+        assert!(!usage.is_used(MEMO_FLAG_BASE_NO + Rom::A1Spirit as u16 + 1));
+    }
+
+    #[test]
+    #[ignore = "needs the vanilla script.dat"]
+    fn test_flag_usage_of_vanilla_script() -> Result<()> {
+        let script = vanilla_script()?;
+        let usage = FlagUsage::new(&script);
+
+        assert!(usage.is_used(ALWAYS_ON_FLAG_NO));
+        let item_flags = script
+            .chests()
+            .filter_map(|x| match x.item() {
+                ChestItem::None(_) => None,
+                item => u16::try_from(item.flag()).ok(),
+            })
+            .chain(script.main_weapons().map(|x| x.main_weapon().flag))
+            .chain(script.sub_weapons().map(|x| x.sub_weapon().flag))
+            .chain(script.seals().map(|x| x.seal().flag))
+            .chain(script.roms().map(|x| x.rom().flag))
+            .filter(|&x| x < FLAG_COUNT);
+        for flag in item_flags {
+            assert!(usage.is_used(flag), "{}", flag);
+        }
+        for talk in &script.talks {
+            if let Some((_, flag)) = talk.item()? {
+                assert!(usage.is_used(flag), "{}", flag);
+            }
+        }
+        // エディター用の範囲はバニラのスクリプトでは未使用
+        for flag in SAVE_FLAG_RANGE.chain(ONE_TIME_FLAG_RANGE) {
+            assert!(!usage.is_used(flag), "{}", flag);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allocate() -> Result<()> {
+        let mut usage = FlagUsage(BTreeMap::new());
+        usage.extend(FlagReference::Talk, [0x101, 0x103]);
+        let mut allocator = FlagAllocator::new(usage);
+        assert_eq!(allocator.allocate(0x100..0x105)?, 0x102);
+        assert_eq!(allocator.allocate(0x100..0x105)?, 0x104);
+        assert!(allocator.allocate(0x100..0x105).is_err());
+
+        Ok(())
+    }
+}
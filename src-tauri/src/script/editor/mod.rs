@@ -1,27 +1,43 @@
 pub mod add_starting_items;
+mod flag_allocator;
 mod objects_factory;
+mod remap_boots_flag;
+mod remap_late_game_duplicate_flags;
 mod replace_talk_items;
 mod script_editor;
+mod script_patch;
 mod talks_editor;
 
-use std::ops::Deref;
+use std::{collections::BTreeMap, ops::Deref};
 
 use anyhow::Result;
 
 use crate::randomizer::storage::Storage;
 
-use super::data::{object::ItemShop, script::Script};
+use super::{
+    data::{object::ItemShop, script::Script},
+    enums::{ChestItem, FieldNumber},
+};
 
 use {
     replace_talk_items::replace_talk_items, script_editor::replace_items,
     talks_editor::replace_shops,
 };
 
-pub use talks_editor::normalize_shuriken_sale;
-
-pub fn apply_storage(script: &mut Script, shuffled: &Storage) -> Result<()> {
+pub use flag_allocator::{FlagAllocator, FlagUsage};
+pub use remap_boots_flag::remap_boots_flag;
+pub use remap_late_game_duplicate_flags::remap_late_game_duplicate_flags;
+pub use script_editor::find_item_set_flag;
+pub use script_patch::{PatchPhase, ScriptPatch, apply_script_patches, builtin_script_patches};
+
+/// `foreign_chests` のチェストは、中身の代わりに値のフラグを立てるだけの空箱にする
+pub fn apply_storage(
+    script: &mut Script,
+    shuffled: &Storage,
+    foreign_chests: &BTreeMap<(FieldNumber, ChestItem), u16>,
+) -> Result<()> {
     let mut worlds = script.worlds.clone();
-    replace_items(&mut worlds, script.deref(), shuffled)?;
+    replace_items(&mut worlds, script.deref(), shuffled, foreign_chests)?;
 
     let shops: Vec<_> = script
         .shops()
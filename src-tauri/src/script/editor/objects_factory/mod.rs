@@ -1,10 +1,10 @@
 mod object_factory;
 
 use object_factory::{
-    empty_chest, equipment_chest, equipment_chest_from_ankh_jewel_or_seal, hidden_main_weapon,
-    hidden_seal, hidden_sub_weapon, invisible_chest, main_weapon, memo, rom, rom_chest,
-    rom_chset_from_ankh_jewel_or_seal, seal, simple_main_weapon, simple_seal, simple_sub_weapon,
-    sub_weapon,
+    empty_chest, equipment_chest, equipment_chest_from_ankh_jewel_or_seal, filler_coins,
+    hidden_main_weapon, hidden_seal, hidden_sub_weapon, invisible_chest, main_weapon, memo, rom,
+    rom_chest, rom_chset_from_ankh_jewel_or_seal, seal, simple_main_weapon, simple_seal,
+    simple_sub_weapon, sub_weapon,
 };
 
 use crate::script::{
@@ -15,7 +15,7 @@ use crate::script::{
     },
 };
 
-pub use object_factory::map_rewrite_with_flags_replaced;
+pub use object_factory::{map_rewrite_with_flags_replaced, placeholder_chest};
 
 pub fn to_object_for_shutter(old_obj: &Object, open_flag: u16, item: Item) -> Object {
     match item {
@@ -68,6 +68,16 @@ pub fn to_objects_for_chest(old_obj: &ChestObject, item: Item) -> Vec<Object> {
     }
 }
 
+/// アイテムプールから取り除いたアイテムの宝箱を埋める
+pub fn to_objects_for_filler_chest(old_obj: &ChestObject, taken_flag: u16) -> Vec<Object> {
+    let [coins, detector] = filler_coins(old_obj, taken_flag);
+    vec![
+        Object::Chest(empty_chest(old_obj, taken_flag)),
+        Object::Unknown(coins),
+        Object::Unknown(detector),
+    ]
+}
+
 pub fn to_objects_for_hand_scanner(old_obj: &RomObject, item: Item) -> Vec<Object> {
     if let Item::Rom(item) = item {
         return vec![Object::Rom(rom(old_obj, item))];
@@ -99,3 +109,41 @@ pub fn to_objects_for_hand_scanner(old_obj: &RomObject, item: Item) -> Vec<Objec
         ],
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::script::{
+        data::object::{InstantItemContent, ObjectKind},
+        enums::Equipment,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_to_objects_for_filler_chest() -> Result<()> {
+        let Object::Chest(chest) =
+            Object::new(1, 0, 0, 400, Equipment::Map as i32, 700, 0, vec![])?
+        else {
+            unreachable!()
+        };
+        let objects = to_objects_for_filler_chest(&chest, 6000);
+        let [
+            Object::Chest(chest),
+            Object::Unknown(coins),
+            Object::Unknown(detector),
+        ] = &objects[..]
+        else {
+            panic!("unexpected objects");
+        };
+        assert!(matches!(chest.item(), ChestItem::None(400)));
+        assert_eq!(coins.kind(), Some(ObjectKind::InstantItem));
+        assert_eq!(
+            (coins.op1, coins.op2),
+            (InstantItemContent::Coins as i32, 10)
+        );
+        assert_eq!(detector.set_flag()?, 6000);
+        Ok(())
+    }
+}
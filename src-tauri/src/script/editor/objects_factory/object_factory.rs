@@ -4,12 +4,12 @@ use anyhow::Result;
 use log::warn;
 
 use crate::script::{
-    consts::{ALWAYS_ON_FLAG_NO, BLANK_TALK_NUMBER},
+    consts::{ALWAYS_ON_FLAG_NO, BLANK_TALK_NUMBER, FILLER_COIN_AMOUNT},
     data::{
         item::{ChestItem, Equipment, MainWeapon, Rom, Seal, SubWeapon},
         object::{
-            ChestObject, MainWeaponObject, Object, RomObject, SealObject, Start, SubWeaponObject,
-            UnknownObject,
+            ChestObject, InstantItemContent, MainWeaponObject, Object, ObjectKind, RomObject,
+            SealObject, Start, SubWeaponObject, UnknownObject,
             starts::{
                 starts_that_hide_when_startup_and_taken, starts_with_open_and_remove_flags,
                 starts_with_replaced_flag, starts_without_old_flag,
@@ -73,6 +73,42 @@ pub fn empty_chest(old_obj: &ChestObject, set_flag: u16) -> ChestObject {
     ChestObject::new(old_obj.x(), old_obj.y(), open_flag, item, -1, starts)
 }
 
+/// 宝箱を開けると出るコインと、一度しか取れないように `taken_flag` を立てる探知オブジェクト
+pub fn filler_coins(old_obj: &ChestObject, taken_flag: u16) -> [UnknownObject; 2] {
+    let starts = starts_with_open_and_remove_flags(old_obj.open_flag(), taken_flag);
+    [
+        UnknownObject {
+            number: ObjectKind::InstantItem.number(),
+            x: old_obj.x(),
+            y: old_obj.y(),
+            op1: InstantItemContent::Coins as i32,
+            op2: FILLER_COIN_AMOUNT,
+            op3: -1,
+            op4: -1,
+            starts: starts.to_vec(),
+        },
+        UnknownObject {
+            number: ObjectKind::LemezaDetector.number(),
+            x: old_obj.x(),
+            y: old_obj.y(),
+            op1: 2,
+            op2: 2,
+            op3: taken_flag as i32,
+            op4: -1,
+            starts: starts.to_vec(),
+        },
+    ]
+}
+
+/// 何も入っていないが、開けると `set_flag` が立つ
+pub fn placeholder_chest(old_obj: &ChestObject, set_flag: u16) -> ChestObject {
+    let open_flag = old_obj.open_flag();
+    let item = ChestItem::None(set_flag as i32);
+    let old_item_flag = u16::try_from(old_obj.item().flag()).unwrap();
+    let starts = starts_with_replaced_flag(old_obj.starts(), old_item_flag, set_flag);
+    ChestObject::new(old_obj.x(), old_obj.y(), open_flag, item, -1, starts)
+}
+
 pub fn simple_sub_weapon(x: i32, y: i32, open_flag: u16, item: SubWeapon) -> SubWeaponObject {
     let starts = starts_with_open_and_remove_flags(open_flag, item.flag);
     SubWeaponObject::new(x, y, item, starts.to_vec())
@@ -161,7 +197,7 @@ pub fn memo(old_obj: &RomObject, set_flag: u16, done_flag: u16) -> UnknownObject
         );
     }
     UnknownObject {
-        number: 37,
+        number: ObjectKind::Memo.number(),
         x: old_obj.x(),
         y: old_obj.y(),
         op1: BLANK_TALK_NUMBER,
@@ -0,0 +1,202 @@
+use anyhow::{Result, bail};
+
+use crate::script::{
+    consts::SAVE_FLAG_RANGE,
+    data::{
+        object::{Object, ObjectKind, Start, UnknownObject},
+        script::World,
+    },
+};
+
+use super::FlagAllocator;
+
+const ORIGINAL_BOOTS_FLAG_NO: u32 = 768;
+
+fn replace_boots_flag(starts: &[Start], boots_flag: u16) -> Vec<Start> {
+    starts
+        .iter()
+        .map(|start| {
+            if start.flag != ORIGINAL_BOOTS_FLAG_NO {
+                return start.clone();
+            }
+            Start {
+                flag: boots_flag as u32,
+                run_when: start.run_when,
+            }
+        })
+        .collect()
+}
+
+/// `index` 番目 (0: op1 .. 3: op4) のオペランドがフラグかどうか。
+/// 登録されていないオブジェクトのオペランドは、フラグかどうかわからないので書き換えない
+pub(super) fn is_flag_operand(kind: Option<ObjectKind>, index: usize) -> bool {
+    match kind {
+        Some(ObjectKind::Chest) => matches!(index, 0 | 2),
+        Some(ObjectKind::SubWeapon | ObjectKind::LemezaDetector | ObjectKind::MapRewrite) => {
+            index == 2
+        }
+        Some(ObjectKind::Rom | ObjectKind::Seal | ObjectKind::MainWeapon | ObjectKind::Memo) => {
+            index == 1
+        }
+        Some(ObjectKind::Hitbox | ObjectKind::Trap) => index == 0,
+        // op4 はエンコードされている。check_breakable_wall_op4 を参照
+        Some(
+            ObjectKind::BreakableWall
+            | ObjectKind::Shop
+            | ObjectKind::InstantItem
+            | ObjectKind::Vimana,
+        )
+        | None => false,
+    }
+}
+
+/// 壊せる壁のフラグは 3 桁しかないので、セーブされるフラグに書き換えられない
+fn check_breakable_wall_op4(op4: i32) -> Result<()> {
+    if (op4 % 10000) / 10 == ORIGINAL_BOOTS_FLAG_NO as i32 {
+        bail!("breakable wall refers to the boots flag: op4={}", op4);
+    }
+    Ok(())
+}
+
+fn replace_ops(kind: Option<ObjectKind>, ops: [i32; 4], boots_flag: u16) -> Result<[i32; 4]> {
+    let mut ops = ops;
+    for (index, op) in ops.iter_mut().enumerate() {
+        if is_flag_operand(kind, index) && *op == ORIGINAL_BOOTS_FLAG_NO as i32 {
+            *op = boots_flag as i32;
+        }
+    }
+    if kind == Some(ObjectKind::BreakableWall) {
+        check_breakable_wall_op4(ops[3])?;
+    }
+    Ok(ops)
+}
+
+fn replace_field_object(obj: &mut UnknownObject, boots_flag: u16) -> Result<()> {
+    let ops = [obj.op1, obj.op2, obj.op3, obj.op4];
+    [obj.op1, obj.op2, obj.op3, obj.op4] = replace_ops(obj.kind(), ops, boots_flag)?;
+    obj.starts = replace_boots_flag(&obj.starts, boots_flag);
+    Ok(())
+}
+
+/// ブーツの立てるフラグ 768 (0x300) は下位バイトが 0 になるので、店や会話に書けない。
+/// 同じ理由で、これを参照する会話もない。
+/// ブーツをどこにでも置けるように、オブジェクトからの参照を未使用のフラグに置き換える
+pub fn remap_boots_flag(worlds: &mut [World], flag_allocator: &mut FlagAllocator) -> Result<()> {
+    let boots_flag = flag_allocator.allocate(SAVE_FLAG_RANGE)?;
+    for field in worlds.iter_mut().flat_map(|world| &mut world.fields) {
+        for obj in &mut field.objects {
+            replace_field_object(obj, boots_flag)?;
+        }
+        for obj in field.maps.iter_mut().flat_map(|map| &mut map.objects) {
+            let ops = [obj.op1(), obj.op2(), obj.op3(), obj.op4()];
+            let new_ops = replace_ops(obj.kind(), ops, boots_flag)?;
+            if new_ops == ops
+                && obj
+                    .starts()
+                    .iter()
+                    .all(|start| start.flag != ORIGINAL_BOOTS_FLAG_NO)
+            {
+                continue;
+            }
+            let [op1, op2, op3, op4] = new_ops;
+            *obj = Object::new(
+                obj.number(),
+                obj.x(),
+                obj.y(),
+                op1,
+                op2,
+                op3,
+                op4,
+                replace_boots_flag(obj.starts(), boots_flag),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script::{
+        editor::FlagUsage,
+        enums::{Equipment, Rom},
+        fixture::script,
+    };
+
+    use super::*;
+
+    fn ops(obj: &Object) -> [i32; 4] {
+        [obj.op1(), obj.op2(), obj.op3(), obj.op4()]
+    }
+
+    #[test]
+    fn test_remap_boots_flag() -> Result<()> {
+        let starts = || {
+            vec![Start {
+                flag: ORIGINAL_BOOTS_FLAG_NO,
+                run_when: true,
+            }]
+        };
+        let mut script = script(vec![
+            Object::new(1, 0, 0, 400, Equipment::Boots as i32, 768, 0, vec![])?,
+            Object::new(1, 0, 0, 768, Rom::GameMaster as i32 + 100, 401, 0, starts())?,
+            Object::new(20, 0, 0, 768, 1, 2, 3, vec![])?,
+            Object::new(70, 0, 0, 768, 0, 0, 1_7671, vec![])?,
+            // 会話番号と量
+            Object::new(37, 0, 0, 768, 402, -1, -1, vec![])?,
+            Object::new(7, 0, 0, 7, 768, -1, -1, vec![])?,
+        ]);
+        script.worlds[0].fields[0].objects.push(UnknownObject {
+            number: 140,
+            x: 0,
+            y: 0,
+            op1: 768,
+            op2: 768,
+            op3: 0,
+            op4: 0,
+            starts: starts(),
+        });
+        script.worlds[0].fields[0].objects.push(UnknownObject {
+            number: 3,
+            x: 0,
+            y: 0,
+            op1: 768,
+            op2: 768,
+            op3: 0,
+            op4: 0,
+            starts: starts(),
+        });
+        let mut flag_allocator = FlagAllocator::new(FlagUsage::new(&script));
+        remap_boots_flag(&mut script.worlds, &mut flag_allocator)?;
+
+        let field = &script.worlds[0].fields[0];
+        let objects = &field.maps[0].objects;
+        assert_eq!(ops(&objects[0]), [400, Equipment::Boots as i32, 6000, 0]);
+        assert_eq!(
+            ops(&objects[1]),
+            [6000, Rom::GameMaster as i32 + 100, 401, 0]
+        );
+        assert_eq!(objects[1].starts()[0].flag, 6000);
+        assert_eq!(ops(&objects[2]), [6000, 1, 2, 3]);
+        assert_eq!(ops(&objects[3]), [768, 0, 0, 1_7671]);
+        assert_eq!(ops(&objects[4]), [768, 402, -1, -1]);
+        assert_eq!(ops(&objects[5]), [7, 768, -1, -1]);
+        let obj = &field.objects[0];
+        assert_eq!([obj.op1, obj.op2, obj.op3, obj.op4], [6000, 768, 0, 0]);
+        assert_eq!(obj.starts[0].flag, 6000);
+        // 登録されていないオブジェクトは starts だけを書き換える
+        let obj = &field.objects[1];
+        assert_eq!([obj.op1, obj.op2, obj.op3, obj.op4], [768, 768, 0, 0]);
+        assert_eq!(obj.starts[0].flag, 6000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remap_boots_flag_of_breakable_wall() -> Result<()> {
+        let mut script = script(vec![Object::new(70, 0, 0, 0, 0, 0, 1_7681, vec![])?]);
+        let mut flag_allocator = FlagAllocator::new(FlagUsage::new(&script));
+        assert!(remap_boots_flag(&mut script.worlds, &mut flag_allocator).is_err());
+
+        Ok(())
+    }
+}
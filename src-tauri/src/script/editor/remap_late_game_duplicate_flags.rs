@@ -0,0 +1,271 @@
+use std::collections::{BTreeMap, btree_map::Entry};
+
+use anyhow::{Result, bail};
+
+use crate::script::{
+    consts::SAVE_FLAG_RANGE,
+    data::{
+        item::ChestItem,
+        object::{Object, ObjectKind, Start, UnknownObject},
+        script::World,
+    },
+    enums::FieldNumber,
+};
+
+use super::{
+    FlagAllocator,
+    flag_allocator::{FLAG_COUNT, FlagReference},
+    remap_boots_flag::is_flag_operand,
+};
+
+/// アイテムのオブジェクトと、それを出す宝箱のフラグ
+fn item_flags(obj: &Object) -> Result<Vec<u16>> {
+    Ok(match obj {
+        Object::Chest(chest) => match chest.item() {
+            ChestItem::None(_) => vec![],
+            ChestItem::Equipment(_) | ChestItem::Rom(_) => {
+                vec![chest.open_flag(), u16::try_from(chest.item().flag())?]
+            }
+        },
+        Object::SubWeapon(_) | Object::Seal(_) => vec![obj.set_flag()?],
+        _ => vec![],
+    })
+}
+
+fn replace_flags(starts: &[Start], flag_map: &BTreeMap<u16, u16>) -> Vec<Start> {
+    starts
+        .iter()
+        .map(|start| {
+            let Some(&flag) = u16::try_from(start.flag)
+                .ok()
+                .and_then(|flag| flag_map.get(&flag))
+            else {
+                return start.clone();
+            };
+            Start {
+                flag: flag as u32,
+                run_when: start.run_when,
+            }
+        })
+        .collect()
+}
+
+fn remap(flag_map: &BTreeMap<u16, u16>, op: i32) -> i32 {
+    u16::try_from(op)
+        .ok()
+        .and_then(|op| flag_map.get(&op))
+        .map_or(op, |&flag| flag as i32)
+}
+
+/// フラグを持つオペランドをすべて書き換える。
+/// 壊せる壁の op4 には 3 桁のフラグしか書けないので、書き換えが必要なら失敗にする
+fn remap_ops(
+    kind: Option<ObjectKind>,
+    ops: [i32; 4],
+    flag_map: &BTreeMap<u16, u16>,
+) -> Result<[i32; 4]> {
+    let mut ops = ops;
+    for (index, op) in ops.iter_mut().enumerate() {
+        if is_flag_operand(kind, index) {
+            *op = remap(flag_map, *op);
+        }
+    }
+    if kind == Some(ObjectKind::BreakableWall)
+        && u16::try_from((ops[3] % 10000) / 10).is_ok_and(|flag| flag_map.contains_key(&flag))
+    {
+        bail!("breakable wall refers to a remapped flag: op4={}", ops[3]);
+    }
+    Ok(ops)
+}
+
+fn remap_field_object(obj: &mut UnknownObject, flag_map: &BTreeMap<u16, u16>) -> Result<()> {
+    let ops = [obj.op1, obj.op2, obj.op3, obj.op4];
+    [obj.op1, obj.op2, obj.op3, obj.op4] = remap_ops(obj.kind(), ops, flag_map)?;
+    obj.starts = replace_flags(&obj.starts, flag_map);
+    Ok(())
+}
+
+/// 夜の地上と真・母の祠の複製の元になったフィールド
+fn original_field(field: FieldNumber) -> Option<FieldNumber> {
+    match field {
+        FieldNumber::SurfaceNight => Some(FieldNumber::Surface),
+        FieldNumber::TrueShrineOfTheMother => Some(FieldNumber::ShrineOfTheMother),
+        _ => None,
+    }
+}
+
+/// 夜の地上と真・母の祠のアイテムは元のアイテムとフラグを共有しているので、片方を取るともう片方が消える。
+/// 別のアイテムを置けるように、複製に専用のフラグを割り当てる。
+pub fn remap_late_game_duplicate_flags(
+    worlds: &mut [World],
+    flag_allocator: &mut FlagAllocator,
+) -> Result<()> {
+    for field in worlds.iter_mut().flat_map(|world| &mut world.fields) {
+        let number = field.number();
+        let Some(original) = original_field(number) else {
+            continue;
+        };
+        // 2 つのフィールドのほかから参照されているフラグは、どちらのアイテムを指すのか決められない
+        let scope = [FlagReference::Field(original), FlagReference::Field(number)];
+        let mut flag_map = BTreeMap::new();
+        for obj in field.maps.iter().flat_map(|map| &map.objects) {
+            // サブウェポンの弾薬にはフラグがない
+            for flag in item_flags(obj)?.into_iter().filter(|&x| x < FLAG_COUNT) {
+                let Entry::Vacant(entry) = flag_map.entry(flag) else {
+                    continue;
+                };
+                let usage = flag_allocator.usage();
+                if let Some(reference) = usage.references(flag).find(|x| !scope.contains(x)) {
+                    bail!(
+                        "flag {} of {:?} is also referred from {:?}",
+                        flag,
+                        number,
+                        reference
+                    );
+                }
+                entry.insert(flag_allocator.allocate(SAVE_FLAG_RANGE)?);
+            }
+        }
+        // 宝箱を開ける仕掛けなども新しいフラグを見るように、フィールドのすべてのオブジェクトを書き換える
+        for obj in &mut field.objects {
+            remap_field_object(obj, &flag_map)?;
+        }
+        for obj in field.maps.iter_mut().flat_map(|map| &mut map.objects) {
+            let ops = [obj.op1(), obj.op2(), obj.op3(), obj.op4()];
+            let [op1, op2, op3, op4] = remap_ops(obj.kind(), ops, &flag_map)?;
+            *obj = Object::new(
+                obj.number(),
+                obj.x(),
+                obj.y(),
+                op1,
+                op2,
+                op3,
+                op4,
+                replace_flags(obj.starts(), &flag_map),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script::{
+        editor::FlagUsage,
+        enums::{Equipment, Seal, SubWeapon},
+        fixture::{field, script_with_fields},
+    };
+
+    use super::*;
+
+    fn objects() -> Result<Vec<Object>> {
+        let starts = vec![Start {
+            flag: 700,
+            run_when: false,
+        }];
+        Ok(vec![
+            Object::new(1, 0, 0, 400, Equipment::Feather as i32, 700, 0, vec![])?,
+            Object::new(13, 0, 0, SubWeapon::Pistol as i32, 1, 701, -1, vec![])?,
+            Object::new(13, 0, 0, SubWeapon::Pistol as i32, 10, 65279, -1, vec![])?,
+            Object::new(71, 0, 0, Seal::Birth as i32, 702, -1, -1, vec![])?,
+            Object::new(2, 0, 0, 1, 2, 3, 4, starts)?,
+        ])
+    }
+
+    #[test]
+    fn test_remap_late_game_duplicate_flags() -> Result<()> {
+        let surface = FieldNumber::Surface as u8;
+        let surface_night = FieldNumber::SurfaceNight as u8;
+        let mut script = script_with_fields(vec![
+            field(surface, objects()?),
+            field(surface_night, objects()?),
+        ]);
+        let mut flag_allocator = FlagAllocator::new(FlagUsage::new(&script));
+        remap_late_game_duplicate_flags(&mut script.worlds, &mut flag_allocator)?;
+
+        let ops = |field: usize| -> Vec<_> {
+            script.worlds[0].fields[field].maps[0]
+                .objects
+                .iter()
+                .map(|x| {
+                    (
+                        x.op1(),
+                        x.op2(),
+                        x.op3(),
+                        x.starts().first().map(|x| x.flag),
+                    )
+                })
+                .collect()
+        };
+        let original = [
+            (400, Equipment::Feather as i32, 700, None),
+            (SubWeapon::Pistol as i32, 1, 701, None),
+            (SubWeapon::Pistol as i32, 10, 65279, None),
+            (Seal::Birth as i32, 702, -1, None),
+            (1, 2, 3, Some(700)),
+        ];
+        assert_eq!(ops(0), original);
+        assert_eq!(
+            ops(1),
+            [
+                (6000, Equipment::Feather as i32, 6001, None),
+                (SubWeapon::Pistol as i32, 1, 6002, None),
+                (SubWeapon::Pistol as i32, 10, 65279, None),
+                (Seal::Birth as i32, 6003, -1, None),
+                (1, 2, 3, Some(6001)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_remap_late_game_duplicate_flags_referred_from_other_field() -> Result<()> {
+        let surface = FieldNumber::Surface as u8;
+        let surface_night = FieldNumber::SurfaceNight as u8;
+        let gate_of_guidance = FieldNumber::GateOfGuidance as u8;
+        let starts = vec![Start {
+            flag: 702,
+            run_when: true,
+        }];
+        let mut script = script_with_fields(vec![
+            field(surface, objects()?),
+            field(surface_night, objects()?),
+            field(
+                gate_of_guidance,
+                vec![Object::new(2, 0, 0, 1, 2, 3, 4, starts)?],
+            ),
+        ]);
+        let mut flag_allocator = FlagAllocator::new(FlagUsage::new(&script));
+        assert!(remap_late_game_duplicate_flags(&mut script.worlds, &mut flag_allocator).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_remap_late_game_duplicate_flags_of_triggers() -> Result<()> {
+        let surface = FieldNumber::Surface as u8;
+        let surface_night = FieldNumber::SurfaceNight as u8;
+        // 宝箱の開くフラグ 400 を立てる仕掛け
+        let detector = Object::new(22, 0, 0, 0, 0, 400, 0, vec![])?;
+        let mut night = field(surface_night, [objects()?, vec![detector]].concat());
+        night.objects = vec![UnknownObject {
+            number: 20,
+            x: 0,
+            y: 0,
+            op1: 400,
+            op2: 0,
+            op3: 0,
+            op4: 0,
+            starts: vec![],
+        }];
+        let mut script = script_with_fields(vec![field(surface, objects()?), night]);
+        let mut flag_allocator = FlagAllocator::new(FlagUsage::new(&script));
+        remap_late_game_duplicate_flags(&mut script.worlds, &mut flag_allocator)?;
+
+        let night = &script.worlds[0].fields[1];
+        let chest_open_flag = night.maps[0].objects[0].op1();
+        assert_ne!(chest_open_flag, 400);
+        assert_eq!(night.maps[0].objects[5].op3(), chest_open_flag);
+        assert_eq!(night.objects[0].op1, chest_open_flag);
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use anyhow::{Result, anyhow, bail};
 use log::debug;
@@ -8,7 +8,7 @@ use crate::{
     script::{
         data::{
             item::{ChestItem, Equipment, Item, Rom},
-            object::{Object, Shop, Start, UnknownObject},
+            object::{Object, ObjectKind, Shop, Start, UnknownObject},
             script::{Script, World},
         },
         enums,
@@ -16,8 +16,9 @@ use crate::{
 };
 
 use super::objects_factory::{
-    map_rewrite_with_flags_replaced, to_object_for_shutter, to_object_for_special_chest,
-    to_objects_for_chest, to_objects_for_hand_scanner,
+    map_rewrite_with_flags_replaced, placeholder_chest, to_object_for_shutter,
+    to_object_for_special_chest, to_objects_for_chest, to_objects_for_filler_chest,
+    to_objects_for_hand_scanner,
 };
 
 fn fix_trap_of_mausoleum_of_the_giants(
@@ -109,6 +110,7 @@ fn replace_all_flags(starts: &[Start], replace_flag_map: &HashMap<u16, u16>) ->
         .collect()
 }
 
+#[allow(clippy::too_many_arguments)]
 fn new_objs(
     obj: &Object,
     mut field_number: enums::FieldNumber,
@@ -116,9 +118,16 @@ fn new_objs(
     script: &Script,
     shuffled: &Storage,
     replace_flag_map: &HashMap<u16, u16>,
+    late_game_duplicates: bool,
+    foreign_chests: &BTreeMap<(enums::FieldNumber, enums::ChestItem), u16>,
 ) -> Result<Vec<Object>> {
-    if field_number == enums::FieldNumber::SurfaceNight {
-        field_number = enums::FieldNumber::Surface;
+    // シャッフルしないなら、複製は元と同じアイテムのまま
+    if !late_game_duplicates {
+        field_number = match field_number {
+            enums::FieldNumber::SurfaceNight => enums::FieldNumber::Surface,
+            enums::FieldNumber::TrueShrineOfTheMother => enums::FieldNumber::ShrineOfTheMother,
+            _ => field_number,
+        };
     }
     match obj {
         Object::Chest(chest_obj) => {
@@ -136,7 +145,15 @@ fn new_objs(
                 }
                 ChestItem::Rom(Rom { content, .. }) => enums::ChestItem::Rom(*content),
             };
+            if let Some(&set_flag) = foreign_chests.get(&(field_number, chest_item)) {
+                return Ok(vec![Object::Chest(placeholder_chest(chest_obj, set_flag))]);
+            }
             let Some(chest) = shuffled.chests.get(&(field_number, chest_item)) else {
+                // アイテムプールから取り除いた地図はコインに差し替える
+                if chest_obj.item().is_map() {
+                    let taken_flag = u16::try_from(chest_obj.item().flag())?;
+                    return Ok(to_objects_for_filler_chest(chest_obj, taken_flag));
+                }
                 bail!("chest not found: {} {:?}", field_number, chest_obj.item())
             };
             let item = Item::new(&chest.item.src, script)?;
@@ -153,9 +170,8 @@ fn new_objs(
             if sub_weapon_obj.sub_weapon().content == enums::SubWeapon::AnkhJewel {
                 // Gate of Guidance
                 if sub_weapon_obj.sub_weapon().flag == 743 {
-                    let wall_check_flag = get_next_wall_check_flag(next_objs)
+                    let open_flag = get_next_wall_check_flag(next_objs)?
                         .ok_or(anyhow!("wall_check_flag not found"))?;
-                    let open_flag = u16::try_from(wall_check_flag)?;
                     return Ok(vec![to_object_for_shutter(obj, open_flag, item)]);
                 }
                 return Ok(vec![to_object_for_special_chest(obj, item)]);
@@ -214,7 +230,8 @@ fn new_objs(
             Ok(to_objects_for_hand_scanner(rom_obj, item))
         }
         Object::Seal(seal_obj) => {
-            let Some(seal) = shuffled.seals.get(&seal_obj.seal().content) else {
+            let key = (seal_obj.seal().content, field_number);
+            let Some(seal) = shuffled.seals.get(&key) else {
                 bail!("seal not found: {}", seal_obj.seal().content)
             };
             let item = Item::new(&seal.item.src, script)?;
@@ -234,11 +251,16 @@ fn new_objs(
             Ok(vec![to_object_for_shutter(obj, open_flag, item)])
         }
         Object::Unknown(unknown_obj) => {
-            match unknown_obj.number {
-                // Chests | Sub weapons | Shops | Roms | Seals | Main weapons
-                1 | 13 | 14 | 32 | 71 | 77 => unreachable!(),
-                // 59: Map rewrite
-                59 => {
+            match unknown_obj.kind() {
+                Some(
+                    ObjectKind::Chest
+                    | ObjectKind::SubWeapon
+                    | ObjectKind::Shop
+                    | ObjectKind::Rom
+                    | ObjectKind::Seal
+                    | ObjectKind::MainWeapon,
+                ) => unreachable!(),
+                Some(ObjectKind::MapRewrite) => {
                     if field_number == enums::FieldNumber::GateOfIllusion {
                         // apply ROMs replacement and mini doll
                         let mut replace_flag_map = replace_flag_map.clone();
@@ -261,7 +283,7 @@ fn new_objs(
                 }
                 // Trap object for the Ankh Jewel Treasure Chest in Mausoleum of the Giants.
                 // It is made to work correctly when acquiring items.
-                140 if unknown_obj.x == 49152 && unknown_obj.y == 16384 => {
+                Some(ObjectKind::Trap) if unknown_obj.x == 49152 && unknown_obj.y == 16384 => {
                     let key = (field_number, enums::SubWeapon::AnkhJewel);
                     let Some(sub_weapon) = shuffled.sub_weapons.get(&key) else {
                         bail!("sub_weapon not found")
@@ -273,7 +295,9 @@ fn new_objs(
                 }
                 // ヴィマーナは飛行機模型を取得したら出現しないようになっている。
                 // 飛行機模型取得後に飛行機模型の宝箱を開けられるように、飛行機模型出現のフラグに変更する。
-                186 if unknown_obj.starts.len() == 1 && unknown_obj.starts[0].flag == 788 => {
+                Some(ObjectKind::Vimana)
+                    if unknown_obj.starts.len() == 1 && unknown_obj.starts[0].flag == 788 =>
+                {
                     Ok(vec![Object::Unknown(UnknownObject {
                         starts: vec![Start {
                             flag: 891,
@@ -297,8 +321,14 @@ fn new_objs(
     }
 }
 
-pub fn replace_items(worlds: &mut [World], script: &Script, shuffled: &Storage) -> Result<()> {
+pub fn replace_items(
+    worlds: &mut [World],
+    script: &Script,
+    shuffled: &Storage,
+    foreign_chests: &BTreeMap<(enums::FieldNumber, enums::ChestItem), u16>,
+) -> Result<()> {
     let replace_flag_map = replace_flag_map(shuffled, script)?;
+    let late_game_duplicates = shuffled.has_late_game_duplicates();
     for world in worlds {
         for field in &mut world.fields {
             let field_number = field.number();
@@ -312,6 +342,8 @@ pub fn replace_items(worlds: &mut [World], script: &Script, shuffled: &Storage)
                         script,
                         shuffled,
                         &replace_flag_map,
+                        late_game_duplicates,
+                        foreign_chests,
                     )?);
                 }
                 map.objects = objects;
@@ -321,24 +353,27 @@ pub fn replace_items(worlds: &mut [World], script: &Script, shuffled: &Storage)
     Ok(())
 }
 
+fn find_next_unknown_object(objs: &[Object], kind: ObjectKind) -> Option<&UnknownObject> {
+    objs.iter().find_map(|x| match x {
+        Object::Unknown(x) if x.kind() == Some(kind) => Some(x),
+        _ => None,
+    })
+}
+
 fn get_next_shutter_check_flag(objs: &[Object]) -> Result<Option<u16>> {
-    Ok(objs
-        .iter()
-        .find(|x| x.number() == 20)
-        .map(|x| u16::try_from(x.op1()))
-        .transpose()?)
+    find_next_unknown_object(objs, ObjectKind::Hitbox)
+        .map(|x| x.set_flag())
+        .transpose()
 }
 
-fn get_next_wall_check_flag(objs: &[Object]) -> Option<i32> {
-    Some(objs.iter().find(|x| x.number() == 59)?.op3())
+fn get_next_wall_check_flag(objs: &[Object]) -> Result<Option<u16>> {
+    find_next_unknown_object(objs, ObjectKind::MapRewrite)
+        .map(|x| x.trigger_flag())
+        .transpose()
 }
 
 fn get_next_breakable_wall_check_flag(objs: &[Object]) -> Result<Option<u16>> {
-    objs.iter()
-        .find(|x| x.number() == 70)
-        .map(|x| {
-            let data = x.op4();
-            Ok(u16::try_from((data - (data / 10000) * 10000) / 10)?)
-        })
+    find_next_unknown_object(objs, ObjectKind::BreakableWall)
+        .map(|x| x.set_flag())
         .transpose()
 }
@@ -0,0 +1,362 @@
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::script::{
+    data::{
+        item::Item,
+        object::{Object, Start, UnknownObject},
+        script::{Field, Script},
+        shop_items_data::{self, ShopItem},
+    },
+    enums::SubWeapon,
+    file::objectdocument::{
+        ObjectDocument, StartDocument, object_from_document, parse_enum, starts_from_document,
+        talk_from_text,
+    },
+};
+
+/// パッチを適用するタイミング
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PatchPhase {
+    #[default]
+    BeforeRandomization,
+    AfterRandomization,
+}
+
+/// `map` を省略するとフィールド直下のオブジェクトを指す
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectLocation {
+    pub world: u8,
+    pub field: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub map: Option<(u8, u8, u8)>,
+    pub index: usize,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShopSubWeaponSale {
+    pub amount: u8,
+    pub price: u16,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum PatchOperation {
+    InsertObject {
+        at: ObjectLocation,
+        object: ObjectDocument,
+    },
+    ReplaceObject {
+        at: ObjectLocation,
+        object: ObjectDocument,
+    },
+    DeleteObject {
+        at: ObjectLocation,
+    },
+    ReplaceStarts {
+        at: ObjectLocation,
+        starts: Vec<StartDocument>,
+    },
+    ReplaceTalk {
+        talk: usize,
+        text: String,
+    },
+    /// 全ての店で `from` の量と値段で売られている `content` を `to` に変える
+    ReplaceShopSubWeapon {
+        content: String,
+        from: ShopSubWeaponSale,
+        to: ShopSubWeaponSale,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptPatch {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub phase: PatchPhase,
+    pub operations: Vec<PatchOperation>,
+}
+
+impl ScriptPatch {
+    pub fn new(raw_str: &str) -> serde_yaml::Result<Self> {
+        serde_yaml::from_str(raw_str)
+    }
+}
+
+/// ランダマイザーに組み込みのパッチ
+pub fn builtin_script_patches() -> Vec<ScriptPatch> {
+    [include_str!(
+        "../../../res/patches/normalize_shuriken_sale.yml"
+    )]
+    .into_iter()
+    .map(|x| ScriptPatch::new(x).unwrap())
+    .collect()
+}
+
+fn find_field<'a>(script: &'a mut Script, at: &ObjectLocation) -> Result<&'a mut Field> {
+    script
+        .worlds
+        .iter_mut()
+        .filter(|world| world.number == at.world)
+        .flat_map(|world| &mut world.fields)
+        .find(|field| field.attrs.0 == at.field)
+        .ok_or_else(|| anyhow!("field not found: world={}, field={}", at.world, at.field))
+}
+
+/// フィールド直下のオブジェクトとマップのオブジェクトを同じように扱うための参照
+enum Objects<'a> {
+    Field(&'a mut Vec<UnknownObject>),
+    Map(&'a mut Vec<Object>),
+}
+
+impl Objects<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Objects::Field(objs) => objs.len(),
+            Objects::Map(objs) => objs.len(),
+        }
+    }
+
+    fn check_index(&self, index: usize) -> Result<()> {
+        if index >= self.len() {
+            bail!("object index out of range: {} >= {}", index, self.len());
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, index: usize, obj: Object) -> Result<()> {
+        if index > self.len() {
+            bail!("object index out of range: {} > {}", index, self.len());
+        }
+        match self {
+            Objects::Field(objs) => objs.insert(index, to_field_object(obj)?),
+            Objects::Map(objs) => objs.insert(index, obj),
+        }
+        Ok(())
+    }
+
+    fn replace(&mut self, index: usize, obj: Object) -> Result<()> {
+        self.check_index(index)?;
+        match self {
+            Objects::Field(objs) => objs[index] = to_field_object(obj)?,
+            Objects::Map(objs) => objs[index] = obj,
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, index: usize) -> Result<()> {
+        self.check_index(index)?;
+        match self {
+            Objects::Field(objs) => drop(objs.remove(index)),
+            Objects::Map(objs) => drop(objs.remove(index)),
+        }
+        Ok(())
+    }
+
+    fn replace_starts(&mut self, index: usize, starts: Vec<Start>) -> Result<()> {
+        self.check_index(index)?;
+        match self {
+            Objects::Field(objs) => objs[index].starts = starts,
+            Objects::Map(objs) => {
+                let obj = &objs[index];
+                objs[index] = Object::new(
+                    obj.number(),
+                    obj.x(),
+                    obj.y(),
+                    obj.op1(),
+                    obj.op2(),
+                    obj.op3(),
+                    obj.op4(),
+                    starts,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn to_field_object(obj: Object) -> Result<UnknownObject> {
+    let Object::Unknown(obj) = obj else {
+        bail!(
+            "field objects must be unknown objects: number={}",
+            obj.number()
+        );
+    };
+    Ok(obj)
+}
+
+fn find_objects<'a>(script: &'a mut Script, at: &ObjectLocation) -> Result<Objects<'a>> {
+    let field = find_field(script, at)?;
+    let Some(map_attrs) = at.map else {
+        return Ok(Objects::Field(&mut field.objects));
+    };
+    let map = field
+        .maps
+        .iter_mut()
+        .find(|map| map.attrs == map_attrs)
+        .ok_or_else(|| anyhow!("map not found: {:?}", map_attrs))?;
+    Ok(Objects::Map(&mut map.objects))
+}
+
+fn apply_operation(script: &mut Script, operation: &PatchOperation) -> Result<()> {
+    match operation {
+        PatchOperation::InsertObject { at, object } => {
+            let obj = object_from_document(object.clone())?;
+            find_objects(script, at)?.insert(at.index, obj)
+        }
+        PatchOperation::ReplaceObject { at, object } => {
+            let obj = object_from_document(object.clone())?;
+            find_objects(script, at)?.replace(at.index, obj)
+        }
+        PatchOperation::DeleteObject { at } => find_objects(script, at)?.remove(at.index),
+        PatchOperation::ReplaceStarts { at, starts } => {
+            let starts = starts_from_document(starts.clone());
+            find_objects(script, at)?.replace_starts(at.index, starts)
+        }
+        PatchOperation::ReplaceTalk { talk, text } => {
+            let Some(old) = script.talks.get_mut(*talk) else {
+                bail!("talk not found: {}", talk);
+            };
+            *old = talk_from_text(text)?;
+            Ok(())
+        }
+        PatchOperation::ReplaceShopSubWeapon { content, from, to } => {
+            let content: SubWeapon = parse_enum(content)?;
+            for talk in &mut script.talks {
+                let Ok((a, b, c)) = shop_items_data::parse(talk) else {
+                    continue;
+                };
+                let items = (
+                    replace_shop_sub_weapon(a, content, *from, *to),
+                    replace_shop_sub_weapon(b, content, *from, *to),
+                    replace_shop_sub_weapon(c, content, *from, *to),
+                );
+                *talk = shop_items_data::stringify(items)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn replace_shop_sub_weapon(
+    item: ShopItem,
+    content: SubWeapon,
+    from: ShopSubWeaponSale,
+    to: ShopSubWeaponSale,
+) -> ShopItem {
+    let ShopItem::SubWeapon(shop_sub_weapon) = &item else {
+        return item;
+    };
+    let sub_weapon = &shop_sub_weapon.item;
+    if sub_weapon.content != content
+        || sub_weapon.price != Some(from.price)
+        || sub_weapon.amount != from.amount
+    {
+        return item;
+    }
+    let mut sub_weapon = sub_weapon.clone();
+    sub_weapon.price = Some(to.price);
+    sub_weapon.amount = to.amount;
+    ShopItem::from_item(Item::SubWeapon(sub_weapon), to.price)
+}
+
+/// `phase` が一致するパッチだけを順番に適用する
+pub fn apply_script_patches(
+    script: &mut Script,
+    patches: &[ScriptPatch],
+    phase: PatchPhase,
+) -> Result<()> {
+    for patch in patches.iter().filter(|x| x.phase == phase) {
+        for (i, operation) in patch.operations.iter().enumerate() {
+            apply_operation(script, operation).with_context(|| {
+                format!("failed to apply patch {:?}: operation {}", patch.name, i)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script::{data::item, fixture};
+
+    use super::*;
+
+    fn script() -> Result<Script> {
+        Ok(fixture::script(vec![
+            Object::new(1, 10, 20, 400, 1, 768, 0, vec![])?,
+            Object::new(2, 0, 0, 1, 2, 3, 4, vec![])?,
+        ]))
+    }
+
+    #[test]
+    fn test_apply_script_patches() -> Result<()> {
+        let patch = ScriptPatch::new(
+            r#"
+name: test
+phase: afterRandomization
+operations:
+  - op: deleteObject
+    at: { world: 0, field: 1, map: [0, 1, 2], index: 1 }
+  - op: replaceStarts
+    at: { world: 0, field: 1, map: [0, 1, 2], index: 0 }
+    starts: [{ flag: 99999, runWhen: true }]
+  - op: insertObject
+    at: { world: 0, field: 1, index: 0 }
+    object: { type: unknown, number: 20, x: 0, y: 0, op1: 900, op2: -1, op3: -1, op4: -1, starts: [] }
+  - op: replaceTalk
+    talk: 0
+    text: abd
+"#,
+        )?;
+        let mut script = script()?;
+        let patches = [patch];
+        apply_script_patches(&mut script, &patches, PatchPhase::BeforeRandomization)?;
+        assert_eq!(script.worlds[0].fields[0].maps[0].objects.len(), 2);
+
+        apply_script_patches(&mut script, &patches, PatchPhase::AfterRandomization)?;
+        let field = &script.worlds[0].fields[0];
+        assert_eq!(field.objects[0].set_flag()?, 900);
+        assert_eq!(field.maps[0].objects.len(), 1);
+        assert_eq!(field.maps[0].objects[0].starts()[0].flag, 99999);
+        assert_eq!(script.talks[0].to_string(), "abd");
+        Ok(())
+    }
+
+    #[test]
+    fn test_builtin_script_patches() -> Result<()> {
+        let shop_sub_weapon = |amount, price| {
+            let sub_weapon = item::SubWeapon {
+                content: SubWeapon::Shuriken,
+                amount,
+                price: Some(price),
+                flag: 65279,
+            };
+            ShopItem::from_item(Item::SubWeapon(sub_weapon), price)
+        };
+        let mut script = script()?;
+        script.talks.push(shop_items_data::stringify((
+            shop_sub_weapon(40, 20),
+            shop_sub_weapon(10, 5),
+            shop_sub_weapon(40, 20),
+        ))?);
+        let patches = builtin_script_patches();
+        apply_script_patches(&mut script, &patches, PatchPhase::BeforeRandomization)?;
+
+        let (a, b, c) = shop_items_data::parse(&script.talks[1])?;
+        let sales: Vec<_> = [a, b, c]
+            .into_iter()
+            .map(|x| match x {
+                ShopItem::SubWeapon(x) => (x.item.amount, x.item.price),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(sales, [(20, Some(10)), (10, Some(5)), (20, Some(10))]);
+        Ok(())
+    }
+}
@@ -8,10 +8,13 @@ static REVERSE_MAP: LazyLock<BTreeMap<u8, FieldNumber>> = LazyLock::new(|| {
         let field_number = FieldNumber::from_u8(i).unwrap();
         map.insert(field_number.to_logic_number().unwrap(), field_number);
     }
+    let surface_night = FieldNumber::SurfaceNight;
+    map.insert(surface_night.to_logic_number().unwrap(), surface_night);
     map
 });
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, num_derive::FromPrimitive)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, num_derive::FromPrimitive, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 #[repr(u8)]
 pub enum FieldNumber {
     GateOfGuidance = 0,
@@ -78,6 +81,7 @@ impl FieldNumber {
             FieldNumber::DimensionalCorridor => Some(18),
             FieldNumber::TrueShrineOfTheMother => Some(19),
             FieldNumber::GaliousCastle => Some(20),
+            FieldNumber::SurfaceNight => Some(22),
             FieldNumber::GaliousWorld
             | FieldNumber::GaliousSmallShrine
             | FieldNumber::GaliousMagicSquare
@@ -86,7 +90,6 @@ impl FieldNumber {
             | FieldNumber::GaliousFanfare
             | FieldNumber::HellTemple1
             | FieldNumber::HellTemple2
-            | FieldNumber::SurfaceNight
             | FieldNumber::LavaPit
             | FieldNumber::GaliousStagesSwitch
             | FieldNumber::Pr3
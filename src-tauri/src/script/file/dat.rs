@@ -32,13 +32,15 @@ pub fn reverse_code_map() -> HashMap<char, u8> {
         .collect()
 }
 
+/// 暗号化された script.dat の先頭らしいかどうか
+pub fn is_cipher_text(from: &[u8]) -> bool {
+    from.len() > 16 && from[0] != 0 && from[0] == from[16] && from[0] != from[1]
+}
+
 pub fn cipher_to_text(from: &[u8]) -> String {
-    debug_assert_ne!(from[0], 0);
+    debug_assert!(is_cipher_text(from));
     let code_map = code_map();
-    let decrypted: String = from.iter().map(|x| code_map[(x ^ KEY) as usize]).collect();
-    debug_assert_eq!(decrypted.chars().next(), decrypted.chars().nth(16));
-    debug_assert_ne!(decrypted.chars().next(), decrypted.chars().nth(1));
-    decrypted
+    from.iter().map(|x| code_map[(x ^ KEY) as usize]).collect()
 }
 
 pub fn text_to_cipher(from: &str) -> Vec<u8> {
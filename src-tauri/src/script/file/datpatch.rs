@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow, bail};
+
+use super::scriptconverter::script_dat_hash;
+
+const MAGIC: &[u8; 4] = b"LMOP";
+const VERSION: u8 = 1;
+const HASH_LEN: usize = 64;
+/// これより短い一致はコピーせずに挿入する
+const MIN_MATCH_LEN: usize = 8;
+
+const OP_COPY: u8 = 0;
+const OP_INSERT: u8 = 1;
+
+fn write_varint(output: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        output.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn read_varint(input: &[u8], pos: &mut usize) -> Result<usize> {
+    let mut value = 0usize;
+    for shift in (0..usize::BITS).step_by(7) {
+        let byte = *input
+            .get(*pos)
+            .ok_or_else(|| anyhow!("unexpected end of patch"))?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("varint too long")
+}
+
+fn window_key(window: &[u8]) -> u64 {
+    u64::from_le_bytes(window.try_into().unwrap())
+}
+
+fn match_len(base: &[u8], target: &[u8]) -> usize {
+    base.iter().zip(target).take_while(|(a, b)| a == b).count()
+}
+
+struct PatchWriter {
+    output: Vec<u8>,
+    pending_insert: Vec<u8>,
+}
+
+impl PatchWriter {
+    fn flush_insert(&mut self) {
+        if self.pending_insert.is_empty() {
+            return;
+        }
+        self.output.push(OP_INSERT);
+        write_varint(&mut self.output, self.pending_insert.len());
+        self.output.append(&mut self.pending_insert);
+    }
+
+    fn copy(&mut self, offset: usize, len: usize) {
+        self.flush_insert();
+        self.output.push(OP_COPY);
+        write_varint(&mut self.output, offset);
+        write_varint(&mut self.output, len);
+    }
+}
+
+/// `base` からの差分だけを含むパッチを作る。
+/// `base` と結果のハッシュ、参加者同士で確認するための fingerprint を埋め込む
+pub fn create_dat_patch(base: &[u8], target: &[u8], fingerprint: &str) -> Vec<u8> {
+    let mut index = HashMap::new();
+    for (i, window) in base.windows(MIN_MATCH_LEN).enumerate() {
+        index.entry(window_key(window)).or_insert(i);
+    }
+
+    let mut writer = PatchWriter {
+        output: Vec::new(),
+        pending_insert: Vec::new(),
+    };
+    writer.output.extend_from_slice(MAGIC);
+    writer.output.push(VERSION);
+    writer.output.extend(script_dat_hash(base));
+    writer.output.extend(script_dat_hash(target));
+    write_varint(&mut writer.output, fingerprint.len());
+    writer.output.extend_from_slice(fingerprint.as_bytes());
+
+    // 書き換えは局所的なので、直前のコピーの続きを優先する
+    let mut expected = 0;
+    let mut i = 0;
+    while i < target.len() {
+        let rest = &target[i..];
+        let sequential = base.get(expected..).map_or(0, |x| match_len(x, rest));
+        let (offset, len) = if sequential >= MIN_MATCH_LEN {
+            (expected, sequential)
+        } else if rest.len() >= MIN_MATCH_LEN
+            && let Some(&offset) = index.get(&window_key(&rest[..MIN_MATCH_LEN]))
+        {
+            (offset, match_len(&base[offset..], rest))
+        } else {
+            (0, 0)
+        };
+        if len < MIN_MATCH_LEN {
+            writer.pending_insert.push(target[i]);
+            i += 1;
+            continue;
+        }
+        writer.copy(offset, len);
+        expected = offset + len;
+        i += len;
+    }
+    writer.flush_insert();
+    writer.output
+}
+
+/// `base` のハッシュがパッチと一致する場合だけ適用する。結果と埋め込まれた fingerprint を返す
+pub fn apply_dat_patch(base: &[u8], patch: &[u8]) -> Result<(Vec<u8>, String)> {
+    let hashes_end = MAGIC.len() + 1 + HASH_LEN * 2;
+    if patch.len() < hashes_end || &patch[..MAGIC.len()] != MAGIC {
+        bail!("Not a script.dat patch");
+    }
+    let version = patch[MAGIC.len()];
+    if version != VERSION {
+        bail!("Unsupported patch version: {}", version);
+    }
+    let hashes = &patch[MAGIC.len() + 1..hashes_end];
+    let (base_hash, target_hash) = hashes.split_at(HASH_LEN);
+    let mut pos = hashes_end;
+    let fingerprint_len = read_varint(patch, &mut pos)?;
+    let Some(fingerprint) = pos
+        .checked_add(fingerprint_len)
+        .and_then(|end| patch.get(pos..end))
+    else {
+        bail!("unexpected end of patch");
+    };
+    let fingerprint = String::from_utf8(fingerprint.to_vec())?;
+    pos += fingerprint_len;
+    if script_dat_hash(base) != base_hash {
+        bail!("script.dat does not match the base of the patch");
+    }
+
+    let mut output = Vec::new();
+    while pos < patch.len() {
+        let op = patch[pos];
+        pos += 1;
+        match op {
+            OP_COPY => {
+                let offset = read_varint(patch, &mut pos)?;
+                let len = read_varint(patch, &mut pos)?;
+                let Some(bytes) = offset
+                    .checked_add(len)
+                    .and_then(|end| base.get(offset..end))
+                else {
+                    bail!("copy out of range: offset={}, len={}", offset, len);
+                };
+                output.extend_from_slice(bytes);
+            }
+            OP_INSERT => {
+                let len = read_varint(patch, &mut pos)?;
+                let Some(bytes) = pos.checked_add(len).and_then(|end| patch.get(pos..end)) else {
+                    bail!("unexpected end of patch");
+                };
+                output.extend_from_slice(bytes);
+                pos += len;
+            }
+            _ => bail!("unknown patch operation: {}", op),
+        }
+    }
+    if script_dat_hash(&output) != target_hash {
+        bail!("Patched script.dat is broken");
+    }
+    Ok((output, fingerprint))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dat_patch_round_trip() -> Result<()> {
+        let base: Vec<u8> = (0..10000u32).map(|x| (x * 7 % 251) as u8).collect();
+        let mut target = base.clone();
+        target[100] ^= 1;
+        target.splice(5000..5000, b"inserted".iter().copied());
+        target.drain(8000..8100);
+
+        let fingerprint = "Feather, Boots, Glove, Scanner, Grail";
+        let patch = create_dat_patch(&base, &target, fingerprint);
+        assert!(patch.len() < 300, "{}", patch.len());
+        let (patched, patched_fingerprint) = apply_dat_patch(&base, &patch)?;
+        assert_eq!(patched, target);
+        assert_eq!(patched_fingerprint, fingerprint);
+
+        let mut other = base.clone();
+        other[0] ^= 1;
+        assert!(apply_dat_patch(&other, &patch).is_err());
+        Ok(())
+    }
+}
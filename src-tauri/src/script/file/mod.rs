@@ -1,3 +1,8 @@
 pub mod dat;
+pub mod datpatch;
+pub mod objectdocument;
 pub mod scriptconverter;
+// lmocodec だけが使う
+#[allow(dead_code)]
+pub mod scriptdecompiler;
 pub mod scripttxtparser;
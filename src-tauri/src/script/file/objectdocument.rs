@@ -0,0 +1,257 @@
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::script::{
+    data::{
+        item::{self, ChestItem},
+        object::{
+            ChestObject, MainWeaponObject, Object, ObjectKind, RomObject, SealObject, ShopObject,
+            Start, SubWeaponObject, UnknownObject,
+        },
+        talk::Talk,
+    },
+    enums,
+};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartDocument {
+    pub flag: u32,
+    pub run_when: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ChestItemDocument {
+    None { flag: i32 },
+    Equipment { content: String, flag: u16 },
+    Rom { content: String, flag: u16 },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ObjectDocument {
+    Chest {
+        x: i32,
+        y: i32,
+        open_flag: u16,
+        item: ChestItemDocument,
+        op4: i32,
+        starts: Vec<StartDocument>,
+    },
+    SubWeapon {
+        x: i32,
+        y: i32,
+        content: String,
+        amount: u8,
+        flag: u16,
+        starts: Vec<StartDocument>,
+    },
+    Shop {
+        x: i32,
+        y: i32,
+        form: i32,
+        music: i32,
+        op3: i32,
+        op4: i32,
+        starts: Vec<StartDocument>,
+    },
+    Rom {
+        x: i32,
+        y: i32,
+        content: String,
+        flag: u16,
+        starts: Vec<StartDocument>,
+    },
+    Seal {
+        x: i32,
+        y: i32,
+        content: String,
+        flag: u16,
+        starts: Vec<StartDocument>,
+    },
+    MainWeapon {
+        x: i32,
+        y: i32,
+        content: String,
+        flag: u16,
+        starts: Vec<StartDocument>,
+    },
+    Unknown(UnknownObjectDocument),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnknownObjectDocument {
+    pub number: u16,
+    /// ObjectKind の名前。読みやすさのためだけに出力し、読み込み時は number との整合性だけを確認する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    pub x: i32,
+    pub y: i32,
+    pub op1: i32,
+    pub op2: i32,
+    pub op3: i32,
+    pub op4: i32,
+    pub starts: Vec<StartDocument>,
+}
+
+pub fn parse_enum<T: FromStr>(content: &str) -> Result<T> {
+    T::from_str(content).map_err(|_| anyhow!("invalid content: {}", content))
+}
+
+pub fn starts_from_document(starts: Vec<StartDocument>) -> Vec<Start> {
+    starts
+        .into_iter()
+        .map(|x| Start {
+            flag: x.flag,
+            run_when: x.run_when,
+        })
+        .collect()
+}
+
+pub fn unknown_object_from_document(doc: UnknownObjectDocument) -> Result<UnknownObject> {
+    if let Some(kind) = &doc.kind
+        && ObjectKind::from_number(doc.number)
+            .map(|x| x.to_string())
+            .as_ref()
+            != Some(kind)
+    {
+        bail!("kind {} does not match object number {}", kind, doc.number);
+    }
+    Ok(UnknownObject {
+        number: doc.number,
+        x: doc.x,
+        y: doc.y,
+        op1: doc.op1,
+        op2: doc.op2,
+        op3: doc.op3,
+        op4: doc.op4,
+        starts: starts_from_document(doc.starts),
+    })
+}
+
+pub fn object_from_document(doc: ObjectDocument) -> Result<Object> {
+    Ok(match doc {
+        ObjectDocument::Chest {
+            x,
+            y,
+            open_flag,
+            item,
+            op4,
+            starts,
+        } => {
+            let item = match item {
+                ChestItemDocument::None { flag } => ChestItem::None(flag),
+                ChestItemDocument::Equipment { content, flag } => {
+                    ChestItem::Equipment(item::Equipment {
+                        content: parse_enum(&content)?,
+                        price: None,
+                        flag,
+                    })
+                }
+                ChestItemDocument::Rom { content, flag } => ChestItem::Rom(item::Rom {
+                    content: parse_enum(&content)?,
+                    price: None,
+                    flag,
+                }),
+            };
+            let starts = starts_from_document(starts);
+            Object::Chest(ChestObject::new(x, y, open_flag, item, op4, starts))
+        }
+        ObjectDocument::SubWeapon {
+            x,
+            y,
+            content,
+            amount,
+            flag,
+            starts,
+        } => {
+            let sub_weapon = item::SubWeapon {
+                content: parse_enum::<enums::SubWeapon>(&content)?,
+                amount,
+                price: None,
+                flag,
+            };
+            let starts = starts_from_document(starts);
+            Object::SubWeapon(SubWeaponObject::new(x, y, sub_weapon, starts))
+        }
+        ObjectDocument::Shop {
+            x,
+            y,
+            form,
+            music,
+            op3,
+            op4,
+            starts,
+        } => {
+            let starts = starts_from_document(starts);
+            Object::Shop(ShopObject::new(x, y, form, music, op3, op4, starts)?)
+        }
+        ObjectDocument::Rom {
+            x,
+            y,
+            content,
+            flag,
+            starts,
+        } => {
+            let rom = item::Rom {
+                content: parse_enum(&content)?,
+                price: None,
+                flag,
+            };
+            Object::Rom(RomObject::new(x, y, rom, starts_from_document(starts)))
+        }
+        ObjectDocument::Seal {
+            x,
+            y,
+            content,
+            flag,
+            starts,
+        } => {
+            let seal = item::Seal {
+                content: parse_enum(&content)?,
+                flag,
+            };
+            Object::Seal(SealObject::new(x, y, seal, starts_from_document(starts)))
+        }
+        ObjectDocument::MainWeapon {
+            x,
+            y,
+            content,
+            flag,
+            starts,
+        } => {
+            let main_weapon = item::MainWeapon {
+                content: parse_enum(&content)?,
+                flag,
+            };
+            let starts = starts_from_document(starts);
+            Object::MainWeapon(MainWeaponObject::new(x, y, main_weapon, starts))
+        }
+        ObjectDocument::Unknown(doc) => {
+            // 既知の番号は型付きで書かないと再構築後の Object の型が変わってしまう
+            let obj = unknown_object_from_document(doc)?;
+            match Object::new(
+                obj.number, obj.x, obj.y, obj.op1, obj.op2, obj.op3, obj.op4, obj.starts,
+            )? {
+                Object::Unknown(obj) => Object::Unknown(obj),
+                _ => bail!(
+                    "object number {} must be written as a typed object",
+                    obj.number
+                ),
+            }
+        }
+    })
+}
+
+/// 制御コマンドを `{setFlag 123}` のように書いた会話
+pub fn talk_from_text(text: &str) -> Result<Talk> {
+    Talk::from_readable_string(text)
+}
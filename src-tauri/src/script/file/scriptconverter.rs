@@ -1,17 +1,52 @@
 use anyhow::{Result, bail};
 use sha3::Digest;
 
-use crate::script::data::script::Script;
+use crate::script::{
+    data::{script::Script, shop_items_data},
+    enums::SubWeapon,
+};
 
-use super::dat::{cipher_to_text, text_to_cipher};
+use super::dat::{cipher_to_text, is_cipher_text, text_to_cipher};
 
 const SCRIPT_DAT_HASH: &str = "d18f3a643bee62db6870b35b1a1781bcc4067bd7409fa620168e16054ddc7ce645463b59e06d0768d87eff9ad9bdc1f0efd04dbc498d2e5de73d5a863a692a90";
 const SCRIPT_DAT_EN_HASH: &str = "146e1b6e9e63ed22fb84b3c38f4d25a0723b07fe3fefe9395af68d6eeaa3b1108b288847ec50114efff4e7600afccc68a983d681b94cbb55a507b21f45d52db7";
 
+/// 既知の script.dat の内容だけを確かめる。改造済みの script.dat には使わない
+fn debug_assert_vanilla_script(script: &Script) -> Result<()> {
+    let first_shop = shop_items_data::parse(&script.talks[252])?;
+    debug_assert_eq!(first_shop.0.number(), SubWeapon::HandScanner as u8);
+    debug_assert_eq!(first_shop.0.price(), 20);
+    debug_assert_eq!(first_shop.0.flag(), 696);
+    debug_assert_eq!(first_shop.1.number(), SubWeapon::Ammunition as u8);
+    debug_assert_eq!(first_shop.1.price(), 500);
+    debug_assert_eq!(first_shop.1.flag(), 65279);
+    debug_assert_eq!(first_shop.2.number(), SubWeapon::Buckler as u8);
+    debug_assert_eq!(first_shop.2.price(), 80);
+    debug_assert_eq!(first_shop.2.flag(), 697);
+
+    let worlds = &script.worlds;
+    debug_assert_eq!(script.talks.len(), 905);
+    debug_assert_eq!(worlds[0].fields[0].objects[0].starts[0].flag, 99999);
+    debug_assert_eq!(worlds[0].fields[0].maps[0].objects[5].starts()[0].flag, 58);
+    Ok(())
+}
+
 pub fn read_script_dat(file: &[u8]) -> Result<Script> {
     if !is_valid_script_dat(file) {
         bail!("Invalid script.dat file");
     }
+    let script = parse_script_dat(file)?;
+    if cfg!(debug_assertions) {
+        debug_assert_vanilla_script(&script)?;
+    }
+    Ok(script)
+}
+
+/// ハッシュを検証しないので、改造済みの script.dat も読める
+pub fn parse_script_dat(file: &[u8]) -> Result<Script> {
+    if !is_cipher_text(file) {
+        bail!("Invalid script.dat file");
+    }
     let txt = cipher_to_text(file);
     Script::parse(&txt)
 }
@@ -21,8 +56,24 @@ pub fn build_script_dat(script: &Script) -> Vec<u8> {
     text_to_cipher(&txt)
 }
 
+pub fn script_dat_hash(file: &[u8]) -> Vec<u8> {
+    sha3::Sha3_512::digest(file).to_vec()
+}
+
 pub fn is_valid_script_dat(file: &[u8]) -> bool {
-    let script_dat_hash = sha3::Sha3_512::digest(file).to_vec();
+    let script_dat_hash = script_dat_hash(file);
     script_dat_hash == hex::decode(SCRIPT_DAT_HASH).unwrap()
         || script_dat_hash == hex::decode(SCRIPT_DAT_EN_HASH).unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script_dat_rejects_non_script() {
+        assert!(parse_script_dat(&[]).is_err());
+        assert!(parse_script_dat(&[0x1d; 16]).is_err());
+        assert!(parse_script_dat(&[0; 32]).is_err());
+    }
+}
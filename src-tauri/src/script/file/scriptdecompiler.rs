@@ -0,0 +1,379 @@
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::script::{
+    data::{
+        item::ChestItem,
+        object::{Object, Start, UnknownObject},
+        script::{Field, Map, Script, World},
+    },
+    file::objectdocument::{
+        ChestItemDocument, ObjectDocument, StartDocument, UnknownObjectDocument,
+        object_from_document, talk_from_text, unknown_object_from_document,
+    },
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DocumentFormat {
+    Yaml,
+    Json,
+}
+
+impl DocumentFormat {
+    /// `.json` なら JSON、それ以外は YAML
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Yaml,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MapDocument {
+    attrs: (u8, u8, u8),
+    up: (i8, i8, i8, i8),
+    right: (i8, i8, i8, i8),
+    down: (i8, i8, i8, i8),
+    left: (i8, i8, i8, i8),
+    objects: Vec<ObjectDocument>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FieldDocument {
+    attrs: (u8, u8, u8, u8, u8),
+    chip_line: (u16, u16),
+    hits: Vec<(i16, i16)>,
+    animes: Vec<Vec<u16>>,
+    objects: Vec<UnknownObjectDocument>,
+    maps: Vec<MapDocument>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WorldDocument {
+    number: u8,
+    fields: Vec<FieldDocument>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScriptDocument {
+    talks: Vec<String>,
+    worlds: Vec<WorldDocument>,
+}
+
+fn starts_to_document(starts: &[Start]) -> Vec<StartDocument> {
+    starts
+        .iter()
+        .map(|x| StartDocument {
+            flag: x.flag,
+            run_when: x.run_when,
+        })
+        .collect()
+}
+
+fn unknown_object_to_document(obj: &UnknownObject) -> UnknownObjectDocument {
+    UnknownObjectDocument {
+        number: obj.number,
+        kind: obj.kind().map(|x| x.to_string()),
+        x: obj.x,
+        y: obj.y,
+        op1: obj.op1,
+        op2: obj.op2,
+        op3: obj.op3,
+        op4: obj.op4,
+        starts: starts_to_document(&obj.starts),
+    }
+}
+
+fn object_to_document(obj: &Object) -> ObjectDocument {
+    match obj {
+        Object::Chest(obj) => ObjectDocument::Chest {
+            x: obj.x(),
+            y: obj.y(),
+            open_flag: obj.open_flag(),
+            item: match obj.item() {
+                ChestItem::None(flag) => ChestItemDocument::None { flag: *flag },
+                ChestItem::Equipment(item) => ChestItemDocument::Equipment {
+                    content: item.content.to_string(),
+                    flag: item.flag,
+                },
+                ChestItem::Rom(item) => ChestItemDocument::Rom {
+                    content: item.content.to_string(),
+                    flag: item.flag,
+                },
+            },
+            op4: obj.op4(),
+            starts: starts_to_document(obj.starts()),
+        },
+        Object::SubWeapon(obj) => ObjectDocument::SubWeapon {
+            x: obj.x(),
+            y: obj.y(),
+            content: obj.sub_weapon().content.to_string(),
+            amount: obj.sub_weapon().amount,
+            flag: obj.sub_weapon().flag,
+            starts: starts_to_document(obj.starts()),
+        },
+        Object::Shop(obj) => ObjectDocument::Shop {
+            x: obj.x(),
+            y: obj.y(),
+            form: obj.form(),
+            music: obj.music(),
+            op3: obj.op3(),
+            op4: obj.op4(),
+            starts: starts_to_document(obj.starts()),
+        },
+        Object::Rom(obj) => ObjectDocument::Rom {
+            x: obj.x(),
+            y: obj.y(),
+            content: obj.rom().content.to_string(),
+            flag: obj.rom().flag,
+            starts: starts_to_document(obj.starts()),
+        },
+        Object::Seal(obj) => ObjectDocument::Seal {
+            x: obj.x(),
+            y: obj.y(),
+            content: obj.seal().content.to_string(),
+            flag: obj.seal().flag,
+            starts: starts_to_document(obj.starts()),
+        },
+        Object::MainWeapon(obj) => ObjectDocument::MainWeapon {
+            x: obj.x(),
+            y: obj.y(),
+            content: obj.main_weapon().content.to_string(),
+            flag: obj.main_weapon().flag,
+            starts: starts_to_document(obj.starts()),
+        },
+        Object::Unknown(obj) => ObjectDocument::Unknown(unknown_object_to_document(obj)),
+    }
+}
+
+fn to_document(script: &Script) -> ScriptDocument {
+    ScriptDocument {
+        talks: script
+            .talks
+            .iter()
+            .map(|x| x.to_readable_string())
+            .collect(),
+        worlds: script
+            .worlds
+            .iter()
+            .map(|world| WorldDocument {
+                number: world.number,
+                fields: world
+                    .fields
+                    .iter()
+                    .map(|field| FieldDocument {
+                        attrs: field.attrs,
+                        chip_line: field.chip_line,
+                        hits: field.hits.clone(),
+                        animes: field.animes.clone(),
+                        objects: field
+                            .objects
+                            .iter()
+                            .map(unknown_object_to_document)
+                            .collect(),
+                        maps: field
+                            .maps
+                            .iter()
+                            .map(|map| MapDocument {
+                                attrs: map.attrs,
+                                up: map.up,
+                                right: map.right,
+                                down: map.down,
+                                left: map.left,
+                                objects: map.objects.iter().map(object_to_document).collect(),
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+fn from_document(doc: ScriptDocument) -> Result<Script> {
+    Ok(Script {
+        talks: doc
+            .talks
+            .iter()
+            .map(|x| talk_from_text(x))
+            .collect::<Result<_>>()?,
+        worlds: doc
+            .worlds
+            .into_iter()
+            .map(|world| {
+                Ok(World {
+                    number: world.number,
+                    fields: world
+                        .fields
+                        .into_iter()
+                        .map(|field| {
+                            Ok(Field {
+                                attrs: field.attrs,
+                                chip_line: field.chip_line,
+                                hits: field.hits,
+                                animes: field.animes,
+                                objects: field
+                                    .objects
+                                    .into_iter()
+                                    .map(unknown_object_from_document)
+                                    .collect::<Result<_>>()?,
+                                maps: field
+                                    .maps
+                                    .into_iter()
+                                    .map(|map| {
+                                        Ok(Map {
+                                            attrs: map.attrs,
+                                            up: map.up,
+                                            right: map.right,
+                                            down: map.down,
+                                            left: map.left,
+                                            objects: map
+                                                .objects
+                                                .into_iter()
+                                                .map(object_from_document)
+                                                .collect::<Result<_>>()?,
+                                        })
+                                    })
+                                    .collect::<Result<_>>()?,
+                            })
+                        })
+                        .collect::<Result<_>>()?,
+                })
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+pub fn decompile_script(script: &Script, format: DocumentFormat) -> Result<String> {
+    let doc = to_document(script);
+    Ok(match format {
+        DocumentFormat::Yaml => serde_yaml::to_string(&doc)?,
+        DocumentFormat::Json => serde_json::to_string_pretty(&doc)? + "\n",
+    })
+}
+
+pub fn compile_script(text: &str, format: DocumentFormat) -> Result<Script> {
+    let doc: ScriptDocument = match format {
+        DocumentFormat::Yaml => serde_yaml::from_str(text)?,
+        DocumentFormat::Json => serde_json::from_str(text)?,
+    };
+    from_document(doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script::{
+        data::talk::Talk,
+        enums::{Equipment, Rom, Seal, SubWeapon},
+        file::scriptconverter::{build_script_dat, parse_script_dat},
+        fixture,
+    };
+
+    use super::*;
+
+    fn starts() -> Vec<Start> {
+        vec![
+            Start {
+                flag: 99999,
+                run_when: true,
+            },
+            Start {
+                flag: 768,
+                run_when: false,
+            },
+        ]
+    }
+
+    fn script() -> Result<Script> {
+        let objects = vec![
+            Object::new(1, 10, 20, 400, Equipment::Boots as i32, 768, 0, starts())?,
+            Object::new(
+                1,
+                10,
+                20,
+                401,
+                100 + Rom::GameMaster as i32,
+                800,
+                -1,
+                starts(),
+            )?,
+            Object::new(1, 10, 20, 402, -1, -1, 0, starts())?,
+            Object::new(13, 0, 0, SubWeapon::Pistol as i32, 1, 700, -1, starts())?,
+            Object::new(14, 0, 0, 100, 5, 252, 253, starts())?,
+            Object::new(32, 0, 0, Rom::GameMaster2 as i32, 801, -1, -1, vec![])?,
+            Object::new(71, 0, 0, Seal::Life as i32, 802, -1, -1, vec![])?,
+            Object::new(77, 0, 0, 2, 803, -1, -1, vec![])?,
+            Object::new(2, -1, 3, 4, 5, 6, 7, starts())?,
+            Object::new(20, 0, 0, 900, -1, -1, -1, vec![])?,
+        ];
+        let mut script = fixture::script(objects);
+        script.talks.push(Talk::from_text(""));
+        script.talks.push(Talk::from_readable_string(
+            "A{setFlag 300}\nB{cmd1}{cmd2 1}",
+        )?);
+        let field = &mut script.worlds[0].fields[0];
+        field.attrs = (1, 2, 3, 4, 5);
+        field.chip_line = (6, 7);
+        field.hits = vec![(-1, 2)];
+        field.animes = vec![vec![1, 2, 3], vec![]];
+        field.objects = vec![UnknownObject {
+            number: 3,
+            x: 0,
+            y: 0,
+            op1: 1,
+            op2: 2,
+            op3: 3,
+            op4: 4,
+            starts: starts(),
+        }];
+        let map = &mut field.maps[0];
+        map.up = (0, 1, 2, 3);
+        map.right = (-1, -1, -1, -1);
+        map.down = (4, 5, 6, 7);
+        map.left = (8, 9, 10, 11);
+        Ok(script)
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let script = script()?;
+        for format in [DocumentFormat::Yaml, DocumentFormat::Json] {
+            let text = decompile_script(&script, format)?;
+            let compiled = compile_script(&text, format)?;
+            assert_eq!(compiled.stringify(), script.stringify());
+            assert_eq!(decompile_script(&compiled, format)?, text);
+        }
+        let yaml = decompile_script(&script, DocumentFormat::Yaml)?;
+        assert!(yaml.contains("A{setFlag 300}"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_document_format_from_path() {
+        let format = |path: &str| DocumentFormat::from_path(Path::new(path));
+        assert_eq!(format("script.JSON"), DocumentFormat::Json);
+        assert_eq!(format("script.yml"), DocumentFormat::Yaml);
+        assert_eq!(format("script"), DocumentFormat::Yaml);
+    }
+
+    #[test]
+    #[ignore = "needs the vanilla script.dat"]
+    fn test_round_trip_vanilla_script() -> Result<()> {
+        let file = fixture::vanilla_script_dat()?;
+        let script = parse_script_dat(&file)?;
+        for format in [DocumentFormat::Yaml, DocumentFormat::Json] {
+            let text = decompile_script(&script, format)?;
+            let compiled = compile_script(&text, format)?;
+            // assert_eq! だとファイル全体が出力されてしまう
+            assert!(build_script_dat(&compiled) == file, "{:?}", format);
+        }
+        Ok(())
+    }
+}
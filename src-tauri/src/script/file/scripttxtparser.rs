@@ -1,242 +1,298 @@
 use std::fmt::Write;
 
-use anyhow::{Result, anyhow, bail};
-use scraper::{ElementRef, Html, node::Attributes};
-
-use crate::script::{
-    data::{
-        object::{Object, Start, UnknownObject},
-        script::{Field, Map, World},
-        shop_items_data,
-        talk::Talk,
-    },
-    enums::SubWeapon,
+use anyhow::{Result, anyhow};
+
+use crate::script::data::{
+    object::{Object, Start, UnknownObject},
+    script::{Field, Map, World},
+    talk::Talk,
 };
 
-pub fn parse_script_txt(text: &str) -> Result<(Vec<Talk>, Vec<World>)> {
-    let parser = Html::parse_fragment(text);
-    let root = parser.root_element().child_elements().collect::<Vec<_>>();
-    // NOTE: scraper converts all tag names to lowercase
-    let talks: Vec<_> = root
-        .iter()
-        .filter(|x| x.value().name() == "talk")
-        .map(|x| {
-            let talk = x
-                .text()
-                .collect::<String>()
-                .trim_start_matches('\n')
-                .to_owned();
-            Talk::from_text(&talk)
+struct Tag<'a> {
+    pos: usize,
+    name: &'a str,
+    attrs: &'a str,
+    closing: bool,
+}
+
+impl Tag<'_> {
+    fn is_open(&self, name: &str) -> bool {
+        !self.closing && self.name.eq_ignore_ascii_case(name)
+    }
+
+    fn is_close(&self, name: &str) -> bool {
+        self.closing && self.name.eq_ignore_ascii_case(name)
+    }
+}
+
+/// `<TALK>`, `<WORLD>` などのタグを順に読み出す。文字列はコピーせず元のテキストを参照する
+struct Tokenizer<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, pos: 0 }
+    }
+
+    fn error(&self, pos: usize, msg: impl std::fmt::Display) -> anyhow::Error {
+        let before = &self.text[..pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before[before.rfind('\n').map_or(0, |x| x + 1)..]
+            .chars()
+            .count()
+            + 1;
+        anyhow!("{}:{}: {}", line, column, msg)
+    }
+
+    fn unexpected(&self, tag: &Tag) -> anyhow::Error {
+        let slash = if tag.closing { "/" } else { "" };
+        self.error(tag.pos, format!("unexpected tag <{}{}>", slash, tag.name))
+    }
+
+    fn next_tag(&mut self) -> Result<Option<Tag<'a>>> {
+        let rest = &self.text[self.pos..];
+        let trimmed = rest.trim_start();
+        self.pos += rest.len() - trimmed.len();
+        if trimmed.is_empty() {
+            return Ok(None);
+        }
+        let pos = self.pos;
+        if !trimmed.starts_with('<') {
+            return Err(self.error(pos, "expected '<'"));
+        }
+        let Some(end) = trimmed.find('>') else {
+            return Err(self.error(pos, "unclosed tag"));
+        };
+        self.pos += end + 1;
+        let inner = &trimmed[1..end];
+        let (closing, inner) = match inner.strip_prefix('/') {
+            Some(inner) => (true, inner),
+            None => (false, inner),
+        };
+        let (name, attrs) = inner.split_once(' ').unwrap_or((inner, ""));
+        Ok(Some(Tag {
+            pos,
+            name,
+            attrs,
+            closing,
+        }))
+    }
+
+    fn next_tag_in(&mut self, parent: &Tag) -> Result<Tag<'a>> {
+        self.next_tag()?
+            .ok_or_else(|| self.error(parent.pos, format!("<{}> is not closed", parent.name)))
+    }
+
+    /// 会話の本文には `<` が含まれることがあるので、閉じタグまでをそのまま読む
+    fn read_text_until_close(&mut self, tag: &Tag) -> Result<&'a str> {
+        let close = format!("</{}>", tag.name);
+        let rest = &self.text[self.pos..];
+        let Some(end) = rest.find(&close) else {
+            return Err(self.error(tag.pos, format!("<{}> is not closed", tag.name)));
+        };
+        self.pos += end + close.len();
+        Ok(&rest[..end])
+    }
+
+    fn attrs<const N: usize>(&self, tag: &Tag) -> Result<[i32; N]> {
+        let attrs = self.attr_list(tag)?;
+        attrs.try_into().map_err(|attrs: Vec<_>| {
+            let msg = format!(
+                "<{}> needs {} attributes, found {}",
+                tag.name,
+                N,
+                attrs.len()
+            );
+            self.error(tag.pos, msg)
         })
-        .collect();
-    if cfg!(debug_assertions) {
-        let first_shop = shop_items_data::parse(&talks[252])?;
-        debug_assert_eq!(first_shop.0.number(), SubWeapon::HandScanner as u8);
-        debug_assert_eq!(first_shop.0.price(), 20);
-        debug_assert_eq!(first_shop.0.flag(), 696);
-        debug_assert_eq!(first_shop.1.number(), SubWeapon::Ammunition as u8);
-        debug_assert_eq!(first_shop.1.price(), 500);
-        debug_assert_eq!(first_shop.1.flag(), 65279);
-        debug_assert_eq!(first_shop.2.number(), SubWeapon::Buckler as u8);
-        debug_assert_eq!(first_shop.2.price(), 80);
-        debug_assert_eq!(first_shop.2.flag(), 697);
     }
-    let worlds: Vec<World> = root
-        .iter()
-        .filter(|world| world.value().name() == "world")
-        .map(|world| {
-            Ok(World {
-                number: u8::try_from(parse_attrs(&world.value().attrs)?[0])?,
-                fields: world
-                    .child_elements()
-                    .filter(|field| field.value().name() == "field")
-                    .map(|field| {
-                        let children = flat_children(field);
-                        let mut attrs = parse_attrs(&field.value().attrs)?.into_iter();
-                        Ok(Field {
-                            attrs: (
-                                u8::try_from(attrs.next().ok_or(anyhow!("No attributes found"))?)?,
-                                u8::try_from(attrs.next().ok_or(anyhow!("No attributes found"))?)?,
-                                u8::try_from(attrs.next().ok_or(anyhow!("No attributes found"))?)?,
-                                u8::try_from(attrs.next().ok_or(anyhow!("No attributes found"))?)?,
-                                u8::try_from(attrs.next().ok_or(anyhow!("No attributes found"))?)?,
-                            ),
-                            chip_line: {
-                                let chip_line = children
-                                    .iter()
-                                    .find(|child| child.value().name() == "chipline")
-                                    .ok_or_else(|| anyhow!("No CHIPLINE found"))?;
-                                let attrs = parse_attrs(&chip_line.value().attrs)?;
-                                (u16::try_from(attrs[0])?, u16::try_from(attrs[1])?)
-                            },
-                            hits: children
-                                .iter()
-                                .filter(|child| child.value().name() == "hit")
-                                .map(|&child| {
-                                    let attrs = parse_attrs(&child.value().attrs)?;
-                                    Ok((i16::try_from(attrs[0])?, i16::try_from(attrs[1])?))
-                                })
-                                .collect::<Result<_>>()?,
-                            animes: children
-                                .iter()
-                                .filter(|child| child.value().name() == "anime")
-                                .map(|&child| {
-                                    let attrs = parse_attrs(&child.value().attrs)?;
-                                    attrs
-                                        .into_iter()
-                                        .map(|x| Ok(u16::try_from(x)?))
-                                        .collect::<Result<Vec<_>>>()
-                                })
-                                .collect::<Result<_>>()?,
-                            objects: children
-                                .iter()
-                                .filter(|child| child.value().name() == "object")
-                                .map(|&child| {
-                                    let obj = parse_object(child)?;
-                                    let Object::Unknown(obj) = obj else {
-                                        bail!("Expected UnknownObject")
-                                    };
-                                    Ok(obj)
-                                })
-                                .collect::<Result<_>>()?,
-                            maps: children
-                                .iter()
-                                .filter(|child| child.value().name() == "map")
-                                .map(|&child| {
-                                    let attrs = parse_attrs(&child.value().attrs)?;
-                                    let map_children = flat_children(child);
-                                    Ok(Map {
-                                        attrs: (
-                                            u8::try_from(attrs[0])?,
-                                            u8::try_from(attrs[1])?,
-                                            u8::try_from(attrs[2])?,
-                                        ),
-                                        up: {
-                                            let up = map_children
-                                                .iter()
-                                                .find(|x| x.value().name() == "up")
-                                                .ok_or_else(|| anyhow!("No UP found"))?;
-                                            let attrs = parse_attrs(&up.value().attrs)?;
-                                            (
-                                                i8::try_from(attrs[0])?,
-                                                i8::try_from(attrs[1])?,
-                                                i8::try_from(attrs[2])?,
-                                                i8::try_from(attrs[3])?,
-                                            )
-                                        },
-                                        right: {
-                                            let up = map_children
-                                                .iter()
-                                                .find(|x| x.value().name() == "right")
-                                                .ok_or_else(|| anyhow!("No RIGHT found"))?;
-                                            let attrs = parse_attrs(&up.value().attrs)?;
-                                            (
-                                                i8::try_from(attrs[0])?,
-                                                i8::try_from(attrs[1])?,
-                                                i8::try_from(attrs[2])?,
-                                                i8::try_from(attrs[3])?,
-                                            )
-                                        },
-                                        down: {
-                                            let down = map_children
-                                                .iter()
-                                                .find(|x| x.value().name() == "down")
-                                                .ok_or_else(|| anyhow!("No DOWN found"))?;
-                                            let attrs = parse_attrs(&down.value().attrs)?;
-                                            (
-                                                i8::try_from(attrs[0])?,
-                                                i8::try_from(attrs[1])?,
-                                                i8::try_from(attrs[2])?,
-                                                i8::try_from(attrs[3])?,
-                                            )
-                                        },
-                                        left: {
-                                            let left = map_children
-                                                .iter()
-                                                .find(|x| x.value().name() == "left")
-                                                .ok_or_else(|| anyhow!("No LEFT found"))?;
-                                            let attrs = parse_attrs(&left.value().attrs)?;
-                                            (
-                                                i8::try_from(attrs[0])?,
-                                                i8::try_from(attrs[1])?,
-                                                i8::try_from(attrs[2])?,
-                                                i8::try_from(attrs[3])?,
-                                            )
-                                        },
-                                        objects: map_children
-                                            .iter()
-                                            .filter(|object| object.value().name() == "object")
-                                            .map(|&object| parse_object(object))
-                                            .collect::<Result<_>>()?,
-                                    })
-                                })
-                                .collect::<Result<_>>()?,
-                        })
-                    })
-                    .collect::<Result<_>>()?,
+
+    fn attr_list(&self, tag: &Tag) -> Result<Vec<i32>> {
+        if tag.attrs.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        tag.attrs
+            .split(',')
+            .map(|x| {
+                x.trim().parse::<i32>().map_err(|err| {
+                    self.error(tag.pos, format!("invalid attribute {:?}: {}", x, err))
+                })
             })
+            .collect()
+    }
+
+    fn convert<T: TryFrom<i32>>(&self, tag: &Tag, value: i32) -> Result<T> {
+        T::try_from(value).map_err(|_| {
+            self.error(
+                tag.pos,
+                format!("attribute of <{}> out of range: {}", tag.name, value),
+            )
         })
-        .collect::<Result<_>>()?;
+    }
 
-    debug_assert_eq!(talks.len(), 905);
-    debug_assert_eq!(worlds[0].fields[0].objects[0].starts[0].flag, 99999);
-    debug_assert_eq!(worlds[0].fields[0].maps[0].objects[5].starts()[0].flag, 58);
-    Ok((talks, worlds))
-}
+    fn direction(&self, tag: &Tag) -> Result<(i8, i8, i8, i8)> {
+        let [a, b, c, d] = self.attrs(tag)?;
+        Ok((
+            self.convert(tag, a)?,
+            self.convert(tag, b)?,
+            self.convert(tag, c)?,
+            self.convert(tag, d)?,
+        ))
+    }
 
-fn parse_attrs(attrs: &Attributes) -> Result<Vec<i32>> {
-    Ok(attrs
-        .iter()
-        .map(|(key, _value)| key)
-        .next()
-        .ok_or(anyhow!("No attributes found"))?
-        .local
-        .split(',')
-        .map(|x| x.parse::<i32>())
-        .collect::<Result<_, _>>()?)
-}
+    fn parse_object(&mut self, tag: &Tag) -> Result<Object> {
+        let [number, x, y, op1, op2, op3, op4] = self.attrs(tag)?;
+        let mut starts = Vec::new();
+        loop {
+            let child = self.next_tag_in(tag)?;
+            if child.is_close("OBJECT") {
+                break;
+            }
+            if !child.is_open("START") {
+                return Err(self.unexpected(&child));
+            }
+            let [flag, run_when] = self.attrs(&child)?;
+            starts.push(Start {
+                flag: self.convert(&child, flag)?,
+                run_when: run_when != 0,
+            });
+        }
+        let number = self.convert(tag, number)?;
+        Object::new(number, x, y, op1, op2, op3, op4, starts)
+            .map_err(|err| self.error(tag.pos, err))
+    }
 
-fn flat_children(root: ElementRef) -> Vec<ElementRef> {
-    root.child_elements()
-        .map(|x| {
-            if x.child_elements().count() == 0 {
-                return vec![x];
+    fn parse_map(&mut self, tag: &Tag) -> Result<Map> {
+        let [a, b, c] = self.attrs(tag)?;
+        let attrs = (
+            self.convert(tag, a)?,
+            self.convert(tag, b)?,
+            self.convert(tag, c)?,
+        );
+        let (mut up, mut right, mut down, mut left) = (None, None, None, None);
+        let mut objects = Vec::new();
+        loop {
+            let child = self.next_tag_in(tag)?;
+            if child.is_close("MAP") {
+                break;
+            } else if child.is_open("UP") {
+                up = Some(self.direction(&child)?);
+            } else if child.is_open("RIGHT") {
+                right = Some(self.direction(&child)?);
+            } else if child.is_open("DOWN") {
+                down = Some(self.direction(&child)?);
+            } else if child.is_open("LEFT") {
+                left = Some(self.direction(&child)?);
+            } else if child.is_open("OBJECT") {
+                objects.push(self.parse_object(&child)?);
+            } else {
+                return Err(self.unexpected(&child));
             }
-            if x.value().name() == "object" || x.value().name() == "map" {
-                return vec![x];
+        }
+        Ok(Map {
+            attrs,
+            up: up.ok_or_else(|| self.error(tag.pos, "No UP found"))?,
+            right: right.ok_or_else(|| self.error(tag.pos, "No RIGHT found"))?,
+            down: down.ok_or_else(|| self.error(tag.pos, "No DOWN found"))?,
+            left: left.ok_or_else(|| self.error(tag.pos, "No LEFT found"))?,
+            objects,
+        })
+    }
+
+    fn parse_field(&mut self, tag: &Tag) -> Result<Field> {
+        let [a, b, c, d, e] = self.attrs(tag)?;
+        let attrs = (
+            self.convert(tag, a)?,
+            self.convert(tag, b)?,
+            self.convert(tag, c)?,
+            self.convert(tag, d)?,
+            self.convert(tag, e)?,
+        );
+        let mut chip_line = None;
+        let mut hits = Vec::new();
+        let mut animes = Vec::new();
+        let mut objects = Vec::new();
+        let mut maps = Vec::new();
+        loop {
+            let child = self.next_tag_in(tag)?;
+            if child.is_close("FIELD") {
+                break;
+            } else if child.is_open("CHIPLINE") {
+                let [a, b] = self.attrs(&child)?;
+                chip_line = Some((self.convert(&child, a)?, self.convert(&child, b)?));
+            } else if child.is_open("HIT") {
+                let [x, y] = self.attrs(&child)?;
+                hits.push((self.convert(&child, x)?, self.convert(&child, y)?));
+            } else if child.is_open("ANIME") {
+                let anime = self
+                    .attr_list(&child)?
+                    .into_iter()
+                    .map(|x| self.convert(&child, x))
+                    .collect::<Result<_>>()?;
+                animes.push(anime);
+            } else if child.is_open("OBJECT") {
+                let Object::Unknown(obj) = self.parse_object(&child)? else {
+                    return Err(self.error(child.pos, "Expected UnknownObject"));
+                };
+                objects.push(obj);
+            } else if child.is_open("MAP") {
+                maps.push(self.parse_map(&child)?);
+            } else {
+                return Err(self.unexpected(&child));
             }
-            let mut vec = vec![x];
-            vec.append(&mut flat_children(x));
-            vec
+        }
+        Ok(Field {
+            attrs,
+            chip_line: chip_line.ok_or_else(|| self.error(tag.pos, "No CHIPLINE found"))?,
+            hits,
+            animes,
+            objects,
+            maps,
         })
-        .reduce(|mut p, mut c| {
-            p.append(&mut c);
-            p
+    }
+
+    fn parse_world(&mut self, tag: &Tag) -> Result<World> {
+        let [number] = self.attrs(tag)?;
+        let mut fields = Vec::new();
+        loop {
+            let child = self.next_tag_in(tag)?;
+            if child.is_close("WORLD") {
+                break;
+            }
+            if !child.is_open("FIELD") {
+                return Err(self.unexpected(&child));
+            }
+            fields.push(self.parse_field(&child)?);
+        }
+        Ok(World {
+            number: self.convert(tag, number)?,
+            fields,
         })
-        .unwrap_or_default()
+    }
 }
 
-fn parse_object(object: ElementRef) -> Result<Object> {
-    let attrs = parse_attrs(&object.value().attrs)?;
-    Object::new(
-        u16::try_from(attrs[0])?,
-        attrs[1],
-        attrs[2],
-        attrs[3],
-        attrs[4],
-        attrs[5],
-        attrs[6],
-        flat_children(object)
-            .iter()
-            .map(|x| {
-                let start_attrs = parse_attrs(&x.value().attrs)?;
-                Ok(Start {
-                    flag: u32::try_from(start_attrs[0])?,
-                    run_when: start_attrs[1] != 0,
-                })
-            })
-            .collect::<Result<_>>()?,
-    )
+fn parse_tags(text: &str) -> Result<(Vec<Talk>, Vec<World>)> {
+    let mut tokenizer = Tokenizer::new(text);
+    let mut talks = Vec::new();
+    let mut worlds = Vec::new();
+    while let Some(tag) = tokenizer.next_tag()? {
+        if tag.is_open("TALK") {
+            let talk = tokenizer.read_text_until_close(&tag)?;
+            // 以前のパーサーと同じく、先頭の改行はすべて取り除く
+            talks.push(Talk::from_text(talk.trim_start_matches('\n')));
+        } else if tag.is_open("WORLD") {
+            worlds.push(tokenizer.parse_world(&tag)?);
+        } else {
+            return Err(tokenizer.unexpected(&tag));
+        }
+    }
+    Ok((talks, worlds))
+}
+
+pub fn parse_script_txt(text: &str) -> Result<(Vec<Talk>, Vec<World>)> {
+    parse_tags(text)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -389,3 +445,43 @@ pub fn stringify_script_txt(talks: &[Talk], worlds: &[World]) -> String {
     ]
     .join("")
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::script::{file::dat::cipher_to_text, fixture};
+
+    use super::*;
+
+    const TEXT: &str = "<TALK>\nab<c</TALK>\n<WORLD 0>\n<FIELD 1,2,3,4,5>\n<CHIPLINE 6,7>\n<HIT -1,2>\n<ANIME 1,2,3>\n<OBJECT 3,0,0,1,2,3,4>\n<START 99999,1>\n</OBJECT>\n<MAP 0,1,2>\n<UP 0,1,2,3>\n<RIGHT -1,-1,-1,-1>\n<DOWN 4,5,6,7>\n<LEFT 8,9,10,11>\n<OBJECT 1,10,20,400,1,768,0>\n<START 768,0>\n</OBJECT>\n</MAP>\n</FIELD>\n</WORLD>\n";
+
+    #[test]
+    fn test_parse_tags() -> Result<()> {
+        let (talks, worlds) = parse_tags(TEXT)?;
+        assert_eq!(talks[0].to_string(), "ab<c");
+        assert_eq!(stringify_script_txt(&talks, &worlds), TEXT);
+
+        let (talks, _) = parse_tags(&TEXT.replace("<TALK>\n", "<TALK>\n\n"))?;
+        assert_eq!(talks[0].to_string(), "ab<c");
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the vanilla script.dat"]
+    fn test_round_trip_vanilla_script_txt() -> Result<()> {
+        let text = cipher_to_text(&fixture::vanilla_script_dat()?);
+        let (talks, worlds) = parse_script_txt(&text)?;
+        // assert_eq! だとスクリプト全体が出力されてしまう
+        assert!(stringify_script_txt(&talks, &worlds) == text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_tags_error_position() {
+        let text = TEXT.replace("<HIT -1,2>", "<HIT -1>");
+        let err = parse_tags(&text).err().unwrap();
+        assert_eq!(err.to_string(), "6:1: <HIT> needs 2 attributes, found 1");
+        let text = TEXT.replace("</MAP>", "</FIELD>");
+        let err = parse_tags(&text).err().unwrap();
+        assert_eq!(err.to_string(), "19:1: unexpected tag </FIELD>");
+    }
+}
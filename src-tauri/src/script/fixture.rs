@@ -0,0 +1,53 @@
+use anyhow::{Context, Result};
+
+use super::{
+    data::{
+        object::Object,
+        script::{Field, Map, Script, World},
+        talk::Talk,
+    },
+    file::scriptconverter::read_script_dat,
+};
+
+/// マップ (0,1,2) を1つだけ持つフィールド
+pub fn field(number: u8, objects: Vec<Object>) -> Field {
+    Field {
+        attrs: (number, 0, 0, 0, 0),
+        chip_line: (0, 0),
+        hits: vec![],
+        animes: vec![],
+        objects: vec![],
+        maps: vec![Map {
+            attrs: (0, 1, 2),
+            up: (0, 0, 0, 0),
+            right: (0, 0, 0, 0),
+            down: (0, 0, 0, 0),
+            left: (0, 0, 0, 0),
+            objects,
+        }],
+    }
+}
+
+/// `fields` を持つワールド 0 と、会話 "abc"
+pub fn script_with_fields(fields: Vec<Field>) -> Script {
+    Script {
+        talks: vec![Talk::from_text("abc")],
+        worlds: vec![World { number: 0, fields }],
+    }
+}
+
+/// ワールド 0、フィールド 1、マップ (0,1,2) に `objects` を置き、会話 "abc" を持つ
+pub fn script(objects: Vec<Object>) -> Script {
+    script_with_fields(vec![field(1, objects)])
+}
+
+/// リポジトリにないバニラの script.dat。
+/// これを使うテストは `LMO_SCRIPT_DAT=<path> cargo test -- --ignored` で実行する
+pub fn vanilla_script_dat() -> Result<Vec<u8>> {
+    let path = std::env::var("LMO_SCRIPT_DAT").context("LMO_SCRIPT_DAT is not set")?;
+    std::fs::read(&path).with_context(|| format!("failed to read {}", path))
+}
+
+pub fn vanilla_script() -> Result<Script> {
+    read_script_dat(&vanilla_script_dat()?)
+}
@@ -1,5 +1,10 @@
-mod consts;
+pub mod consts;
 pub mod data;
+// lmocodec だけが使う
+#[allow(dead_code)]
+pub mod diff;
 pub mod editor;
 pub mod enums;
 pub mod file;
+#[cfg(test)]
+pub mod fixture;
@@ -1,5 +1,8 @@
 #[cfg(not(test))]
-use crate::script::data::{shop_items_data, talk::Talk};
+use crate::script::{
+    data::{shop_items_data, talk::Talk},
+    file::scriptconverter::parse_script_dat,
+};
 
 #[cfg(not(test))]
 #[allow(unused)]
@@ -13,11 +16,35 @@ mod script;
 
 #[cfg(not(test))]
 fn main() {
-    let a = std::env::args().nth(1).unwrap();
-    let talk = Talk::from_bytes(parse_bytes(&a));
-    let sid = shop_items_data::parse(&talk).unwrap();
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|x| x.as_str()) {
+        Some("dump") if args.len() == 3 => print_talks(&args[2], None),
+        Some("search") if args.len() == 4 => print_talks(&args[2], Some(&args[3])),
+        Some(a) if args.len() == 2 => {
+            let talk = Talk::from_bytes(parse_bytes(a));
+            let sid = shop_items_data::parse(&talk).unwrap();
 
-    println!("{:#?}", sid);
+            println!("{:#?}", sid);
+        }
+        _ => {
+            eprintln!("Usage: lmotalk.exe [shop items data bytes]");
+            eprintln!("       lmotalk.exe dump [script.dat]");
+            eprintln!("       lmotalk.exe search [script.dat] [query]");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(test))]
+fn print_talks(script_dat_path: &str, query: Option<&str>) {
+    let script = parse_script_dat(&std::fs::read(script_dat_path).unwrap()).unwrap();
+    for (i, talk) in script.talks.iter().enumerate() {
+        let text = talk.to_readable_string().replace('\n', "\\n");
+        if query.is_some_and(|query| !text.contains(query)) {
+            continue;
+        }
+        println!("{:>3}: {}", i, text);
+    }
 }
 
 #[cfg(not(test))]